@@ -1,28 +1,50 @@
 use chrono::Local;
 use eframe::egui;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 use sysinfo::System;
 
+use crate::atomic_file::{self, BackupMode};
+use crate::cli::{CliArgs, FileSource};
+use crate::commands::{self, Command, CommandPaletteState};
+use crate::diff::DiffView;
+use crate::document::Document;
+use crate::encoding::{self, TextEncoding};
+use crate::export::{self, ExportFormat};
+use crate::file_watch::{self, DiskChange, DiskState, ExternalChange};
+use crate::find::{self, FindState};
+use crate::line_ending::{self, LineEnding};
+use crate::navigation::{self, GoToState, Location, NavHistory};
+use crate::preview::PreviewState;
+use crate::recovery::{self, Journal, Recovered};
+use crate::session::{self, Session, SessionDocument};
+use crate::settings::{self, Settings, Theme};
+use crate::split::{Split, SplitOrientation};
+use crate::stats::{DetailedStats, DocStats, StatsCache};
 use crate::system_monitor::{self, SystemStats};
 use crate::theme;
-use crate::weather::{self, WeatherInfo};
+use crate::weather::{self, OpenMeteo, WeatherProvider, WeatherState};
+use crate::wrap::{self, WrapMode};
 
 #[derive(Debug, Clone)]
 pub enum PendingAction {
-    New,
-    Open,
+    CloseTab(u64),
     Exit,
 }
 
 pub struct NotepadApp {
     // Document state
-    pub text: String,
-    pub file_path: Option<PathBuf>,
-    pub dirty: bool,
+    pub documents: Vec<Document>,
+    pub active_doc: usize,
+    pub last_rendered_doc: Option<u64>,
+    /// Most recently opened or saved files, newest first
+    pub recent_files: Vec<PathBuf>,
+    /// Whether missing recent files were pruned since the File menu opened
+    pub recent_checked: bool,
 
     // UI state
     pub show_unsaved_dialog: bool,
@@ -30,10 +52,38 @@ pub struct NotepadApp {
     pub status_message: Option<String>,
     pub font_size: f32,
     pub dark_mode: bool,
+    pub find: FindState,
+    pub go_to: GoToState,
+    pub nav: NavHistory,
+    pub diff_view: Option<DiffView>,
+    pub show_stats: bool,
+    pub show_forecast: bool,
+    pub stats: StatsCache<DocStats>,
+    pub detailed_stats: StatsCache<DetailedStats>,
+    pub preview: PreviewState,
+    pub command_palette: CommandPaletteState,
+
+    // Crash recovery and autosave
+    pub journal: Journal,
+    /// Snapshots from a previous session, offered for restore on startup
+    pub recovered: Vec<Recovered>,
+    pub last_autosave: Instant,
+
+    // Changes made by other programs
+    pub last_disk_check: Instant,
+    /// Document waiting for confirmation before overwriting a newer file,
+    /// and the encoding it is being saved in
+    pub confirm_overwrite: Option<(u64, TextEncoding)>,
+
+    // Persistent settings
+    pub settings: Settings,
+    pub saved_settings: Settings,
+    pub last_settings_save: Instant,
 
     // External data
-    pub weather: Arc<Mutex<Option<WeatherInfo>>>,
-    pub last_weather_fetch: Instant,
+    pub weather: Arc<Mutex<WeatherState>>,
+    pub weather_provider: Arc<dyn WeatherProvider>,
+    pub last_weather_fetch: Option<Instant>,
 
     // System monitoring
     pub system: System,
@@ -42,35 +92,58 @@ pub struct NotepadApp {
 }
 
 impl NotepadApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        theme::apply_mocha(&cc.egui_ctx);
-
-        let weather = Arc::new(Mutex::new(None));
-
-        // Fetch weather in background on startup
-        let weather_clone = Arc::clone(&weather);
-        thread::spawn(move || {
-            if let Some(info) = weather::fetch_weather() {
-                if let Ok(mut w) = weather_clone.lock() {
-                    *w = Some(info);
-                }
-            }
-        });
+    pub fn new(
+        cc: &eframe::CreationContext<'_>,
+        settings: Settings,
+        settings_warning: Option<String>,
+    ) -> Self {
+        if settings.theme.is_dark() {
+            theme::apply_mocha(&cc.egui_ctx);
+        } else {
+            theme::apply_latte(&cc.egui_ctx);
+        }
+        Self::with_settings(settings, settings_warning)
+    }
 
+    /// The app state without any window, e.g. for tests
+    pub fn with_settings(settings: Settings, settings_warning: Option<String>) -> Self {
         let mut system = System::new_all();
         system.refresh_cpu_all();
+        let recovered = recovery::leftovers(&system);
+        let weather_provider = Arc::new(OpenMeteo::new(&settings.weather));
 
         Self {
-            text: String::new(),
-            file_path: None,
-            dirty: false,
+            documents: vec![Document::new()],
+            active_doc: 0,
+            last_rendered_doc: None,
+            recent_files: Vec::new(),
+            recent_checked: false,
             show_unsaved_dialog: false,
             pending_action: None,
-            status_message: None,
-            font_size: 14.0,
-            dark_mode: true,
-            weather,
-            last_weather_fetch: Instant::now(),
+            status_message: settings_warning,
+            font_size: settings.font_size.clamp(8.0, 48.0),
+            dark_mode: settings.theme.is_dark(),
+            find: FindState::default(),
+            go_to: GoToState::default(),
+            nav: NavHistory::default(),
+            diff_view: None,
+            show_stats: false,
+            show_forecast: false,
+            stats: StatsCache::default(),
+            detailed_stats: StatsCache::default(),
+            preview: PreviewState::default(),
+            command_palette: CommandPaletteState::default(),
+            journal: Journal::new(),
+            recovered,
+            last_autosave: Instant::now(),
+            last_disk_check: Instant::now(),
+            confirm_overwrite: None,
+            saved_settings: settings.clone(),
+            settings,
+            last_settings_save: Instant::now(),
+            weather: Arc::new(Mutex::new(WeatherState::default())),
+            weather_provider,
+            last_weather_fetch: None,
             system,
             system_stats: SystemStats::default(),
             last_system_refresh: Instant::now(),
@@ -78,26 +151,186 @@ impl NotepadApp {
     }
 
     pub fn refresh_weather_if_needed(&mut self) {
-        if self.last_weather_fetch.elapsed() > Duration::from_secs(600) {
-            self.last_weather_fetch = Instant::now();
+        if !self.settings.weather.enabled || !weather::has_location(&self.settings.weather) {
+            return;
+        }
+
+        let Ok(mut state) = self.weather.lock() else {
+            return;
+        };
+        if state.in_flight {
+            return;
+        }
+        // After failures, retry sooner than the refresh interval at first and
+        // back off while they keep failing
+        let interval =
+            state.next_fetch_after(Duration::from_secs(self.settings.weather.refresh_secs));
+        let due = match self.last_weather_fetch {
+            Some(last) => last.elapsed() > interval,
+            None => true,
+        };
+        if due {
+            self.last_weather_fetch = Some(Instant::now());
+            state.in_flight = true;
+            let generation = state.generation;
             let weather_clone = Arc::clone(&self.weather);
+            let provider = Arc::clone(&self.weather_provider);
+            let settings = self.settings.weather.clone();
             thread::spawn(move || {
-                if let Some(info) = weather::fetch_weather() {
-                    if let Ok(mut w) = weather_clone.lock() {
-                        *w = Some(info);
-                    }
+                let result = provider.fetch(&settings);
+                if let Ok(mut w) = weather_clone.lock() {
+                    w.finish(generation, result);
                 }
             });
         }
     }
 
+    /// Fetches the weather on the next frame instead of waiting for the
+    /// refresh interval or retry backoff
+    pub fn refresh_weather_now(&mut self) {
+        self.last_weather_fetch = None;
+    }
+
+    /// Fetches the weather again on the next frame, after its location
+    /// changed
+    pub fn refetch_weather(&mut self) {
+        self.last_weather_fetch = None;
+        if let Ok(mut weather) = self.weather.lock() {
+            let generation = weather.generation + 1;
+            *weather = WeatherState {
+                generation,
+                ..WeatherState::default()
+            };
+        }
+    }
+
     pub fn refresh_system_info(&mut self) {
-        if self.last_system_refresh.elapsed() > Duration::from_millis(1000) {
+        if !self.settings.monitor.enabled {
+            return;
+        }
+
+        let interval = Duration::from_millis(self.settings.monitor.refresh_ms);
+        if self.last_system_refresh.elapsed() > interval {
             self.system_stats = system_monitor::collect_stats(&mut self.system);
             self.last_system_refresh = Instant::now();
         }
     }
 
+    /// Snapshots dirty buffers for crash recovery and autosaves documents
+    /// that have a path, when enabled
+    pub fn update_recovery(&mut self) {
+        if let Some(error) = self.journal.update(&self.documents) {
+            self.status_message = Some(error);
+        }
+
+        let interval = Duration::from_secs(self.settings.files.autosave_secs.max(1));
+        if !self.settings.files.autosave || self.last_autosave.elapsed() < interval {
+            return;
+        }
+        self.last_autosave = Instant::now();
+
+        let mut saved = 0;
+        for index in 0..self.documents.len() {
+            let doc = &self.documents[index];
+            if !doc.dirty || doc.read_only {
+                continue;
+            }
+            let Some(path) = doc.file_path.clone() else {
+                continue;
+            };
+            if doc.external_change.is_some() || self.changed_on_disk(index) {
+                continue;
+            }
+            // Skip backups, or timestamped mode would keep one per interval
+            let encoding = doc.encoding;
+            match self.write_document(index, path, encoding, BackupMode::None) {
+                Ok(_) => saved += 1,
+                Err(e) => {
+                    self.status_message = Some(format!("Autosave failed: {}", e));
+                    return;
+                }
+            }
+        }
+        if saved > 0 {
+            self.status_message = Some(format!(
+                "Autosaved {} file{}",
+                saved,
+                if saved == 1 { "" } else { "s" }
+            ));
+        }
+    }
+
+    /// Opens a recovered snapshot in a tab. A snapshot of a file that still
+    /// exists is applied on top of the file as one undoable step.
+    pub fn restore_snapshot(&mut self, index: usize) {
+        if index >= self.recovered.len() {
+            return;
+        }
+        let recovered = self.recovered.remove(index);
+        recovery::discard(&recovered);
+        let snapshot = recovered.snapshot;
+        let title = snapshot.title();
+
+        let opened = match &snapshot.file_path {
+            Some(path) if path.exists() => self.open_path(path.clone()),
+            _ => false,
+        };
+        if opened {
+            let doc = self.active_doc_mut();
+            doc.replace_text("Recover", snapshot.text.clone());
+            doc.encoding = snapshot.encoding();
+            doc.line_ending = snapshot.line_ending();
+            self.status_message = Some(format!("Recovered: {}", title));
+        } else {
+            let mut doc = Document::from_unsaved_text(snapshot.text.clone(), snapshot.encoding());
+            doc.file_path = snapshot.file_path.clone();
+            doc.line_ending = snapshot.line_ending();
+            self.add_document(doc);
+            self.status_message = Some(format!("Recovered: {}", title));
+        }
+    }
+
+    pub fn discard_snapshot(&mut self, index: usize) {
+        if index < self.recovered.len() {
+            let recovered = self.recovered.remove(index);
+            recovery::discard(&recovered);
+        }
+    }
+
+    /// Shows a recovered snapshot against the file currently on disk
+    pub fn compare_snapshot(&mut self, index: usize) {
+        let Some(snapshot) = self.recovered.get(index).map(|r| &r.snapshot) else {
+            return;
+        };
+
+        let on_disk = snapshot
+            .file_path
+            .as_ref()
+            .and_then(|path| fs::read(path).ok())
+            .map(|bytes| {
+                let (text, _) = encoding::decode(&bytes, snapshot.encoding());
+                line_ending::normalize(&text).into_owned()
+            });
+        let (old_label, old_text) = match on_disk {
+            Some(text) => ("On disk".to_string(), text),
+            None => ("Not on disk".to_string(), String::new()),
+        };
+
+        self.diff_view = Some(DiffView::new(
+            format!("Recovered: {}", snapshot.title()),
+            old_label,
+            &old_text,
+            format!("Snapshot from {}", snapshot.taken_at_text()),
+            &snapshot.text,
+        ));
+    }
+
+    pub fn toggle_theme(&mut self, ctx: &egui::Context) {
+        self.dark_mode = !self.dark_mode;
+        self.settings.theme = Theme::from_dark_mode(self.dark_mode);
+        self.apply_theme(ctx);
+    }
+
     pub fn apply_theme(&self, ctx: &egui::Context) {
         if self.dark_mode {
             theme::apply_mocha(ctx);
@@ -106,90 +339,1113 @@ impl NotepadApp {
         }
     }
 
+    /// Copies runtime state into `settings` and writes the file when it
+    /// changed, at most once every couple of seconds.
+    pub fn persist_settings_if_needed(&mut self, ctx: &egui::Context) {
+        self.settings.font_size = self.font_size;
+
+        ctx.input(|i| {
+            let viewport = i.viewport();
+            if viewport.minimized == Some(true) || viewport.fullscreen == Some(true) {
+                return;
+            }
+            if let Some(rect) = viewport.inner_rect {
+                self.settings.window.width = rect.width();
+                self.settings.window.height = rect.height();
+            }
+            if let Some(rect) = viewport.outer_rect {
+                self.settings.window.x = Some(rect.min.x);
+                self.settings.window.y = Some(rect.min.y);
+            }
+        });
+
+        if self.settings != self.saved_settings
+            && self.last_settings_save.elapsed() > Duration::from_secs(2)
+        {
+            self.save_settings();
+        }
+    }
+
+    pub fn save_settings(&mut self) {
+        // A file from a newer version is never overwritten. `settings::load`
+        // already reported that at startup, so skip quietly rather than
+        // repeating it on every change and window move.
+        if self.settings.version <= settings::SETTINGS_VERSION {
+            if let Err(e) = settings::save(&self.settings) {
+                self.status_message = Some(format!("Error saving settings: {}", e));
+            }
+        }
+        // Remember the attempt even on failure so a broken config dir
+        // does not retry every frame
+        self.saved_settings = self.settings.clone();
+        self.last_settings_save = Instant::now();
+    }
+
+    /// Consumes a key press bound to `command`, if one happened this frame
+    pub fn shortcut_pressed(&self, ctx: &egui::Context, command: Command) -> bool {
+        self.settings.shortcuts(command).iter().any(|shortcut| {
+            ctx.input_mut(|i| {
+                i.modifiers.matches_exact(shortcut.modifiers) && i.consume_shortcut(shortcut)
+            })
+        })
+    }
+
+    /// Human-readable shortcut for `command`, for menus
+    pub fn shortcut_text(&self, ctx: &egui::Context, command: Command) -> String {
+        self.settings
+            .shortcuts(command)
+            .first()
+            .map(|shortcut| ctx.format_shortcut(shortcut))
+            .unwrap_or_default()
+    }
+
+    /// Whether `command` can run right now; menus grey out and the command
+    /// palette hides the ones that cannot
+    pub fn command_enabled(&self, command: Command) -> bool {
+        let doc = self.active_doc();
+        match command {
+            Command::Undo => doc.history.can_undo(),
+            Command::Redo => doc.history.can_redo(),
+            Command::NavigateBack => self.nav.can_go_back(),
+            Command::NavigateForward => self.nav.can_go_forward(),
+            Command::HardWrap => !doc.read_only,
+            Command::ReopenWithEncoding(_) => doc.file_path.is_some(),
+            Command::ToggleRuler => self.settings.editor.wrap == WrapMode::Column,
+            Command::FocusOtherPane | Command::SwapPanes | Command::ClosePane => {
+                doc.split.is_some()
+            }
+            _ => true,
+        }
+    }
+
+    /// On/off state of toggles and choices, shown as a highlighted entry
+    pub fn command_checked(&self, command: Command) -> Option<bool> {
+        let editor = &self.settings.editor;
+        match command {
+            Command::ToggleReadOnly => Some(self.active_doc().read_only),
+            Command::MarkdownPreview => Some(editor.markdown_preview),
+            Command::Wrap(mode) => Some(editor.wrap == mode),
+            Command::ToggleTheme => Some(self.dark_mode),
+            Command::FontSize(size) => Some(self.font_size == f32::from(size)),
+            Command::ToggleLineNumbers => Some(editor.line_numbers),
+            Command::ToggleRuler => Some(editor.show_ruler),
+            Command::ReopenWithEncoding(encoding) | Command::SaveWithEncoding(encoding) => {
+                Some(self.active_doc().encoding == encoding)
+            }
+            Command::PdfPageSize(size) => Some(self.settings.pdf.page_size == size),
+            Command::PdfFont(font) => Some(self.settings.pdf.font == font),
+            Command::TogglePdfHeaderFooter => Some(self.settings.pdf.header_footer),
+            Command::Backup(mode) => Some(self.settings.files.backup == mode),
+            Command::ToggleAutosave => Some(self.settings.files.autosave),
+            Command::ToggleRestoreSession => Some(self.settings.files.restore_session),
+            Command::ToggleWeather => Some(self.settings.weather.enabled),
+            Command::WeatherLocation(source) => Some(self.settings.weather.location == source),
+            Command::TemperatureUnit(unit) => Some(self.settings.weather.temperature_unit == unit),
+            Command::WindUnit(unit) => Some(self.settings.weather.wind_unit == unit),
+            Command::ToggleMonitor => Some(self.settings.monitor.enabled),
+            _ => None,
+        }
+    }
+
+    pub fn run_command(&mut self, ctx: &egui::Context, command: Command) {
+        match command {
+            Command::New => self.new_file(),
+            Command::Open => self.open_file(),
+            Command::Save => {
+                self.save_file();
+            }
+            Command::SaveAs => {
+                self.save_file_as();
+            }
+            Command::ReopenWithEncoding(encoding) => self.reopen_with_encoding(encoding),
+            Command::SaveWithEncoding(encoding) => {
+                self.save_with_encoding(encoding);
+            }
+            Command::Export(format) => self.export(format),
+            Command::PdfPageSize(size) => self.settings.pdf.page_size = size,
+            Command::PdfFont(font) => self.settings.pdf.font = font,
+            Command::TogglePdfHeaderFooter => {
+                self.settings.pdf.header_footer = !self.settings.pdf.header_footer;
+            }
+            Command::ToggleReadOnly => {
+                let doc = self.active_doc_mut();
+                doc.read_only = !doc.read_only;
+            }
+            Command::CloseTab => self.close_tab(self.active_doc),
+            Command::Exit => self.request_exit(ctx),
+            Command::Undo => self.undo(ctx),
+            Command::Redo => self.redo(ctx),
+            Command::Find => self.open_find(ctx, false),
+            Command::Replace => self.open_find(ctx, true),
+            Command::GoToLine => self.open_go_to(),
+            Command::NavigateBack => self.navigate_back(ctx),
+            Command::NavigateForward => self.navigate_forward(ctx),
+            Command::HardWrap => self.hard_wrap(ctx),
+            Command::OpenPalette => self.open_command_palette(),
+            Command::MarkdownPreview => {
+                self.settings.editor.markdown_preview = !self.settings.editor.markdown_preview;
+            }
+            Command::Wrap(mode) => self.settings.editor.wrap = mode,
+            Command::ToggleRuler => {
+                self.settings.editor.show_ruler = !self.settings.editor.show_ruler;
+            }
+            Command::Split(orientation) => self.split_editor(ctx, orientation),
+            Command::FocusOtherPane => self.focus_other_pane(ctx),
+            Command::SwapPanes => self.swap_panes(ctx),
+            Command::ClosePane => self.close_pane(ctx),
+            Command::ToggleTheme => self.toggle_theme(ctx),
+            Command::FontSmaller => self.font_size = (self.font_size - 1.0).max(8.0),
+            Command::FontLarger => self.font_size = (self.font_size + 1.0).min(48.0),
+            Command::FontSize(size) => self.font_size = f32::from(size),
+            Command::ToggleLineNumbers => {
+                self.settings.editor.line_numbers = !self.settings.editor.line_numbers;
+            }
+            Command::Backup(mode) => self.settings.files.backup = mode,
+            Command::ToggleAutosave => {
+                self.settings.files.autosave = !self.settings.files.autosave;
+            }
+            Command::ToggleRestoreSession => {
+                self.settings.files.restore_session = !self.settings.files.restore_session;
+            }
+            Command::ToggleWeather => {
+                self.settings.weather.enabled = !self.settings.weather.enabled;
+            }
+            Command::WeatherLocation(source) => {
+                if self.settings.weather.location != source {
+                    self.settings.weather.location = source;
+                    self.refetch_weather();
+                }
+            }
+            Command::TemperatureUnit(unit) => self.settings.weather.temperature_unit = unit,
+            Command::WindUnit(unit) => self.settings.weather.wind_unit = unit,
+            Command::ToggleMonitor => {
+                self.settings.monitor.enabled = !self.settings.monitor.enabled;
+            }
+        }
+    }
+
+    pub fn open_command_palette(&mut self) {
+        self.command_palette = CommandPaletteState {
+            open: true,
+            focus_input: true,
+            ..CommandPaletteState::default()
+        };
+    }
+
+    pub fn close_command_palette(&mut self, ctx: &egui::Context) {
+        self.command_palette.open = false;
+        let id = self.active_doc().editor_id();
+        ctx.memory_mut(|m| m.request_focus(id));
+    }
+
+    /// Commands shown in the palette for its current query
+    pub fn command_palette_matches(&self) -> Vec<Command> {
+        let available = Command::ALL
+            .iter()
+            .copied()
+            .filter(|&command| command != Command::OpenPalette && self.command_enabled(command));
+        commands::search(&self.command_palette.query, available)
+    }
+
     pub fn window_title(&self) -> String {
         Local::now()
             .format("%A, %B %d, %Y  %I:%M:%S %p")
             .to_string()
     }
 
-    pub fn new_file(&mut self) {
-        if self.dirty {
-            self.show_unsaved_dialog = true;
-            self.pending_action = Some(PendingAction::New);
-        } else {
-            self.do_new_file();
-        }
+    pub fn active_doc(&self) -> &Document {
+        &self.documents[self.active_doc]
     }
 
-    pub fn do_new_file(&mut self) {
-        self.text.clear();
-        self.file_path = None;
-        self.dirty = false;
-        self.status_message = Some("New file created".to_string());
+    pub fn active_doc_mut(&mut self) -> &mut Document {
+        &mut self.documents[self.active_doc]
     }
 
-    pub fn open_file(&mut self) {
-        if self.dirty {
-            self.show_unsaved_dialog = true;
-            self.pending_action = Some(PendingAction::Open);
+    /// Whether `doc` has to be saved or discarded before the app exits.
+    /// Untitled buffers are kept by session restore instead.
+    fn blocks_exit(&self, doc: &Document) -> bool {
+        doc.dirty && !(doc.file_path.is_none() && self.settings.files.restore_session)
+    }
+
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.documents.iter().any(|d| self.blocks_exit(d))
+    }
+
+    /// Loads the recent files list and, if enabled, reopens the documents
+    /// that were open when the app last exited
+    pub fn restore_session(&mut self, ctx: &egui::Context) {
+        let session = session::load();
+        self.recent_files = session.recent_files;
+        if !self.settings.files.restore_session {
+            return;
+        }
+
+        // Keep a settings warning visible over the "Opened" messages
+        let warning = self.status_message.take();
+        let mut restored = 0;
+        let mut active = None;
+        for (index, saved) in session.documents.into_iter().enumerate() {
+            let opened = match (&saved.file_path, saved.text) {
+                (Some(path), _) => path.exists() && self.open_path(path.clone()),
+                (None, Some(text)) => {
+                    self.add_document(Document::from_unsaved_text(text, TextEncoding::Utf8));
+                    true
+                }
+                (None, None) => false,
+            };
+            if !opened {
+                continue;
+            }
+
+            let doc = self.active_doc_mut();
+            let cursor = saved.cursor.min(doc.text.chars().count());
+            doc.read_only = saved.read_only;
+            doc.scroll_offset = egui::vec2(saved.scroll[0], saved.scroll[1]);
+            doc.select_chars(ctx, cursor, cursor);
+            // The saved scroll offset wins over scrolling to the caret
+            doc.scroll_to_char = None;
+            restored += 1;
+            if index == session.active_doc {
+                active = Some(self.active_doc);
+            }
+        }
+
+        if let Some(index) = active {
+            self.active_doc = index;
+        }
+        // Reopening the session is not something to navigate back through
+        self.nav = NavHistory::default();
+        self.status_message = warning.or_else(|| {
+            (restored > 0).then(|| {
+                format!(
+                    "Restored {} document{} from the last session",
+                    restored,
+                    if restored == 1 { "" } else { "s" }
+                )
+            })
+        });
+    }
+
+    /// Writes the open documents and recent files list for the next launch
+    pub fn save_session(&self) {
+        let documents: Vec<&Document> = if self.settings.files.restore_session {
+            self.documents.iter().filter(|d| !d.is_pristine()).collect()
         } else {
-            self.do_open_file();
+            Vec::new()
+        };
+
+        let session = Session {
+            recent_files: self.recent_files.clone(),
+            active_doc: documents
+                .iter()
+                .position(|d| d.id == self.active_doc().id)
+                .unwrap_or(0),
+            documents: documents
+                .iter()
+                .map(|doc| SessionDocument {
+                    file_path: doc.file_path.clone(),
+                    text: doc.file_path.is_none().then(|| doc.text.clone()),
+                    cursor: doc.cursor,
+                    scroll: [doc.scroll_offset.x, doc.scroll_offset.y],
+                    read_only: doc.read_only,
+                })
+                .collect(),
+        };
+        // Called on exit, where there is no status bar left to report to
+        let _ = session::save(&session);
+    }
+
+    fn add_recent(&mut self, path: &Path) {
+        let limit = self.settings.files.recent_limit;
+        session::push_recent(&mut self.recent_files, path, limit);
+    }
+
+    pub fn open_recent(&mut self, path: PathBuf) {
+        if !self.open_path(path.clone()) {
+            self.recent_files.retain(|p| *p != path);
         }
     }
 
-    pub fn do_open_file(&mut self) {
+    /// Drops recent files deleted or moved since they were opened
+    pub fn prune_recent(&mut self) {
+        self.recent_files.retain(|path| path.exists());
+        self.recent_files.truncate(self.settings.files.recent_limit);
+    }
+
+    pub fn clear_recent(&mut self) {
+        self.recent_files.clear();
+    }
+
+    pub fn new_file(&mut self) {
+        self.documents.push(Document::new());
+        self.active_doc = self.documents.len() - 1;
+        self.status_message = Some("New file created".to_string());
+    }
+
+    pub fn open_file(&mut self) {
         if let Some(path) = rfd::FileDialog::new()
             .add_filter("Text files", &["txt"])
             .add_filter("All files", &["*"])
             .pick_file()
         {
-            match fs::read_to_string(&path) {
-                Ok(contents) => {
-                    self.text = contents;
-                    self.file_path = Some(path.clone());
-                    self.dirty = false;
-                    self.status_message = Some(format!("Opened: {}", path.display()));
-                }
-                Err(e) => {
-                    self.status_message = Some(format!("Error opening file: {}", e));
+            self.open_path(path);
+        }
+    }
+
+    /// Makes `doc` the active tab, replacing an untouched untitled tab
+    fn add_document(&mut self, doc: Document) {
+        if self.active_doc().is_pristine() {
+            self.documents[self.active_doc] = doc;
+        } else {
+            self.documents.push(doc);
+            self.active_doc = self.documents.len() - 1;
+        }
+    }
+
+    /// Opens `path` in a new tab, or switches to the tab that already has
+    /// the file open. Returns whether the file is now the active tab.
+    pub fn open_path(&mut self, path: PathBuf) -> bool {
+        // Recent files are stored canonical, so `./notes.txt` and a recent
+        // entry for the same file must compare equal
+        let path = fs::canonicalize(&path).unwrap_or(path);
+        if let Some(index) = self
+            .documents
+            .iter()
+            .position(|d| d.file_path.as_ref() == Some(&path))
+        {
+            self.record_jump();
+            self.active_doc = index;
+            self.status_message = Some(format!("Switched to: {}", path.display()));
+            return true;
+        }
+
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let encoding = encoding::detect(&bytes);
+                let (text, had_errors) = encoding::decode(&bytes, encoding);
+                let mut doc = Document::from_file(path.clone(), text, encoding);
+                doc.disk_state = DiskState::new(&path, &bytes).ok();
+                self.add_recent(&path);
+                self.record_jump();
+                self.status_message = Some(if had_errors {
+                    format!(
+                        "Opened with invalid {} sequences replaced: {}",
+                        encoding.label(),
+                        path.display()
+                    )
+                } else if doc.mixed_line_endings {
+                    format!(
+                        "Opened: {} (mixed line endings, will be saved as {})",
+                        path.display(),
+                        doc.line_ending.label()
+                    )
+                } else {
+                    format!("Opened: {}", path.display())
+                });
+                self.add_document(doc);
+                true
+            }
+            Err(e) => {
+                self.status_message = Some(format!("Error opening file: {}", e));
+                false
+            }
+        }
+    }
+
+    /// Opens the files and applies the options given on the command line
+    pub fn apply_cli_args(&mut self, ctx: &egui::Context, args: CliArgs) {
+        if let Some(theme) = args.theme {
+            // Only for this session: the saved theme is left alone
+            self.dark_mode = theme.is_dark();
+            self.apply_theme(ctx);
+        }
+
+        let mut first_opened = None;
+        for file in args.files {
+            let opened = match file.source {
+                FileSource::Stdin => self.open_stdin(),
+                FileSource::Path(path) if !path.exists() => {
+                    // Like most editors, a missing file starts a new one at that path
+                    self.status_message = Some(format!("New file: {}", path.display()));
+                    self.add_document(Document {
+                        file_path: Some(path),
+                        ..Document::new()
+                    });
+                    true
                 }
+                FileSource::Path(path) => self.open_path(path),
+            };
+            if !opened {
+                continue;
+            }
+
+            let doc = &mut self.documents[self.active_doc];
+            doc.read_only = args.read_only;
+            if let Some(line) = file.line {
+                let index = doc.char_index_at(line, file.column.unwrap_or(1));
+                doc.set_cursor(ctx, index);
+            }
+            first_opened.get_or_insert(self.active_doc);
+        }
+
+        if let Some(index) = first_opened {
+            self.active_doc = index;
+        }
+    }
+
+    fn open_stdin(&mut self) -> bool {
+        let mut bytes = Vec::new();
+        match io::stdin().read_to_end(&mut bytes) {
+            Ok(_) => {
+                let encoding = encoding::detect(&bytes);
+                let (text, _) = encoding::decode(&bytes, encoding);
+                self.add_document(Document::from_unsaved_text(text, encoding));
+                self.status_message = Some("Read from stdin".to_string());
+                true
+            }
+            Err(e) => {
+                self.status_message = Some(format!("Error reading stdin: {}", e));
+                false
             }
         }
     }
 
-    pub fn save_file(&mut self) {
-        if let Some(ref path) = self.file_path {
-            self.write_file(path.clone());
+    /// Saves the active document, returning whether it was written
+    pub fn save_file(&mut self) -> bool {
+        self.save_with_encoding(self.active_doc().encoding)
+    }
+
+    /// Saves the active document in `encoding`, returning whether it was
+    /// written. The document only switches to `encoding` once the write
+    /// succeeds, so a failed or cancelled save keeps the old one.
+    pub fn save_with_encoding(&mut self, encoding: TextEncoding) -> bool {
+        if let Some(path) = self.active_doc().file_path.clone() {
+            if self.changed_on_disk(self.active_doc) {
+                self.confirm_overwrite = Some((self.active_doc().id, encoding));
+                return false;
+            }
+            self.write_file(path, encoding)
         } else {
-            self.save_file_as();
+            match pick_save_path() {
+                Some(path) => self.write_file(path, encoding),
+                None => false,
+            }
         }
     }
 
-    pub fn save_file_as(&mut self) {
-        if let Some(path) = rfd::FileDialog::new()
-            .add_filter("Text files", &["txt"])
-            .add_filter("All files", &["*"])
+    pub fn save_file_as(&mut self) -> bool {
+        match pick_save_path() {
+            Some(path) => self.write_file(path, self.active_doc().encoding),
+            None => false,
+        }
+    }
+
+    /// Saves the document held by the overwrite dialog over the file that
+    /// changed on disk, in the encoding the save asked for
+    pub fn overwrite_changed_file(&mut self) -> bool {
+        let Some((id, encoding)) = self.confirm_overwrite.take() else {
+            return false;
+        };
+        let Some(index) = self.documents.iter().position(|d| d.id == id) else {
+            return false;
+        };
+        self.active_doc = index;
+        match self.active_doc().file_path.clone() {
+            Some(path) => self.write_file(path, encoding),
+            None => false,
+        }
+    }
+
+    pub fn write_file(&mut self, path: PathBuf, encoding: TextEncoding) -> bool {
+        let backup = self.settings.files.backup;
+        match self.write_document(self.active_doc, path.clone(), encoding, backup) {
+            Ok(backup) => {
+                self.add_recent(&path);
+                self.status_message = Some(match backup {
+                    Some(backup) => {
+                        format!("Saved: {} (backup: {})", path.display(), backup.display())
+                    }
+                    None => format!("Saved: {}", path.display()),
+                });
+                true
+            }
+            Err(e) => {
+                self.status_message = Some(format!("Error saving file: {}", e));
+                false
+            }
+        }
+    }
+
+    /// Writes document `index` to `path` in `encoding`, marking it saved in
+    /// that encoding. Returns the backup path, if one was made.
+    fn write_document(
+        &mut self,
+        index: usize,
+        path: PathBuf,
+        encoding: TextEncoding,
+        backup: BackupMode,
+    ) -> Result<Option<PathBuf>, String> {
+        let doc = &self.documents[index];
+        let text = line_ending::apply(&doc.text, doc.line_ending);
+        let bytes = encoding::encode(&text, encoding).map_err(|c| {
+            format!(
+                "{:?} cannot be written as {}; try Save with Encoding",
+                c,
+                encoding.label()
+            )
+        })?;
+
+        let backup = atomic_file::write(&path, &bytes, backup).map_err(|e| e.to_string())?;
+        // Stored canonical like opened files, so reopening the same file
+        // through another path switches to this tab
+        let path = fs::canonicalize(&path).unwrap_or(path);
+        let doc = &mut self.documents[index];
+        doc.encoding = encoding;
+        doc.disk_state = DiskState::new(&path, &bytes).ok();
+        doc.external_change = None;
+        doc.file_path = Some(path);
+        doc.mark_saved();
+        Ok(backup)
+    }
+
+    /// Exports the active document to a file picked by the user
+    pub fn export(&mut self, format: ExportFormat) {
+        let doc = self.active_doc();
+        let title = doc.title();
+        let file_name = match &doc.file_path {
+            Some(path) => export::default_path(path, format),
+            None => Path::new(&title).with_extension(format.extension()),
+        };
+        let Some(path) = rfd::FileDialog::new()
+            .set_file_name(file_name.file_name().unwrap_or_default().to_string_lossy())
+            .add_filter(format.label(), &[format.extension()])
             .save_file()
+        else {
+            return;
+        };
+        // The export must not replace the document, or any other open file
+        if self
+            .documents
+            .iter()
+            .filter_map(|d| d.file_path.as_deref())
+            .any(|open| export::same_file(open, &path))
         {
-            self.write_file(path);
+            self.status_message =
+                Some(format!("Not exported: {} is open in a tab", path.display()));
+            return;
         }
+
+        let doc = self.active_doc();
+        let bytes = export::render(
+            format,
+            &title,
+            &doc.text,
+            doc.language(),
+            &theme::palette(self.dark_mode),
+            &self.settings.pdf,
+        );
+        self.status_message = Some(match atomic_file::write(&path, &bytes, BackupMode::None) {
+            Ok(_) => format!("Exported to {}", path.display()),
+            Err(e) => format!("Error exporting: {}", e),
+        });
     }
 
-    fn write_file(&mut self, path: PathBuf) {
-        match fs::write(&path, &self.text) {
-            Ok(_) => {
-                self.file_path = Some(path.clone());
-                self.dirty = false;
-                self.status_message = Some(format!("Saved: {}", path.display()));
+    pub fn reopen_with_encoding(&mut self, encoding: TextEncoding) {
+        if let Some(had_errors) = self.reload(Some(encoding)) {
+            self.status_message = Some(if had_errors {
+                format!(
+                    "Reopened as {} with invalid sequences replaced",
+                    encoding.label()
+                )
+            } else {
+                format!("Reopened as {}", encoding.label())
+            });
+        }
+    }
+
+    /// Replaces the active buffer with the file's current contents on disk
+    pub fn reload_file(&mut self) {
+        if let Some(had_errors) = self.reload(None) {
+            let title = self.active_doc().title();
+            self.status_message = Some(if had_errors {
+                format!("Reloaded with invalid sequences replaced: {}", title)
+            } else {
+                format!("Reloaded: {}", title)
+            });
+        }
+    }
+
+    /// Re-reads the active file from disk, decoding it as `encoding` or the
+    /// detected encoding. The reload is one undoable step, so unsaved edits
+    /// can be recovered. Returns whether invalid sequences were replaced,
+    /// or `None` if the file could not be read.
+    fn reload(&mut self, encoding: Option<TextEncoding>) -> Option<bool> {
+        let Some(path) = self.active_doc().file_path.clone() else {
+            self.status_message = Some("Save the document before reopening it".to_string());
+            return None;
+        };
+
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                self.status_message = Some(format!("Error opening file: {}", e));
+                return None;
+            }
+        };
+
+        let encoding = encoding.unwrap_or_else(|| encoding::detect(&bytes));
+        let (text, had_errors) = encoding::decode(&bytes, encoding);
+        let (ending, mixed) = line_ending::detect(&text);
+        let doc = self.active_doc_mut();
+        doc.replace_text(
+            &format!("Reload as {}", encoding.label()),
+            line_ending::normalize(&text).into_owned(),
+        );
+        doc.encoding = encoding;
+        doc.line_ending = ending;
+        doc.mark_saved();
+        doc.mixed_line_endings = mixed;
+        doc.disk_state = DiskState::new(&path, &bytes).ok();
+        doc.external_change = None;
+        Some(had_errors)
+    }
+
+    /// Polls every open file for changes made by other programs
+    pub fn check_external_changes(&mut self) {
+        if self.last_disk_check.elapsed() < file_watch::CHECK_INTERVAL {
+            return;
+        }
+        self.last_disk_check = Instant::now();
+
+        for doc in &mut self.documents {
+            // A deleted file may come back, e.g. after a git checkout
+            if !matches!(doc.external_change, None | Some(ExternalChange::Deleted)) {
+                continue;
+            }
+            let (Some(path), Some(known)) = (&doc.file_path, &doc.disk_state) else {
+                continue;
+            };
+            match file_watch::check(path, known) {
+                DiskChange::Unchanged => doc.external_change = None,
+                DiskChange::Touched(state) => {
+                    doc.disk_state = Some(state);
+                    doc.external_change = None;
+                }
+                DiskChange::Changed(change) => doc.external_change = Some(change),
+            }
+        }
+    }
+
+    /// Whether document `index` was changed on disk since it was opened or
+    /// last saved
+    pub fn changed_on_disk(&self, index: usize) -> bool {
+        let doc = &self.documents[index];
+        match (&doc.file_path, &doc.disk_state) {
+            (Some(path), Some(known)) => matches!(
+                file_watch::check(path, known),
+                DiskChange::Changed(ExternalChange::Modified)
+            ),
+            _ => false,
+        }
+    }
+
+    /// Dismisses an external change and keeps the buffer as it is. It stays
+    /// dirty, since it no longer matches the file on disk.
+    pub fn keep_mine(&mut self) {
+        let doc = self.active_doc_mut();
+        let Some(change) = doc.external_change.take() else {
+            return;
+        };
+        doc.disk_state = match (change, &doc.file_path) {
+            (ExternalChange::Modified, Some(path)) => DiskState::read(path).ok(),
+            _ => None,
+        };
+        doc.history.mark_unsaved();
+        doc.dirty = true;
+    }
+
+    /// Points the active document at the path its file was moved to
+    pub fn follow_rename(&mut self) {
+        let doc = self.active_doc_mut();
+        if let Some(ExternalChange::Renamed(new_path)) = doc.external_change.clone() {
+            doc.disk_state = DiskState::read(&new_path).ok();
+            doc.external_change = None;
+            doc.file_path = Some(new_path.clone());
+            self.status_message = Some(format!("Now editing: {}", new_path.display()));
+        }
+    }
+
+    /// Compares the active buffer with the file currently on disk
+    pub fn show_disk_diff(&mut self) {
+        let doc = self.active_doc();
+        let Some(path) = &doc.file_path else {
+            return;
+        };
+        match fs::read(path) {
+            Ok(bytes) => {
+                let (text, _) = encoding::decode(&bytes, doc.encoding);
+                self.diff_view = Some(DiffView::new(
+                    format!("Changes: {}", doc.title()),
+                    "On disk".to_string(),
+                    &line_ending::normalize(&text),
+                    "In editor".to_string(),
+                    &doc.text,
+                ));
             }
             Err(e) => {
-                self.status_message = Some(format!("Error saving file: {}", e));
+                self.status_message = Some(format!("Error opening file: {}", e));
+            }
+        }
+    }
+
+    pub fn convert_line_endings(&mut self, line_ending: LineEnding) {
+        if self.active_doc().read_only {
+            self.status_message = Some("Document is read-only".to_string());
+            return;
+        }
+        self.active_doc_mut().set_line_ending(line_ending);
+        self.status_message = Some(format!(
+            "Line endings will be saved as {}",
+            line_ending.label()
+        ));
+    }
+
+    pub fn undo(&mut self, ctx: &egui::Context) {
+        self.undo_steps(ctx, 1);
+    }
+
+    pub fn redo(&mut self, ctx: &egui::Context) {
+        self.redo_steps(ctx, 1);
+    }
+
+    /// Undoes `count` steps at once, as picked from the Edit > History menu
+    pub fn undo_steps(&mut self, ctx: &egui::Context, count: usize) {
+        let doc = self.active_doc_mut();
+        let mut cursor = None;
+        for _ in 0..count {
+            cursor = doc.undo().or(cursor);
+        }
+        if let Some(cursor) = cursor {
+            doc.set_cursor(ctx, cursor);
+        }
+    }
+
+    pub fn redo_steps(&mut self, ctx: &egui::Context, count: usize) {
+        let doc = self.active_doc_mut();
+        let mut cursor = None;
+        for _ in 0..count {
+            cursor = doc.redo().or(cursor);
+        }
+        if let Some(cursor) = cursor {
+            doc.set_cursor(ctx, cursor);
+        }
+    }
+
+    pub fn open_find(&mut self, ctx: &egui::Context, show_replace: bool) {
+        // Seed the query with a single-line selection
+        let doc = self.active_doc();
+        if let Some((start, end)) = doc.selection(ctx) {
+            let start = find::byte_offset(&doc.text, start);
+            let end = find::byte_offset(&doc.text, end);
+            let selected = &doc.text[start..end];
+            if !selected.is_empty() && !selected.contains('\n') {
+                self.find.query = selected.to_string();
+                self.find.invalidate();
+            }
+        }
+        self.find.open(show_replace);
+    }
+
+    pub fn close_find(&mut self, ctx: &egui::Context) {
+        self.find.open = false;
+        let id = self.active_doc().editor_id();
+        ctx.memory_mut(|m| m.request_focus(id));
+    }
+
+    /// Refreshes the match list for the active document if it went stale
+    pub fn update_find(&mut self, ctx: &egui::Context) {
+        let doc = &self.documents[self.active_doc];
+        let caret = doc.selection(ctx).map(|(start, _)| start).unwrap_or(0);
+        self.find.update(doc, caret);
+    }
+
+    pub fn find_step(&mut self, ctx: &egui::Context, forward: bool) {
+        self.update_find(ctx);
+        self.record_jump();
+        self.find.step(forward);
+        self.reveal_current_match(ctx);
+    }
+
+    pub fn reveal_current_match(&mut self, ctx: &egui::Context) {
+        if let Some(range) = self.find.current_match() {
+            let doc = &mut self.documents[self.active_doc];
+            let start = find::char_index(&doc.text, range.start);
+            let end = find::char_index(&doc.text, range.end);
+            doc.select_chars(ctx, start, end);
+        }
+    }
+
+    pub fn replace_current(&mut self, ctx: &egui::Context) {
+        if self.active_doc().read_only {
+            self.status_message = Some("Document is read-only".to_string());
+            return;
+        }
+        self.update_find(ctx);
+        let doc = &mut self.documents[self.active_doc];
+        if let Some((range, replacement)) = self.find.replace_current(&doc.text) {
+            let mut text = std::mem::take(&mut doc.text);
+            text.replace_range(range, &replacement);
+            doc.replace_text("Replace", text);
+            self.update_find(ctx);
+            self.reveal_current_match(ctx);
+        }
+    }
+
+    pub fn replace_all(&mut self, ctx: &egui::Context) {
+        if self.active_doc().read_only {
+            self.status_message = Some("Document is read-only".to_string());
+            return;
+        }
+        self.update_find(ctx);
+        let doc = &mut self.documents[self.active_doc];
+        let count = match self.find.replace_all(&doc.text) {
+            Some((text, count)) if count > 0 => {
+                doc.replace_text("Replace All", text);
+                count
+            }
+            _ => 0,
+        };
+        self.status_message = Some(format!(
+            "{} replacement{}",
+            count,
+            if count == 1 { "" } else { "s" }
+        ));
+    }
+
+    /// Reflows the selected lines, or the paragraph around the caret, to
+    /// the wrap column
+    pub fn hard_wrap(&mut self, ctx: &egui::Context) {
+        if self.active_doc().read_only {
+            self.status_message = Some("Document is read-only".to_string());
+            return;
+        }
+        let column = self.settings.editor.wrap_column;
+        let doc = &mut self.documents[self.active_doc];
+        let language = doc.language();
+        let (start, end) = doc.selection(ctx).unwrap_or((doc.cursor, doc.cursor));
+        let start = find::byte_offset(&doc.text, start);
+        let end = find::byte_offset(&doc.text, end);
+        let range = if start == end {
+            match wrap::paragraph_range(&doc.text, start, language) {
+                Some(range) => range,
+                None => return,
+            }
+        } else {
+            wrap::line_range(&doc.text, start..end)
+        };
+
+        let wrapped = wrap::hard_wrap(&doc.text[range.clone()], column, language);
+        if wrapped == doc.text[range.clone()] {
+            return;
+        }
+        let mut text = doc.text.clone();
+        text.replace_range(range.clone(), &wrapped);
+        let first = find::char_index(&text, range.start);
+        let last = first + wrapped.chars().count();
+        doc.replace_text("Hard Wrap", text);
+        doc.select_chars(ctx, first, last);
+    }
+
+    /// Shows the active document in a second pane, or turns an existing
+    /// split the other way. The new pane starts at the same caret and
+    /// scroll position as the current one.
+    pub fn split_editor(&mut self, ctx: &egui::Context, orientation: SplitOrientation) {
+        let doc = &mut self.documents[self.active_doc];
+        if let Some(split) = &mut doc.split {
+            split.orientation = orientation;
+            return;
+        }
+        if let Some(state) = egui::TextEdit::load_state(ctx, doc.pane_editor_id(0)) {
+            state.store(ctx, doc.pane_editor_id(1));
+        }
+        doc.split = Some(Split {
+            orientation,
+            focused: 0,
+            scroll_offset: doc.scroll_offset,
+        });
+        self.last_rendered_doc = None;
+    }
+
+    pub fn focus_other_pane(&mut self, ctx: &egui::Context) {
+        let doc = &mut self.documents[self.active_doc];
+        if let Some(split) = &mut doc.split {
+            split.focused = 1 - split.focused;
+            ctx.memory_mut(|m| m.request_focus(doc.editor_id()));
+        }
+    }
+
+    /// Exchanges what the two panes show; the focus moves along with its
+    /// caret
+    pub fn swap_panes(&mut self, ctx: &egui::Context) {
+        let doc = &mut self.documents[self.active_doc];
+        let (first, second) = (doc.pane_editor_id(0), doc.pane_editor_id(1));
+        let Some(split) = &mut doc.split else {
+            return;
+        };
+        let first_state = egui::TextEdit::load_state(ctx, first).unwrap_or_default();
+        let second_state = egui::TextEdit::load_state(ctx, second).unwrap_or_default();
+        first_state.store(ctx, second);
+        second_state.store(ctx, first);
+        std::mem::swap(&mut doc.scroll_offset, &mut split.scroll_offset);
+        split.focused = 1 - split.focused;
+        ctx.memory_mut(|m| m.request_focus(doc.editor_id()));
+        self.last_rendered_doc = None;
+    }
+
+    /// Closes the focused pane, leaving the other one as the only editor
+    pub fn close_pane(&mut self, ctx: &egui::Context) {
+        let doc = &mut self.documents[self.active_doc];
+        let Some(split) = doc.split.take() else {
+            return;
+        };
+        if split.focused == 0 {
+            if let Some(state) = egui::TextEdit::load_state(ctx, doc.pane_editor_id(1)) {
+                state.store(ctx, doc.pane_editor_id(0));
+            }
+            doc.scroll_offset = split.scroll_offset;
+            self.last_rendered_doc = None;
+        }
+        ctx.memory_mut(|m| m.request_focus(doc.editor_id()));
+    }
+
+    /// Flips the task list box whose `[ ]`/`[x]` character is at byte
+    /// `offset` in `revision` of the active document. Does nothing if the
+    /// text has changed since.
+    pub fn toggle_task(&mut self, revision: u64, offset: usize) {
+        if self.active_doc().read_only {
+            self.status_message = Some("Document is read-only".to_string());
+            return;
+        }
+        let doc = self.active_doc_mut();
+        if doc.revision != revision {
+            return;
+        }
+        let replacement = match offset
+            .checked_sub(1)
+            .and_then(|start| doc.text.get(start..offset + 2))
+        {
+            Some("[ ]") => "x",
+            Some("[x]" | "[X]") => " ",
+            _ => return,
+        };
+        let mut text = doc.text.clone();
+        text.replace_range(offset..offset + 1, replacement);
+        doc.replace_text("Toggle Task", text);
+    }
+
+    fn current_location(&self) -> Location {
+        Location {
+            doc_id: self.active_doc().id,
+            cursor: self.active_doc().cursor,
+        }
+    }
+
+    /// Remembers the caret position before a big jump, for Alt+Left
+    pub fn record_jump(&mut self) {
+        let location = self.current_location();
+        self.nav.record(location);
+    }
+
+    fn jump_to(&mut self, ctx: &egui::Context, location: Location) {
+        if let Some(index) = self.documents.iter().position(|d| d.id == location.doc_id) {
+            self.active_doc = index;
+            let doc = self.active_doc_mut();
+            let cursor = location.cursor.min(doc.text.chars().count());
+            doc.set_cursor(ctx, cursor);
+        }
+    }
+
+    pub fn navigate_back(&mut self, ctx: &egui::Context) {
+        let current = self.current_location();
+        let documents = &self.documents;
+        let exists = |id| documents.iter().any(|d| d.id == id);
+        if let Some(location) = self.nav.back(current, exists) {
+            self.jump_to(ctx, location);
+        }
+    }
+
+    pub fn navigate_forward(&mut self, ctx: &egui::Context) {
+        let current = self.current_location();
+        let documents = &self.documents;
+        let exists = |id| documents.iter().any(|d| d.id == id);
+        if let Some(location) = self.nav.forward(current, exists) {
+            self.jump_to(ctx, location);
+        }
+    }
+
+    pub fn open_go_to(&mut self) {
+        let doc = self.active_doc();
+        self.go_to.input = format!("{}:{}", doc.cursor_line, doc.cursor_column);
+        self.go_to.error = None;
+        self.go_to.open = true;
+        self.go_to.focus_input = true;
+    }
+
+    pub fn close_go_to(&mut self, ctx: &egui::Context) {
+        self.go_to.open = false;
+        let id = self.active_doc().editor_id();
+        ctx.memory_mut(|m| m.request_focus(id));
+    }
+
+    /// Moves the caret to the location typed into the Go to Line dialog
+    pub fn go_to_line(&mut self, ctx: &egui::Context) {
+        match navigation::resolve_go_to(&self.go_to.input, self.active_doc()) {
+            Some(index) => {
+                self.record_jump();
+                self.active_doc_mut().set_cursor(ctx, index);
+                self.go_to.open = false;
+            }
+            None => {
+                self.go_to.error = Some("Enter a line, line:column or percentage".to_string());
             }
         }
     }
 
+    pub fn select_tab(&mut self, index: usize) {
+        if index < self.documents.len() {
+            self.active_doc = index;
+        }
+    }
+
+    pub fn close_tab(&mut self, index: usize) {
+        let Some(doc) = self.documents.get(index) else {
+            return;
+        };
+
+        if doc.dirty {
+            self.active_doc = index;
+            self.show_unsaved_dialog = true;
+            self.pending_action = Some(PendingAction::CloseTab(doc.id));
+        } else {
+            self.do_close_tab(index);
+        }
+    }
+
+    pub fn do_close_tab(&mut self, index: usize) {
+        self.documents.remove(index);
+        if self.documents.is_empty() {
+            self.documents.push(Document::new());
+        }
+        if self.active_doc > index || self.active_doc >= self.documents.len() {
+            self.active_doc = self.active_doc.saturating_sub(1);
+        }
+    }
+
+    /// Walks through every dirty tab before closing the window. Each call
+    /// prompts for the next dirty tab; once none are left the window closes.
     pub fn request_exit(&mut self, ctx: &egui::Context) {
-        if self.dirty {
+        if let Some(index) = self.documents.iter().position(|d| self.blocks_exit(d)) {
+            self.active_doc = index;
             self.show_unsaved_dialog = true;
             self.pending_action = Some(PendingAction::Exit);
         } else {
@@ -199,42 +1455,171 @@ impl NotepadApp {
 
     pub fn execute_pending_action(&mut self, action: &PendingAction, ctx: &egui::Context) {
         match action {
-            PendingAction::New => self.do_new_file(),
-            PendingAction::Open => self.do_open_file(),
-            PendingAction::Exit => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+            PendingAction::CloseTab(id) => {
+                if let Some(index) = self.documents.iter().position(|d| d.id == *id) {
+                    self.do_close_tab(index);
+                }
+            }
+            PendingAction::Exit => {
+                // "Don't Save" leaves the tab dirty: discard it and move on
+                if self.active_doc().dirty {
+                    self.do_close_tab(self.active_doc);
+                }
+                self.request_exit(ctx);
+            }
         }
     }
 
     pub fn handle_keyboard_shortcuts(&mut self, ctx: &egui::Context) {
-        let ctrl = ctx.input(|i| i.modifiers.ctrl || i.modifiers.mac_cmd);
-        let shift = ctx.input(|i| i.modifiers.shift);
-
-        if ctrl && ctx.input(|i| i.key_pressed(egui::Key::N)) {
-            self.new_file();
+        if self.show_unsaved_dialog || self.confirm_overwrite.is_some() {
+            return;
         }
 
-        if ctrl && ctx.input(|i| i.key_pressed(egui::Key::O)) {
-            self.open_file();
+        // Bound keys are consumed even when the command is unavailable, so
+        // the TextEdit's own undoer never sees them and Ctrl+H does not
+        // delete a character.
+        for &command in Command::ALL {
+            if self.shortcut_pressed(ctx, command) && self.command_enabled(command) {
+                self.run_command(ctx, command);
+            }
         }
 
-        if ctrl && ctx.input(|i| i.key_pressed(egui::Key::S)) {
-            if shift {
-                self.save_file_as();
-            } else {
-                self.save_file();
+        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            if self.command_palette.open {
+                self.close_command_palette(ctx);
+                return;
+            }
+            if self.find.open {
+                self.close_find(ctx);
+            }
+            if self.go_to.open {
+                self.close_go_to(ctx);
             }
         }
     }
 
     pub fn handle_close_request(&mut self, ctx: &egui::Context) {
         if ctx.input(|i| i.viewport().close_requested()) {
-            if self.dirty && !self.show_unsaved_dialog {
-                self.show_unsaved_dialog = true;
-                self.pending_action = Some(PendingAction::Exit);
+            if self.show_unsaved_dialog {
                 ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
-            } else if self.show_unsaved_dialog {
+            } else if self.has_unsaved_changes() {
+                self.request_exit(ctx);
                 ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
             }
         }
     }
 }
+
+/// Asks the user where to save a text file
+fn pick_save_path() -> Option<PathBuf> {
+    rfd::FileDialog::new()
+        .add_filter("Text files", &["txt"])
+        .add_filter("All files", &["*"])
+        .save_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::{self, BlockKind};
+
+    #[test]
+    fn overwrite_after_save_with_encoding_uses_the_new_encoding() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "hello").unwrap();
+
+        let mut app = NotepadApp::with_settings(Settings::default(), None);
+        assert!(app.open_path(path.clone()));
+        fs::write(&path, "changed elsewhere").unwrap();
+
+        assert!(!app.save_with_encoding(TextEncoding::Utf16Le));
+        assert!(app.confirm_overwrite.is_some());
+        assert_eq!(app.active_doc().encoding, TextEncoding::Utf8);
+
+        assert!(app.overwrite_changed_file());
+        assert_eq!(app.active_doc().encoding, TextEncoding::Utf16Le);
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(encoding::detect(&bytes), TextEncoding::Utf16Le);
+        assert_eq!(encoding::decode(&bytes, TextEncoding::Utf16Le).0, "hello");
+    }
+
+    #[test]
+    fn cancelled_overwrite_keeps_the_old_encoding() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "hello").unwrap();
+
+        let mut app = NotepadApp::with_settings(Settings::default(), None);
+        assert!(app.open_path(path.clone()));
+        fs::write(&path, "changed elsewhere").unwrap();
+
+        assert!(!app.save_with_encoding(TextEncoding::Utf16Le));
+        app.confirm_overwrite = None;
+        assert_eq!(app.active_doc().encoding, TextEncoding::Utf8);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "changed elsewhere");
+    }
+
+    #[test]
+    fn toggle_task_flips_the_box_in_the_source() {
+        let mut app = NotepadApp::with_settings(Settings::default(), None);
+        let text = "# Tasks\n- [ ] café\n  - [X] done";
+        app.active_doc_mut()
+            .replace_text("Typing", text.to_string());
+
+        let offsets: Vec<usize> = markdown::parse(text)
+            .iter()
+            .filter_map(|block| match &block.kind {
+                BlockKind::List(items) => Some(items),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|item| item.task.map(|task| task.offset))
+            .collect();
+
+        let revision = app.active_doc().revision;
+        app.toggle_task(revision, offsets[0]);
+        assert_eq!(app.active_doc().text, "# Tasks\n- [x] café\n  - [X] done");
+
+        let revision = app.active_doc().revision;
+        app.toggle_task(revision, offsets[1]);
+        assert_eq!(app.active_doc().text, "# Tasks\n- [x] café\n  - [ ] done");
+
+        // A click on a preview of an older revision is ignored
+        app.toggle_task(revision, offsets[0]);
+        assert_eq!(app.active_doc().text, "# Tasks\n- [x] café\n  - [ ] done");
+
+        app.active_doc_mut().undo();
+        assert_eq!(app.active_doc().text, "# Tasks\n- [x] café\n  - [X] done");
+    }
+
+    #[test]
+    fn saved_paths_are_canonical() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".").join("notes.txt");
+
+        let mut app = NotepadApp::with_settings(Settings::default(), None);
+        app.active_doc_mut()
+            .replace_text("Typing", "hello".to_string());
+        assert!(app.write_file(path.clone(), TextEncoding::Utf8));
+        let canonical = fs::canonicalize(&path).unwrap();
+        assert_eq!(app.active_doc().file_path.as_ref(), Some(&canonical));
+
+        // Opening it again by another spelling finds the same tab
+        let tabs = app.documents.len();
+        assert!(app.open_path(path));
+        assert_eq!(app.documents.len(), tabs);
+    }
+
+    #[test]
+    fn prune_recent_drops_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let kept = dir.path().join("kept.txt");
+        fs::write(&kept, "").unwrap();
+
+        let mut app = NotepadApp::with_settings(Settings::default(), None);
+        app.recent_files = vec![dir.path().join("gone.txt"), kept.clone()];
+        app.prune_recent();
+        assert_eq!(app.recent_files, [kept]);
+    }
+}
@@ -0,0 +1,258 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// What to keep of the previous version when overwriting a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupMode {
+    None,
+    /// `notes.txt~`, replaced on every save
+    Tilde,
+    /// `notes.txt.20250101-120000~`, one per save
+    Timestamped,
+}
+
+impl BackupMode {
+    pub const ALL: &'static [BackupMode] =
+        &[BackupMode::None, BackupMode::Tilde, BackupMode::Timestamped];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BackupMode::None => "No backup",
+            BackupMode::Tilde => "Keep file~",
+            BackupMode::Timestamped => "Keep timestamped copies",
+        }
+    }
+
+    fn backup_path(self, path: &Path) -> Option<PathBuf> {
+        let name = path.file_name()?.to_string_lossy();
+        let backup_name = match self {
+            BackupMode::None => return None,
+            BackupMode::Tilde => format!("{}~", name),
+            BackupMode::Timestamped => {
+                format!("{}.{}~", name, Local::now().format("%Y%m%d-%H%M%S"))
+            }
+        };
+        Some(path.with_file_name(backup_name))
+    }
+}
+
+/// Which step of a save failed, with the underlying I/O error
+#[derive(Debug)]
+pub enum SaveError {
+    CreateTemp(PathBuf, io::Error),
+    Write(PathBuf, io::Error),
+    Sync(PathBuf, io::Error),
+    Permissions(PathBuf, io::Error),
+    Backup(PathBuf, io::Error),
+    Rename(PathBuf, io::Error),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::CreateTemp(dir, e) => {
+                write!(
+                    f,
+                    "could not create a temporary file in {}: {}",
+                    dir.display(),
+                    e
+                )
+            }
+            SaveError::Write(temp, e) => write!(f, "could not write {}: {}", temp.display(), e),
+            SaveError::Sync(temp, e) => {
+                write!(f, "could not flush {} to disk: {}", temp.display(), e)
+            }
+            SaveError::Permissions(path, e) => {
+                write!(f, "could not copy permissions of {}: {}", path.display(), e)
+            }
+            SaveError::Backup(backup, e) => {
+                write!(f, "could not create backup {}: {}", backup.display(), e)
+            }
+            SaveError::Rename(path, e) => {
+                write!(f, "could not replace {}: {}", path.display(), e)
+            }
+        }
+    }
+}
+
+/// Writes `bytes` to `path` without ever leaving a half-written file: the
+/// data goes to a temporary file in the same directory, is flushed to disk
+/// and then renamed over the target. The target's permissions are kept and
+/// symlinks are followed. Returns the backup path, if one was made.
+pub fn write(path: &Path, bytes: &[u8], backup: BackupMode) -> Result<Option<PathBuf>, SaveError> {
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let existing = fs::metadata(&target).ok();
+
+    let (temp_path, mut temp) = create_temp(&dir, &target)?;
+    let result = (|| {
+        temp.write_all(bytes)
+            .map_err(|e| SaveError::Write(temp_path.clone(), e))?;
+        temp.sync_all()
+            .map_err(|e| SaveError::Sync(temp_path.clone(), e))?;
+        drop(temp);
+
+        if let Some(ref metadata) = existing {
+            fs::set_permissions(&temp_path, metadata.permissions())
+                .map_err(|e| SaveError::Permissions(target.clone(), e))?;
+        }
+
+        let backup_path = match backup.backup_path(&target) {
+            Some(backup_path) if existing.is_some() => {
+                fs::copy(&target, &backup_path)
+                    .map_err(|e| SaveError::Backup(backup_path.clone(), e))?;
+                Some(backup_path)
+            }
+            _ => None,
+        };
+
+        fs::rename(&temp_path, &target).map_err(|e| SaveError::Rename(target.clone(), e))?;
+        Ok(backup_path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    } else {
+        sync_dir(&dir);
+    }
+    result
+}
+
+fn create_temp(dir: &Path, target: &Path) -> Result<(PathBuf, File), SaveError> {
+    let name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    loop {
+        let temp_path = dir.join(format!(
+            ".{}.{}.{}.tmp",
+            name,
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(SaveError::CreateTemp(dir.to_path_buf(), e)),
+        }
+    }
+}
+
+/// Flushes the rename itself to disk. Best effort: not every platform lets
+/// a directory be opened.
+fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn creates_and_overwrites_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+
+        assert_eq!(write(&path, b"first", BackupMode::Tilde).unwrap(), None);
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+        assert_eq!(write(&path, b"second", BackupMode::None).unwrap(), None);
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(entries(dir.path()), ["notes.txt"]);
+    }
+
+    #[test]
+    fn tilde_backup_keeps_the_previous_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "old").unwrap();
+
+        let backup = write(&path, b"new", BackupMode::Tilde).unwrap().unwrap();
+        assert_eq!(backup.file_name().unwrap(), "notes.txt~");
+        assert_eq!(fs::read(&backup).unwrap(), b"old");
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+
+        write(&path, b"newer", BackupMode::Tilde).unwrap();
+        assert_eq!(fs::read(&backup).unwrap(), b"new");
+        assert_eq!(entries(dir.path()), ["notes.txt", "notes.txt~"]);
+    }
+
+    #[test]
+    fn timestamped_backup_is_named_after_the_save_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "old").unwrap();
+
+        let backup = write(&path, b"new", BackupMode::Timestamped)
+            .unwrap()
+            .unwrap();
+        let name = backup.file_name().unwrap().to_string_lossy().into_owned();
+        let stamp = name
+            .strip_prefix("notes.txt.")
+            .and_then(|rest| rest.strip_suffix('~'))
+            .unwrap();
+        assert!(chrono::NaiveDateTime::parse_from_str(stamp, "%Y%m%d-%H%M%S").is_ok());
+        assert_eq!(fs::read(&backup).unwrap(), b"old");
+    }
+
+    #[test]
+    fn failed_save_removes_the_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        // A non-empty directory cannot be replaced by a file
+        let path = dir.path().join("notes.txt");
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("inside"), "").unwrap();
+
+        let err = write(&path, b"data", BackupMode::None).unwrap_err();
+        assert!(matches!(err, SaveError::Rename(..)), "{}", err);
+        assert_eq!(entries(dir.path()), ["notes.txt"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_permissions_and_follows_symlinks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script.sh");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+        let link = dir.path().join("link.sh");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+
+        write(&link, b"new", BackupMode::None).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+}
@@ -0,0 +1,401 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::export::ExportFormat;
+use crate::settings::Theme;
+
+pub const USAGE: &str = "\
+Usage: rusty-notepad [OPTIONS] [+LINE] [FILE[:LINE[:COL]]]...
+
+Opens each FILE in its own tab. Use - to read a document from stdin.
+
+Arguments:
+  FILE[:LINE[:COL]]      File to open, optionally jumping to LINE and COL
+  +LINE[:COL]            Jump to LINE (and COL) in the next FILE
+
+Options:
+  -t, --theme <THEME>    Color theme for this session: latte or mocha
+  -r, --read-only        Open the given files read-only
+  -e, --export <FORMAT>  Convert each FILE to html or pdf and exit without
+                         opening a window
+  -o, --output <PATH>    Where to write the export (default: FILE with the
+                         format's extension, or stdout for -)
+  -h, --help             Print this help and exit
+  -V, --version          Print the version and exit
+";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileSource {
+    Path(PathBuf),
+    Stdin,
+}
+
+/// One document requested on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileArg {
+    pub source: FileSource,
+    /// 1-based line to place the caret on
+    pub line: Option<usize>,
+    /// 1-based column to place the caret on
+    pub column: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CliArgs {
+    pub files: Vec<FileArg>,
+    pub theme: Option<Theme>,
+    pub read_only: bool,
+    /// Convert the files instead of opening them
+    pub export: Option<ExportFormat>,
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run(CliArgs),
+    Help,
+    Version,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    UnknownOption(String),
+    MissingValue(&'static str),
+    InvalidTheme(String),
+    InvalidPosition(String),
+    DanglingPosition(String),
+    InvalidExportFormat(String),
+    OutputWithoutExport,
+    ExportWithoutFiles,
+    OutputWithManyFiles,
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UnknownOption(opt) => write!(f, "unknown option '{}'", opt),
+            CliError::MissingValue(opt) => write!(f, "option '{}' requires a value", opt),
+            CliError::InvalidTheme(theme) => {
+                write!(f, "invalid theme '{}' (expected latte or mocha)", theme)
+            }
+            CliError::InvalidPosition(pos) => {
+                write!(
+                    f,
+                    "invalid position '{}' (expected +LINE or +LINE:COL)",
+                    pos
+                )
+            }
+            CliError::DanglingPosition(pos) => write!(f, "'{}' is not followed by a file", pos),
+            CliError::InvalidExportFormat(format) => {
+                write!(
+                    f,
+                    "invalid export format '{}' (expected html or pdf)",
+                    format
+                )
+            }
+            CliError::OutputWithoutExport => write!(f, "'--output' requires '--export'"),
+            CliError::ExportWithoutFiles => write!(f, "'--export' needs at least one file"),
+            CliError::OutputWithManyFiles => {
+                write!(f, "'--output' can only be used with a single file")
+            }
+        }
+    }
+}
+
+fn parse_theme(value: &str) -> Result<Theme, CliError> {
+    match value.to_ascii_lowercase().as_str() {
+        "latte" | "light" => Ok(Theme::Latte),
+        "mocha" | "dark" => Ok(Theme::Mocha),
+        _ => Err(CliError::InvalidTheme(value.to_string())),
+    }
+}
+
+/// Parses `LINE` or `LINE:COL`, both 1-based
+pub fn parse_position(text: &str) -> Option<(usize, Option<usize>)> {
+    let (line, column) = match text.split_once(':') {
+        Some((line, column)) => (line, Some(column)),
+        None => (text, None),
+    };
+    let line = line.parse().ok().filter(|&l| l > 0)?;
+    let column = match column {
+        Some(c) => Some(c.parse().ok().filter(|&c| c > 0)?),
+        None => None,
+    };
+    Some((line, column))
+}
+
+/// Splits `path:line:col` or `path:line`. A name that exists on disk as
+/// given always wins, so files with colons in their names still open.
+fn file_arg(arg: &str) -> FileArg {
+    let number = |s: &str| s.parse::<usize>().ok().filter(|&n| n > 0);
+    let plain = |path: &str, line, column| FileArg {
+        source: FileSource::Path(PathBuf::from(path)),
+        line,
+        column,
+    };
+
+    if Path::new(arg).exists() {
+        return plain(arg, None, None);
+    }
+
+    if let Some((rest, last)) = arg.rsplit_once(':') {
+        if let Some(last) = number(last) {
+            if let Some((path, line)) = rest.rsplit_once(':') {
+                if let (Some(line), false) = (number(line), path.is_empty()) {
+                    return plain(path, Some(line), Some(last));
+                }
+            }
+            if !rest.is_empty() {
+                return plain(rest, Some(last), None);
+            }
+        }
+    }
+
+    plain(arg, None, None)
+}
+
+/// Parses the arguments after the program name
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, CliError> {
+    let mut parsed = CliArgs::default();
+    let mut pending_position: Option<(String, usize, Option<usize>)> = None;
+    let mut only_files = false;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut file = if only_files {
+            file_arg(&arg)
+        } else if arg == "-" {
+            FileArg {
+                source: FileSource::Stdin,
+                line: None,
+                column: None,
+            }
+        } else if let Some(position) = arg.strip_prefix('+') {
+            let (line, column) =
+                parse_position(position).ok_or_else(|| CliError::InvalidPosition(arg.clone()))?;
+            pending_position = Some((arg, line, column));
+            continue;
+        } else if arg.starts_with('-') {
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            match name {
+                "-h" | "--help" => return Ok(Command::Help),
+                "-V" | "--version" => return Ok(Command::Version),
+                "-r" | "--read-only" => parsed.read_only = true,
+                "-t" | "--theme" => {
+                    let value = inline_value
+                        .or_else(|| args.next())
+                        .ok_or(CliError::MissingValue("--theme"))?;
+                    parsed.theme = Some(parse_theme(&value)?);
+                }
+                "-e" | "--export" => {
+                    let value = inline_value
+                        .or_else(|| args.next())
+                        .ok_or(CliError::MissingValue("--export"))?;
+                    let format = ExportFormat::from_name(&value)
+                        .ok_or(CliError::InvalidExportFormat(value))?;
+                    parsed.export = Some(format);
+                }
+                "-o" | "--output" => {
+                    let value = inline_value
+                        .or_else(|| args.next())
+                        .ok_or(CliError::MissingValue("--output"))?;
+                    parsed.output = Some(PathBuf::from(value));
+                }
+                "--" => only_files = true,
+                _ => return Err(CliError::UnknownOption(arg)),
+            }
+            continue;
+        } else {
+            file_arg(&arg)
+        };
+
+        if let Some((_, line, column)) = pending_position.take() {
+            file.line = Some(line);
+            file.column = column;
+        }
+        parsed.files.push(file);
+    }
+
+    if let Some((arg, _, _)) = pending_position {
+        return Err(CliError::DanglingPosition(arg));
+    }
+    if parsed.export.is_none() && parsed.output.is_some() {
+        return Err(CliError::OutputWithoutExport);
+    }
+    if parsed.export.is_some() && parsed.files.is_empty() {
+        return Err(CliError::ExportWithoutFiles);
+    }
+    if parsed.output.is_some() && parsed.files.len() > 1 {
+        return Err(CliError::OutputWithManyFiles);
+    }
+
+    Ok(Command::Run(parsed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> Result<CliArgs, CliError> {
+        match parse(args.iter().map(|a| a.to_string()))? {
+            Command::Run(parsed) => Ok(parsed),
+            other => panic!("expected Run, got {:?}", other),
+        }
+    }
+
+    fn file(path: &str, line: Option<usize>, column: Option<usize>) -> FileArg {
+        FileArg {
+            source: FileSource::Path(PathBuf::from(path)),
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn plus_position_applies_to_the_next_file() {
+        let parsed = run(&["+12:5", "a.txt", "b.txt", "+3", "c.txt"]).unwrap();
+        assert_eq!(
+            parsed.files,
+            [
+                file("a.txt", Some(12), Some(5)),
+                file("b.txt", None, None),
+                file("c.txt", Some(3), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn path_suffix_sets_line_and_column() {
+        let parsed = run(&["missing.rs:10:4", "missing.rs:7", "missing.rs:x"]).unwrap();
+        assert_eq!(
+            parsed.files,
+            [
+                file("missing.rs", Some(10), Some(4)),
+                file("missing.rs", Some(7), None),
+                file("missing.rs:x", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn existing_path_with_colons_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes:2");
+        std::fs::write(&path, "").unwrap();
+        let arg = path.to_str().unwrap();
+
+        let parsed = run(&[arg]).unwrap();
+        assert_eq!(parsed.files, [file(arg, None, None)]);
+    }
+
+    #[test]
+    fn dash_reads_stdin_and_double_dash_ends_options() {
+        let parsed = run(&["-", "--", "-r", "-"]).unwrap();
+        assert_eq!(
+            parsed.files,
+            [
+                FileArg {
+                    source: FileSource::Stdin,
+                    line: None,
+                    column: None,
+                },
+                file("-r", None, None),
+                file("-", None, None),
+            ]
+        );
+        assert!(!parsed.read_only);
+    }
+
+    #[test]
+    fn options_take_separate_or_inline_values() {
+        let parsed = run(&["-t", "light", "--read-only", "a.txt"]).unwrap();
+        assert_eq!(parsed.theme, Some(Theme::Latte));
+        assert!(parsed.read_only);
+
+        let parsed = run(&["--theme=Mocha", "a.txt"]).unwrap();
+        assert_eq!(parsed.theme, Some(Theme::Mocha));
+    }
+
+    #[test]
+    fn export_with_output() {
+        let parsed = run(&["-e", "pdf", "-o", "out.pdf", "a.md"]).unwrap();
+        assert_eq!(parsed.export, Some(ExportFormat::Pdf));
+        assert_eq!(parsed.output, Some(PathBuf::from("out.pdf")));
+        assert_eq!(parsed.files, [file("a.md", None, None)]);
+
+        let parsed = run(&["--export=html", "a.md", "b.md"]).unwrap();
+        assert_eq!(parsed.export, Some(ExportFormat::Html));
+        assert_eq!(parsed.files.len(), 2);
+    }
+
+    #[test]
+    fn help_and_version() {
+        let parse = |args: &[&str]| parse(args.iter().map(|a| a.to_string()));
+        assert_eq!(parse(&["a.txt", "--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["-h"]), Ok(Command::Help));
+        assert_eq!(parse(&["-V"]), Ok(Command::Version));
+    }
+
+    #[test]
+    fn errors() {
+        let cases: &[(&[&str], CliError, &str)] = &[
+            (
+                &["--bogus"],
+                CliError::UnknownOption("--bogus".to_string()),
+                "unknown option '--bogus'",
+            ),
+            (
+                &["--theme"],
+                CliError::MissingValue("--theme"),
+                "option '--theme' requires a value",
+            ),
+            (
+                &["-t", "neon"],
+                CliError::InvalidTheme("neon".to_string()),
+                "invalid theme 'neon' (expected latte or mocha)",
+            ),
+            (
+                &["+0", "a.txt"],
+                CliError::InvalidPosition("+0".to_string()),
+                "invalid position '+0' (expected +LINE or +LINE:COL)",
+            ),
+            (
+                &["+3:x", "a.txt"],
+                CliError::InvalidPosition("+3:x".to_string()),
+                "invalid position '+3:x' (expected +LINE or +LINE:COL)",
+            ),
+            (
+                &["a.txt", "+3"],
+                CliError::DanglingPosition("+3".to_string()),
+                "'+3' is not followed by a file",
+            ),
+            (
+                &["-e", "docx", "a.txt"],
+                CliError::InvalidExportFormat("docx".to_string()),
+                "invalid export format 'docx' (expected html or pdf)",
+            ),
+            (
+                &["-o", "out.html", "a.txt"],
+                CliError::OutputWithoutExport,
+                "'--output' requires '--export'",
+            ),
+            (
+                &["-e", "html"],
+                CliError::ExportWithoutFiles,
+                "'--export' needs at least one file",
+            ),
+            (
+                &["-e", "html", "-o", "out.html", "a.txt", "b.txt"],
+                CliError::OutputWithManyFiles,
+                "'--output' can only be used with a single file",
+            ),
+        ];
+        for (args, error, message) in cases {
+            assert_eq!(run(args).as_ref(), Err(error), "{:?}", args);
+            assert_eq!(error.to_string(), *message);
+        }
+    }
+}
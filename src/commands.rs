@@ -0,0 +1,524 @@
+use crate::atomic_file::BackupMode;
+use crate::encoding::TextEncoding;
+use crate::export::ExportFormat;
+use crate::pdf::{PageSize, PdfFont};
+use crate::split::SplitOrientation;
+use crate::weather::{LocationSource, TemperatureUnit, WindUnit};
+use crate::wrap::WrapMode;
+
+/// Font sizes offered under Settings > Presets, with their keybinding id
+/// and label
+const FONT_PRESETS: &[(u8, &str, &str)] = &[
+    (12, "font_small", "Small (12)"),
+    (14, "font_medium", "Medium (14)"),
+    (18, "font_large", "Large (18)"),
+    (24, "font_extra_large", "Extra Large (24)"),
+];
+
+fn font_preset(size: u8) -> Option<&'static (u8, &'static str, &'static str)> {
+    FONT_PRESETS.iter().find(|(preset, _, _)| *preset == size)
+}
+
+/// Every action the user can run. Menus, keyboard shortcuts and the command
+/// palette are all built from this list, so an action's label and shortcut
+/// read the same everywhere.
+///
+/// Menu entries that edit a value rather than run an action stay widgets:
+/// the wrap column, the recent files limit, PDF font size and margins, and
+/// the weather place name and coordinates. So do the entries of Open Recent
+/// and Edit > History, which list files and undo steps, not actions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    New,
+    Open,
+    Save,
+    SaveAs,
+    ReopenWithEncoding(TextEncoding),
+    SaveWithEncoding(TextEncoding),
+    Export(ExportFormat),
+    PdfPageSize(PageSize),
+    PdfFont(PdfFont),
+    TogglePdfHeaderFooter,
+    ToggleReadOnly,
+    CloseTab,
+    Exit,
+    Undo,
+    Redo,
+    Find,
+    Replace,
+    GoToLine,
+    NavigateBack,
+    NavigateForward,
+    HardWrap,
+    OpenPalette,
+    MarkdownPreview,
+    Wrap(WrapMode),
+    ToggleRuler,
+    Split(SplitOrientation),
+    FocusOtherPane,
+    SwapPanes,
+    ClosePane,
+    ToggleTheme,
+    FontSmaller,
+    FontLarger,
+    FontSize(u8),
+    ToggleLineNumbers,
+    Backup(BackupMode),
+    ToggleAutosave,
+    ToggleRestoreSession,
+    ToggleWeather,
+    WeatherLocation(LocationSource),
+    TemperatureUnit(TemperatureUnit),
+    WindUnit(WindUnit),
+    ToggleMonitor,
+}
+
+impl Command {
+    pub const ALL: &'static [Command] = &[
+        Command::New,
+        Command::Open,
+        Command::Save,
+        Command::SaveAs,
+        Command::ReopenWithEncoding(TextEncoding::Utf8),
+        Command::ReopenWithEncoding(TextEncoding::Utf8Bom),
+        Command::ReopenWithEncoding(TextEncoding::Utf16Le),
+        Command::ReopenWithEncoding(TextEncoding::Utf16Be),
+        Command::ReopenWithEncoding(TextEncoding::Latin1),
+        Command::ReopenWithEncoding(TextEncoding::Windows1252),
+        Command::ReopenWithEncoding(TextEncoding::Iso8859_15),
+        Command::ReopenWithEncoding(TextEncoding::Windows1250),
+        Command::ReopenWithEncoding(TextEncoding::Windows1251),
+        Command::ReopenWithEncoding(TextEncoding::ShiftJis),
+        Command::ReopenWithEncoding(TextEncoding::Gbk),
+        Command::SaveWithEncoding(TextEncoding::Utf8),
+        Command::SaveWithEncoding(TextEncoding::Utf8Bom),
+        Command::SaveWithEncoding(TextEncoding::Utf16Le),
+        Command::SaveWithEncoding(TextEncoding::Utf16Be),
+        Command::SaveWithEncoding(TextEncoding::Latin1),
+        Command::SaveWithEncoding(TextEncoding::Windows1252),
+        Command::SaveWithEncoding(TextEncoding::Iso8859_15),
+        Command::SaveWithEncoding(TextEncoding::Windows1250),
+        Command::SaveWithEncoding(TextEncoding::Windows1251),
+        Command::SaveWithEncoding(TextEncoding::ShiftJis),
+        Command::SaveWithEncoding(TextEncoding::Gbk),
+        Command::Export(ExportFormat::Html),
+        Command::Export(ExportFormat::Pdf),
+        Command::PdfPageSize(PageSize::A4),
+        Command::PdfPageSize(PageSize::A5),
+        Command::PdfPageSize(PageSize::Letter),
+        Command::PdfPageSize(PageSize::Legal),
+        Command::PdfFont(PdfFont::Courier),
+        Command::PdfFont(PdfFont::Helvetica),
+        Command::PdfFont(PdfFont::Times),
+        Command::TogglePdfHeaderFooter,
+        Command::ToggleReadOnly,
+        Command::CloseTab,
+        Command::Exit,
+        Command::Undo,
+        Command::Redo,
+        Command::Find,
+        Command::Replace,
+        Command::GoToLine,
+        Command::NavigateBack,
+        Command::NavigateForward,
+        Command::OpenPalette,
+        Command::MarkdownPreview,
+        Command::Wrap(WrapMode::None),
+        Command::Wrap(WrapMode::Window),
+        Command::Wrap(WrapMode::Column),
+        Command::ToggleRuler,
+        Command::HardWrap,
+        Command::Split(SplitOrientation::Horizontal),
+        Command::Split(SplitOrientation::Vertical),
+        Command::FocusOtherPane,
+        Command::SwapPanes,
+        Command::ClosePane,
+        Command::ToggleTheme,
+        Command::FontSmaller,
+        Command::FontLarger,
+        Command::FontSize(12),
+        Command::FontSize(14),
+        Command::FontSize(18),
+        Command::FontSize(24),
+        Command::ToggleLineNumbers,
+        Command::Backup(BackupMode::None),
+        Command::Backup(BackupMode::Tilde),
+        Command::Backup(BackupMode::Timestamped),
+        Command::ToggleAutosave,
+        Command::ToggleRestoreSession,
+        Command::ToggleWeather,
+        Command::WeatherLocation(LocationSource::Place),
+        Command::WeatherLocation(LocationSource::Coordinates),
+        Command::WeatherLocation(LocationSource::Ip),
+        Command::TemperatureUnit(TemperatureUnit::Celsius),
+        Command::TemperatureUnit(TemperatureUnit::Fahrenheit),
+        Command::WindUnit(WindUnit::Kmh),
+        Command::WindUnit(WindUnit::Mph),
+        Command::WindUnit(WindUnit::Ms),
+        Command::ToggleMonitor,
+    ];
+
+    /// Stable name used as the key in the `[keybindings]` settings table
+    pub fn id(self) -> String {
+        // Choices are named after their variant, lowercased like the
+        // values in the rest of the settings file
+        let choice = |prefix: &str, value: &dyn std::fmt::Debug| {
+            format!("{}_{}", prefix, format!("{:?}", value).to_lowercase())
+        };
+        let id = match self {
+            Command::New => "new",
+            Command::Open => "open",
+            Command::Save => "save",
+            Command::SaveAs => "save_as",
+            Command::Export(ExportFormat::Html) => "export_html",
+            Command::Export(ExportFormat::Pdf) => "export_pdf",
+            Command::ToggleReadOnly => "read_only",
+            Command::CloseTab => "close_tab",
+            Command::Exit => "exit",
+            Command::Undo => "undo",
+            Command::Redo => "redo",
+            Command::Find => "find",
+            Command::Replace => "replace",
+            Command::GoToLine => "go_to_line",
+            Command::NavigateBack => "navigate_back",
+            Command::NavigateForward => "navigate_forward",
+            Command::HardWrap => "hard_wrap",
+            Command::OpenPalette => "command_palette",
+            Command::MarkdownPreview => "markdown_preview",
+            Command::Wrap(WrapMode::None) => "wrap_none",
+            Command::Wrap(WrapMode::Window) => "wrap_window",
+            Command::Wrap(WrapMode::Column) => "wrap_column",
+            Command::Split(SplitOrientation::Horizontal) => "split_horizontal",
+            Command::Split(SplitOrientation::Vertical) => "split_vertical",
+            Command::FocusOtherPane => "focus_other_pane",
+            Command::SwapPanes => "swap_panes",
+            Command::ClosePane => "close_pane",
+            Command::ToggleTheme => "toggle_theme",
+            Command::FontSmaller => "font_smaller",
+            Command::FontLarger => "font_larger",
+            Command::FontSize(size) => font_preset(size).map_or("font_size", |(_, id, _)| id),
+            Command::ToggleLineNumbers => "line_numbers",
+            Command::ToggleWeather => "show_weather",
+            Command::ToggleMonitor => "show_monitor",
+            Command::TogglePdfHeaderFooter => "pdf_header_footer",
+            Command::ToggleRuler => "show_ruler",
+            Command::ToggleAutosave => "autosave",
+            Command::ToggleRestoreSession => "restore_session",
+            Command::ReopenWithEncoding(encoding) => return choice("reopen", &encoding),
+            Command::SaveWithEncoding(encoding) => return choice("save", &encoding),
+            Command::PdfPageSize(size) => return choice("pdf_page", &size),
+            Command::PdfFont(font) => return choice("pdf_font", &font),
+            Command::Backup(mode) => return choice("backup", &mode),
+            Command::WeatherLocation(source) => return choice("weather_location", &source),
+            Command::TemperatureUnit(unit) => return choice("temperature", &unit),
+            Command::WindUnit(unit) => return choice("wind", &unit),
+        };
+        id.to_string()
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Command::New => "New",
+            Command::Open => "Open...",
+            Command::Save => "Save",
+            Command::SaveAs => "Save As...",
+            Command::Export(ExportFormat::Html) => "Export HTML...",
+            Command::Export(ExportFormat::Pdf) => "Export PDF...",
+            Command::ToggleReadOnly => "Read Only",
+            Command::CloseTab => "Close Tab",
+            Command::Exit => "Exit",
+            Command::Undo => "Undo",
+            Command::Redo => "Redo",
+            Command::Find => "Find...",
+            Command::Replace => "Replace...",
+            Command::GoToLine => "Go to Line...",
+            Command::NavigateBack => "Back",
+            Command::NavigateForward => "Forward",
+            Command::HardWrap => "Hard Wrap Paragraph",
+            Command::OpenPalette => "Command Palette...",
+            Command::MarkdownPreview => "Markdown Preview",
+            Command::Wrap(mode) => mode.label(),
+            Command::Split(orientation) => orientation.label(),
+            Command::FocusOtherPane => "Focus Other Pane",
+            Command::SwapPanes => "Swap Panes",
+            Command::ClosePane => "Close Pane",
+            Command::ToggleTheme => "Toggle Light/Dark Theme",
+            Command::FontSmaller => "Decrease Font Size",
+            Command::FontLarger => "Increase Font Size",
+            Command::FontSize(size) => font_preset(size).map_or("Font Size", |(_, _, label)| label),
+            Command::ToggleLineNumbers => "Show Line Numbers",
+            Command::ToggleWeather => "Show Weather",
+            Command::ToggleMonitor => "Show System Monitor",
+            Command::ReopenWithEncoding(encoding) | Command::SaveWithEncoding(encoding) => {
+                encoding.label()
+            }
+            Command::PdfPageSize(size) => size.label(),
+            Command::PdfFont(font) => font.label(),
+            Command::TogglePdfHeaderFooter => "Header and Footer",
+            Command::ToggleRuler => "Show Ruler",
+            Command::Backup(mode) => mode.label(),
+            Command::ToggleAutosave => "Autosave Files",
+            Command::ToggleRestoreSession => "Restore Session on Startup",
+            Command::WeatherLocation(source) => source.label(),
+            Command::TemperatureUnit(unit) => unit.label(),
+            Command::WindUnit(unit) => unit.label(),
+        }
+    }
+
+    /// Submenu of [`Command::menu`] the command lives in, if any
+    pub fn submenu(self) -> Option<&'static str> {
+        match self {
+            Command::ReopenWithEncoding(_) => Some("Reopen with Encoding"),
+            Command::SaveWithEncoding(_) => Some("Save with Encoding"),
+            Command::PdfPageSize(_) | Command::PdfFont(_) | Command::TogglePdfHeaderFooter => {
+                Some("PDF Page Setup")
+            }
+            Command::FontSize(_) => Some("Presets"),
+            Command::Backup(_) => Some("Backups on Save"),
+            Command::WeatherLocation(_) => Some("Weather Location"),
+            Command::TemperatureUnit(_) => Some("Weather Units"),
+            Command::WindUnit(_) => Some("Wind Speed"),
+            _ => None,
+        }
+    }
+
+    /// Menu path and label, as listed in the command palette
+    pub fn title(self) -> String {
+        match self.submenu() {
+            Some(submenu) => format!("{}: {}: {}", self.menu(), submenu, self.label()),
+            None => format!("{}: {}", self.menu(), self.label()),
+        }
+    }
+
+    /// Menu the command lives in, shown as a prefix in the command palette
+    pub fn menu(self) -> &'static str {
+        match self {
+            Command::New
+            | Command::Open
+            | Command::Save
+            | Command::SaveAs
+            | Command::ReopenWithEncoding(_)
+            | Command::SaveWithEncoding(_)
+            | Command::Export(_)
+            | Command::PdfPageSize(_)
+            | Command::PdfFont(_)
+            | Command::TogglePdfHeaderFooter
+            | Command::ToggleReadOnly
+            | Command::CloseTab
+            | Command::Exit => "File",
+            Command::Undo
+            | Command::Redo
+            | Command::Find
+            | Command::Replace
+            | Command::GoToLine
+            | Command::NavigateBack
+            | Command::NavigateForward => "Edit",
+            Command::OpenPalette
+            | Command::MarkdownPreview
+            | Command::Wrap(_)
+            | Command::ToggleRuler
+            | Command::Split(_)
+            | Command::FocusOtherPane
+            | Command::SwapPanes
+            | Command::ClosePane
+            | Command::HardWrap
+            | Command::ToggleTheme => "View",
+            Command::FontSmaller
+            | Command::FontLarger
+            | Command::FontSize(_)
+            | Command::ToggleLineNumbers
+            | Command::Backup(_)
+            | Command::ToggleAutosave
+            | Command::ToggleRestoreSession
+            | Command::ToggleWeather
+            | Command::WeatherLocation(_)
+            | Command::TemperatureUnit(_)
+            | Command::WindUnit(_)
+            | Command::ToggleMonitor => "Settings",
+        }
+    }
+
+    /// Default key binding, with alternatives separated by commas
+    pub fn default_shortcut(self) -> Option<&'static str> {
+        match self {
+            Command::New => Some("Ctrl+N"),
+            Command::Open => Some("Ctrl+O"),
+            Command::Save => Some("Ctrl+S"),
+            Command::SaveAs => Some("Ctrl+Shift+S"),
+            Command::CloseTab => Some("Ctrl+W"),
+            Command::Undo => Some("Ctrl+Z"),
+            Command::Redo => Some("Ctrl+Shift+Z, Ctrl+Y"),
+            Command::Find => Some("Ctrl+F"),
+            Command::Replace => Some("Ctrl+H"),
+            Command::GoToLine => Some("Ctrl+G"),
+            Command::NavigateBack => Some("Alt+ArrowLeft"),
+            Command::NavigateForward => Some("Alt+ArrowRight"),
+            Command::HardWrap => Some("Alt+Q"),
+            Command::OpenPalette => Some("Ctrl+Shift+P"),
+            Command::MarkdownPreview => Some("Ctrl+Shift+M"),
+            Command::Split(SplitOrientation::Horizontal) => Some("Ctrl+Alt+Backslash"),
+            Command::Split(SplitOrientation::Vertical) => Some("Ctrl+Backslash"),
+            Command::FocusOtherPane => Some("F6"),
+            Command::SwapPanes => Some("Shift+F6"),
+            Command::ClosePane => Some("Ctrl+Shift+W"),
+            _ => None,
+        }
+    }
+}
+
+/// State of the Ctrl+Shift+P command palette
+#[derive(Debug, Default)]
+pub struct CommandPaletteState {
+    pub open: bool,
+    pub query: String,
+    /// Index into the current matches of the highlighted entry
+    pub selected: usize,
+    /// Give the search field keyboard focus on the next frame
+    pub focus_input: bool,
+}
+
+/// Scores `text` against a fuzzy `query` whose characters must all appear
+/// in order, ignoring case. Matches at the start of words and runs of
+/// adjacent characters score higher. `None` when `text` does not match.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let mut chars = text.char_indices().peekable();
+    let mut before = ' ';
+
+    for wanted in query.chars().filter(|c| !c.is_whitespace()) {
+        let wanted = wanted.to_lowercase().next().unwrap_or(wanted);
+        loop {
+            let (i, c) = chars.next()?;
+            let at_word_start = !before.is_alphanumeric();
+            before = c;
+            if c.to_lowercase().next() != Some(wanted) {
+                continue;
+            }
+            score += 1;
+            if at_word_start {
+                score += 8;
+            }
+            match previous {
+                Some(p) if p + 1 == i => score += 4,
+                Some(p) => score -= ((i - p) as i32).min(4),
+                None => score -= (i as i32).min(4),
+            }
+            previous = Some(i + c.len_utf8() - 1);
+            break;
+        }
+    }
+    Some(score)
+}
+
+/// Commands matching `query`, best first. An empty query lists every
+/// command in registry order.
+pub fn search(query: &str, commands: impl Iterator<Item = Command>) -> Vec<Command> {
+    let mut scored: Vec<(i32, Command)> = commands
+        .filter_map(|command| fuzzy_score(query, &command.title()).map(|score| (score, command)))
+        .collect();
+    // Stable, so equal scores keep registry order
+    scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    scored.into_iter().map(|(_, command)| command).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_score_needs_every_character_in_order() {
+        assert!(fuzzy_score("sv", "Save").is_some());
+        assert!(fuzzy_score("SAVE", "save as").is_some());
+        assert_eq!(fuzzy_score("vs", "Save"), None);
+        assert_eq!(fuzzy_score("saves", "Save"), None);
+        assert_eq!(fuzzy_score("", "Save"), Some(0));
+        assert_eq!(fuzzy_score("s a", "Save As"), fuzzy_score("sa", "Save As"));
+    }
+
+    #[test]
+    fn word_starts_and_runs_score_higher() {
+        let word_starts = fuzzy_score("gl", "Go to Line").unwrap();
+        let middle = fuzzy_score("gl", "Angle").unwrap();
+        assert!(word_starts > middle);
+
+        let run = fuzzy_score("ine", "Line").unwrap();
+        let scattered = fuzzy_score("ine", "Lxixnxe").unwrap();
+        assert!(run > scattered);
+    }
+
+    #[test]
+    fn search_ranks_the_best_match_first() {
+        let results = search("undo", Command::ALL.iter().copied());
+        assert_eq!(results.first(), Some(&Command::Undo));
+        assert!(!results.contains(&Command::Save));
+
+        let results = search("font size", Command::ALL.iter().copied());
+        assert!(results.contains(&Command::FontSmaller));
+        assert!(results.contains(&Command::FontLarger));
+    }
+
+    #[test]
+    fn empty_query_lists_every_command_in_order() {
+        let results = search("", Command::ALL.iter().copied());
+        assert_eq!(results, Command::ALL);
+    }
+
+    #[test]
+    fn command_ids_are_unique() {
+        let mut ids: Vec<String> = Command::ALL.iter().map(|c| c.id()).collect();
+        ids.sort_unstable();
+        let len = ids.len();
+        ids.dedup();
+        assert_eq!(ids.len(), len);
+    }
+
+    #[test]
+    fn every_menu_choice_is_registered() {
+        let registered = |command: Command| Command::ALL.contains(&command);
+        assert!(TextEncoding::ALL
+            .iter()
+            .all(|&e| registered(Command::ReopenWithEncoding(e))));
+        assert!(TextEncoding::ALL
+            .iter()
+            .all(|&e| registered(Command::SaveWithEncoding(e))));
+        assert!(PageSize::ALL
+            .iter()
+            .all(|&s| registered(Command::PdfPageSize(s))));
+        assert!(PdfFont::ALL
+            .iter()
+            .all(|&f| registered(Command::PdfFont(f))));
+        assert!(BackupMode::ALL
+            .iter()
+            .all(|&m| registered(Command::Backup(m))));
+        assert!(LocationSource::ALL
+            .iter()
+            .all(|&s| registered(Command::WeatherLocation(s))));
+        assert!(TemperatureUnit::ALL
+            .iter()
+            .all(|&u| registered(Command::TemperatureUnit(u))));
+        assert!(WindUnit::ALL
+            .iter()
+            .all(|&u| registered(Command::WindUnit(u))));
+        assert!(WrapMode::ALL.iter().all(|&m| registered(Command::Wrap(m))));
+        assert!(SplitOrientation::ALL
+            .iter()
+            .all(|&o| registered(Command::Split(o))));
+        assert!(FONT_PRESETS
+            .iter()
+            .all(|&(size, _, _)| registered(Command::FontSize(size))));
+    }
+
+    #[test]
+    fn choice_ids_follow_the_variant_name() {
+        assert_eq!(
+            Command::SaveWithEncoding(TextEncoding::Utf16Le).id(),
+            "save_utf16le"
+        );
+        assert_eq!(Command::Backup(BackupMode::Tilde).id(), "backup_tilde");
+        assert_eq!(
+            Command::SaveWithEncoding(TextEncoding::Utf8).title(),
+            "File: Save with Encoding: UTF-8"
+        );
+    }
+}
@@ -0,0 +1,39 @@
+use similar::{ChangeTag, TextDiff};
+
+/// A line-by-line comparison of two versions of a document, ready to show
+/// in the diff window
+pub struct DiffView {
+    pub title: String,
+    pub old_label: String,
+    pub new_label: String,
+    pub lines: Vec<(ChangeTag, String)>,
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl DiffView {
+    pub fn new(title: String, old_label: String, old: &str, new_label: String, new: &str) -> Self {
+        let diff = TextDiff::from_lines(old, new);
+        let mut lines = Vec::new();
+        let (mut added, mut removed) = (0, 0);
+
+        for change in diff.iter_all_changes() {
+            match change.tag() {
+                ChangeTag::Insert => added += 1,
+                ChangeTag::Delete => removed += 1,
+                ChangeTag::Equal => {}
+            }
+            let line = change.value().trim_end_matches('\n').to_string();
+            lines.push((change.tag(), line));
+        }
+
+        Self {
+            title,
+            old_label,
+            new_label,
+            lines,
+            added,
+            removed,
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use eframe::egui;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::encoding::TextEncoding;
+use crate::file_watch::{DiskState, ExternalChange};
+use crate::history::EditHistory;
+use crate::language::Language;
+use crate::line_ending::{self, LineEnding};
+use crate::split::Split;
+use crate::syntax::Highlighter;
+
+static NEXT_DOCUMENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A single open buffer, shown as one tab in the editor
+pub struct Document {
+    pub id: u64,
+    pub text: String,
+    pub file_path: Option<PathBuf>,
+    pub dirty: bool,
+    pub read_only: bool,
+    pub encoding: TextEncoding,
+    pub line_ending: LineEnding,
+    /// The file on disk mixed several line ending styles
+    pub mixed_line_endings: bool,
+    pub scroll_offset: egui::Vec2,
+    /// Caret position as a char index, as of the last frame
+    pub cursor: usize,
+    /// 1-based line and column of the caret, as of the last frame
+    pub cursor_line: usize,
+    pub cursor_column: usize,
+    /// Number of selected chars, as of the last frame
+    pub selection_len: usize,
+    pub history: EditHistory,
+    /// Bumped on every change to `text`, so caches can tell when to rebuild
+    pub revision: u64,
+    /// Char index the editor should scroll into view on the next frame
+    pub scroll_to_char: Option<usize>,
+    /// The file as last opened or saved, to notice changes by other programs
+    pub disk_state: Option<DiskState>,
+    /// A change on disk the user has not dealt with yet
+    pub external_change: Option<ExternalChange>,
+    /// Language picked by hand, overriding the one guessed from the file
+    /// extension
+    pub language_override: Option<Language>,
+    pub highlighter: Highlighter,
+    /// A second editor pane on this document, if the view is split
+    pub split: Option<Split>,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_DOCUMENT_ID.fetch_add(1, Ordering::Relaxed),
+            text: String::new(),
+            file_path: None,
+            dirty: false,
+            read_only: false,
+            encoding: TextEncoding::Utf8,
+            line_ending: LineEnding::native(),
+            mixed_line_endings: false,
+            scroll_offset: egui::Vec2::ZERO,
+            cursor: 0,
+            cursor_line: 1,
+            cursor_column: 1,
+            selection_len: 0,
+            history: EditHistory::new(""),
+            revision: 0,
+            scroll_to_char: None,
+            disk_state: None,
+            external_change: None,
+            language_override: None,
+            highlighter: Highlighter::default(),
+            split: None,
+        }
+    }
+
+    /// A document for a file's decoded contents. Line endings are
+    /// normalized to `\n` and the original style is remembered for saving.
+    pub fn from_file(path: PathBuf, text: String, encoding: TextEncoding) -> Self {
+        let (line_ending, mixed_line_endings) = line_ending::detect(&text);
+        let text = line_ending::normalize(&text).into_owned();
+        Self {
+            history: EditHistory::new(&text),
+            text,
+            file_path: Some(path),
+            encoding,
+            line_ending,
+            mixed_line_endings,
+            ..Self::new()
+        }
+    }
+
+    /// An untitled buffer holding text that has not been saved anywhere yet
+    pub fn from_unsaved_text(text: String, encoding: TextEncoding) -> Self {
+        let (line_ending, _) = line_ending::detect(&text);
+        let text = line_ending::normalize(&text).into_owned();
+        let mut history = EditHistory::new(&text);
+        history.mark_unsaved();
+        Self {
+            history,
+            text,
+            dirty: true,
+            encoding,
+            line_ending,
+            ..Self::new()
+        }
+    }
+
+    pub fn language(&self) -> Language {
+        self.language_override
+            .unwrap_or_else(|| Language::from_path(self.file_path.as_deref()))
+    }
+
+    /// Name shown in the tab strip and dialogs
+    pub fn title(&self) -> String {
+        self.file_path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Untitled".to_string())
+    }
+
+    /// An untitled, empty, unmodified buffer that can be replaced on open
+    pub fn is_pristine(&self) -> bool {
+        self.file_path.is_none() && self.text.is_empty() && !self.dirty
+    }
+
+    /// The editor pane with the keyboard focus, or the only one
+    pub fn editor_id(&self) -> egui::Id {
+        self.pane_editor_id(self.focused_pane())
+    }
+
+    pub fn pane_editor_id(&self, pane: usize) -> egui::Id {
+        let id = egui::Id::new(("text_editor", self.id));
+        if pane == 0 {
+            id
+        } else {
+            id.with(pane)
+        }
+    }
+
+    pub fn focused_pane(&self) -> usize {
+        self.split.as_ref().map_or(0, |split| split.focused)
+    }
+
+    /// Feeds the latest buffer contents into the undo history
+    pub fn record_edit(&mut self) {
+        // Pasted text may carry its own line endings
+        if self.text.contains('\r') {
+            self.text = line_ending::normalize(&self.text).into_owned();
+        }
+        self.history.record(&self.text);
+        self.revision += 1;
+        self.dirty = !self.history.is_at_saved();
+    }
+
+    /// Replaces the whole buffer as a single undoable step
+    pub fn replace_text(&mut self, label: &str, text: String) {
+        self.text = text;
+        self.history.record_step(label, &self.text);
+        self.revision += 1;
+        self.dirty = !self.history.is_at_saved();
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.history.mark_saved();
+        self.dirty = false;
+        self.mixed_line_endings = false;
+    }
+
+    /// Switches the style used on save. The text is unchanged, so the
+    /// document stays dirty until it is saved.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if self.line_ending != line_ending || self.mixed_line_endings {
+            self.line_ending = line_ending;
+            self.mixed_line_endings = false;
+            self.history.mark_unsaved();
+            self.dirty = true;
+        }
+    }
+
+    pub fn undo(&mut self) -> Option<usize> {
+        if self.read_only {
+            return None;
+        }
+        let cursor = self.history.undo(&mut self.text)?;
+        self.revision += 1;
+        self.dirty = !self.history.is_at_saved();
+        Some(cursor)
+    }
+
+    pub fn redo(&mut self) -> Option<usize> {
+        if self.read_only {
+            return None;
+        }
+        let cursor = self.history.redo(&mut self.text)?;
+        self.revision += 1;
+        self.dirty = !self.history.is_at_saved();
+        Some(cursor)
+    }
+
+    /// Char index of a 1-based line and column, clamped to the document
+    pub fn char_index_at(&self, line: usize, column: usize) -> usize {
+        let mut index = 0;
+        for (i, text) in self.text.split('\n').enumerate() {
+            let len = text.chars().count();
+            if i + 1 == line.max(1) {
+                return index + column.saturating_sub(1).min(len);
+            }
+            index += len + 1;
+        }
+        self.text.chars().count()
+    }
+
+    /// Current selection as (start, end) char indices, if the editor has one
+    pub fn selection(&self, ctx: &egui::Context) -> Option<(usize, usize)> {
+        let state = egui::TextEdit::load_state(ctx, self.editor_id())?;
+        let range = state.cursor.char_range()?;
+        let (a, b) = (range.primary.index, range.secondary.index);
+        Some((a.min(b), a.max(b)))
+    }
+
+    /// Selects the chars from `start` to `end` and scrolls them into view
+    /// without moving keyboard focus.
+    pub fn select_chars(&mut self, ctx: &egui::Context, start: usize, end: usize) {
+        let id = self.editor_id();
+        let mut state = egui::TextEdit::load_state(ctx, id).unwrap_or_default();
+        state
+            .cursor
+            .set_char_range(Some(egui::text::CCursorRange::two(
+                egui::text::CCursor::new(start),
+                egui::text::CCursor::new(end),
+            )));
+        state.store(ctx, id);
+        self.scroll_to_char = Some(end);
+    }
+
+    /// Moves the editor caret to the given char index and focuses the editor
+    pub fn set_cursor(&mut self, ctx: &egui::Context, char_index: usize) {
+        self.select_chars(ctx, char_index, char_index);
+        ctx.memory_mut(|m| m.request_focus(self.editor_id()));
+    }
+}
@@ -0,0 +1,238 @@
+use encoding_rs::Encoding;
+
+/// Text encodings a document can be read from and written back to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Windows1252,
+    Iso8859_15,
+    Windows1250,
+    Windows1251,
+    ShiftJis,
+    Gbk,
+}
+
+impl TextEncoding {
+    /// Every encoding offered in the Reopen/Save with Encoding menus
+    pub const ALL: &'static [TextEncoding] = &[
+        TextEncoding::Utf8,
+        TextEncoding::Utf8Bom,
+        TextEncoding::Utf16Le,
+        TextEncoding::Utf16Be,
+        TextEncoding::Latin1,
+        TextEncoding::Windows1252,
+        TextEncoding::Iso8859_15,
+        TextEncoding::Windows1250,
+        TextEncoding::Windows1251,
+        TextEncoding::ShiftJis,
+        TextEncoding::Gbk,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf8Bom => "UTF-8 with BOM",
+            TextEncoding::Utf16Le => "UTF-16 LE",
+            TextEncoding::Utf16Be => "UTF-16 BE",
+            TextEncoding::Latin1 => "ISO-8859-1",
+            TextEncoding::Windows1252 => "Windows-1252",
+            TextEncoding::Iso8859_15 => "ISO-8859-15",
+            TextEncoding::Windows1250 => "Windows-1250",
+            TextEncoding::Windows1251 => "Windows-1251",
+            TextEncoding::ShiftJis => "Shift_JIS",
+            TextEncoding::Gbk => "GBK",
+        }
+    }
+
+    fn bom(self) -> &'static [u8] {
+        match self {
+            TextEncoding::Utf8Bom => &[0xEF, 0xBB, 0xBF],
+            TextEncoding::Utf16Le => &[0xFF, 0xFE],
+            TextEncoding::Utf16Be => &[0xFE, 0xFF],
+            _ => &[],
+        }
+    }
+
+    /// The `encoding_rs` codec, for everything except Latin-1 which
+    /// `encoding_rs` treats as an alias of Windows-1252
+    fn codec(self) -> Option<&'static Encoding> {
+        match self {
+            TextEncoding::Utf8 | TextEncoding::Utf8Bom => Some(encoding_rs::UTF_8),
+            TextEncoding::Utf16Le => Some(encoding_rs::UTF_16LE),
+            TextEncoding::Utf16Be => Some(encoding_rs::UTF_16BE),
+            TextEncoding::Latin1 => None,
+            TextEncoding::Windows1252 => Some(encoding_rs::WINDOWS_1252),
+            TextEncoding::Iso8859_15 => Some(encoding_rs::ISO_8859_15),
+            TextEncoding::Windows1250 => Some(encoding_rs::WINDOWS_1250),
+            TextEncoding::Windows1251 => Some(encoding_rs::WINDOWS_1251),
+            TextEncoding::ShiftJis => Some(encoding_rs::SHIFT_JIS),
+            TextEncoding::Gbk => Some(encoding_rs::GBK),
+        }
+    }
+}
+
+/// Guesses the encoding of a file's raw bytes: a BOM wins, then UTF-16
+/// without a BOM (lots of NUL bytes on one side), then UTF-8, and anything
+/// else is assumed to be Windows-1252.
+pub fn detect(bytes: &[u8]) -> TextEncoding {
+    for encoding in [
+        TextEncoding::Utf8Bom,
+        TextEncoding::Utf16Le,
+        TextEncoding::Utf16Be,
+    ] {
+        if bytes.starts_with(encoding.bom()) {
+            return encoding;
+        }
+    }
+
+    if bytes.len() >= 4 && bytes.len() % 2 == 0 {
+        let pairs = bytes.len() / 2;
+        let even_nuls = bytes.iter().step_by(2).filter(|&&b| b == 0).count();
+        let odd_nuls = bytes.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+        if odd_nuls * 3 > pairs && even_nuls == 0 {
+            return TextEncoding::Utf16Le;
+        }
+        if even_nuls * 3 > pairs && odd_nuls == 0 {
+            return TextEncoding::Utf16Be;
+        }
+    }
+
+    if std::str::from_utf8(bytes).is_ok() {
+        TextEncoding::Utf8
+    } else {
+        TextEncoding::Windows1252
+    }
+}
+
+/// Decodes `bytes` as `encoding`, skipping its BOM if present. The flag is
+/// set when malformed sequences had to be replaced.
+pub fn decode(bytes: &[u8], encoding: TextEncoding) -> (String, bool) {
+    let bytes = bytes.strip_prefix(encoding.bom()).unwrap_or(bytes);
+    match encoding.codec() {
+        Some(codec) => {
+            let (text, had_errors) = codec.decode_without_bom_handling(bytes);
+            (text.into_owned(), had_errors)
+        }
+        None => (bytes.iter().map(|&b| b as char).collect(), false),
+    }
+}
+
+/// Encodes `text` as `encoding`, including its BOM. Fails with the first
+/// character the encoding cannot represent.
+pub fn encode(text: &str, encoding: TextEncoding) -> Result<Vec<u8>, char> {
+    let mut bytes = encoding.bom().to_vec();
+
+    match encoding {
+        TextEncoding::Utf8 | TextEncoding::Utf8Bom => bytes.extend_from_slice(text.as_bytes()),
+        // encoding_rs only decodes UTF-16, so encode it by hand
+        TextEncoding::Utf16Le => bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes)),
+        TextEncoding::Utf16Be => bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes)),
+        TextEncoding::Latin1 => {
+            for c in text.chars() {
+                bytes.push(u8::try_from(u32::from(c)).map_err(|_| c)?);
+            }
+        }
+        _ => {
+            let codec = encoding.codec().unwrap_or(encoding_rs::UTF_8);
+            let (encoded, _, had_errors) = codec.encode(text);
+            if had_errors {
+                let unmappable = text
+                    .chars()
+                    .find(|c| codec.encode(c.encode_utf8(&mut [0; 4])).2)
+                    .unwrap_or(char::REPLACEMENT_CHARACTER);
+                return Err(unmappable);
+            }
+            bytes.extend_from_slice(&encoded);
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_encoding_round_trips() {
+        let samples = [
+            (TextEncoding::Utf8, "héllo 世界 🦀"),
+            (TextEncoding::Utf8Bom, "héllo 世界 🦀"),
+            (TextEncoding::Utf16Le, "héllo 世界 🦀"),
+            (TextEncoding::Utf16Be, "héllo 世界 🦀"),
+            (TextEncoding::Latin1, "café ÿ\u{80}"),
+            (TextEncoding::Windows1252, "€ café"),
+            (TextEncoding::Iso8859_15, "€ œuvre"),
+            (TextEncoding::Windows1250, "Łódź"),
+            (TextEncoding::Windows1251, "Привет"),
+            (TextEncoding::ShiftJis, "日本語"),
+            (TextEncoding::Gbk, "中文"),
+        ];
+        assert_eq!(samples.len(), TextEncoding::ALL.len());
+        for (encoding, text) in samples {
+            let bytes = encode(text, encoding).unwrap();
+            assert_eq!(
+                decode(&bytes, encoding),
+                (text.to_string(), false),
+                "{}",
+                encoding.label()
+            );
+        }
+    }
+
+    #[test]
+    fn bom_decides_the_encoding() {
+        for encoding in [
+            TextEncoding::Utf8Bom,
+            TextEncoding::Utf16Le,
+            TextEncoding::Utf16Be,
+        ] {
+            let bytes = encode("hi", encoding).unwrap();
+            assert!(bytes.starts_with(encoding.bom()));
+            assert_eq!(detect(&bytes), encoding);
+        }
+    }
+
+    #[test]
+    fn utf16_without_bom_is_detected_from_nul_bytes() {
+        let le: Vec<u8> = "hello".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = "hello".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(detect(&le), TextEncoding::Utf16Le);
+        assert_eq!(detect(&be), TextEncoding::Utf16Be);
+    }
+
+    #[test]
+    fn detection_edge_cases() {
+        assert_eq!(detect(b""), TextEncoding::Utf8);
+        // Too short, or an odd length, is never taken for UTF-16
+        assert_eq!(detect(b"a\0"), TextEncoding::Utf8);
+        assert_eq!(detect(b"a\0b\0c"), TextEncoding::Utf8);
+        assert_eq!(detect(b"caf\xe9"), TextEncoding::Windows1252);
+        assert_eq!(detect(b"\xff\xfe"), TextEncoding::Utf16Le);
+    }
+
+    #[test]
+    fn invalid_sequences_are_flagged() {
+        let (text, had_errors) = decode(b"caf\xe9", TextEncoding::Utf8);
+        assert_eq!(text, "caf\u{FFFD}");
+        assert!(had_errors);
+    }
+
+    #[test]
+    fn latin1_maps_bytes_to_code_points() {
+        // Windows-1252 would read 0x80 as the euro sign
+        assert_eq!(decode(b"\x80", TextEncoding::Latin1).0, "\u{80}");
+        assert_eq!(decode(b"\x80", TextEncoding::Windows1252).0, "€");
+    }
+
+    #[test]
+    fn unmappable_characters_are_reported() {
+        assert_eq!(encode("price: €5", TextEncoding::Latin1), Err('€'));
+        assert_eq!(encode("a世b", TextEncoding::Windows1252), Err('世'));
+        assert_eq!(encode("Ω", TextEncoding::Windows1251), Err('Ω'));
+    }
+}
@@ -0,0 +1,513 @@
+use chrono::Local;
+use eframe::egui::Color32;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Read, Write as _};
+use std::path::{Path, PathBuf};
+
+use crate::atomic_file::{self, BackupMode};
+use crate::cli::{CliArgs, FileSource};
+use crate::encoding;
+use crate::language::Language;
+use crate::line_ending;
+use crate::markdown::{self, Alignment, Block, BlockKind, Inline, ListItem};
+use crate::pdf;
+use crate::settings::{PdfSettings, Settings};
+use crate::syntax::{Highlighter, Token};
+use crate::theme::{self, CatppuccinPalette};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Html,
+    Pdf,
+}
+
+impl ExportFormat {
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Html => "HTML",
+            ExportFormat::Pdf => "PDF",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Html => "html",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "html" | "htm" => Some(ExportFormat::Html),
+            "pdf" => Some(ExportFormat::Pdf),
+            _ => None,
+        }
+    }
+}
+
+/// The exported file for a document's text
+pub fn render(
+    format: ExportFormat,
+    title: &str,
+    text: &str,
+    language: Language,
+    palette: &CatppuccinPalette,
+    pdf_settings: &PdfSettings,
+) -> Vec<u8> {
+    match format {
+        ExportFormat::Html => html(title, text, language, palette).into_bytes(),
+        ExportFormat::Pdf => {
+            let date = Local::now().format("%Y-%m-%d %H:%M").to_string();
+            pdf::render(title, &date, text, pdf_settings)
+        }
+    }
+}
+
+/// Where an export of `source` goes unless the user picks another place.
+/// Never `source` itself: exporting `page.html` to HTML suggests
+/// `page.export.html`.
+pub fn default_path(source: &Path, format: ExportFormat) -> PathBuf {
+    let path = source.with_extension(format.extension());
+    if path == source {
+        source.with_extension(format!("export.{}", format.extension()))
+    } else {
+        path
+    }
+}
+
+/// Whether `a` and `b` name the same file, following links where they exist
+pub(crate) fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Exports every file named on the command line without opening a window.
+/// Returns the process exit code.
+pub fn run_cli(format: ExportFormat, args: &CliArgs, settings: &Settings) -> i32 {
+    let theme = args.theme.unwrap_or(settings.theme);
+    let palette = theme::palette(theme.is_dark());
+    let mut exit_code = 0;
+
+    for file in &args.files {
+        let (path, bytes) = match &file.source {
+            FileSource::Stdin => {
+                let mut bytes = Vec::new();
+                (None, io::stdin().read_to_end(&mut bytes).map(|_| bytes))
+            }
+            FileSource::Path(path) => (Some(path.as_path()), fs::read(path)),
+        };
+        let name = path.map_or("stdin".to_string(), |p| p.display().to_string());
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("rusty-notepad: {}: {}", name, e);
+                exit_code = 1;
+                continue;
+            }
+        };
+
+        let (text, _) = encoding::decode(&bytes, encoding::detect(&bytes));
+        let text = line_ending::normalize(&text);
+        let title = path
+            .and_then(|p| p.file_name())
+            .map_or("stdin".to_string(), |n| n.to_string_lossy().into_owned());
+        let output = render(
+            format,
+            &title,
+            &text,
+            Language::from_path(path),
+            &palette,
+            &settings.pdf,
+        );
+
+        let target = args
+            .output
+            .clone()
+            .or_else(|| path.map(|p| default_path(p, format)));
+        let result = match target.as_deref() {
+            Some(target) if path.is_some_and(|source| same_file(source, target)) => Err(format!(
+                "{}: refusing to overwrite the file being exported",
+                target.display()
+            )),
+            Some(target) if target != Path::new("-") => {
+                atomic_file::write(target, &output, BackupMode::None)
+                    .map(|_| eprintln!("Exported {} to {}", name, target.display()))
+                    .map_err(|e| e.to_string())
+            }
+            _ => io::stdout().write_all(&output).map_err(|e| e.to_string()),
+        };
+        if let Err(e) = result {
+            eprintln!("rusty-notepad: {}", e);
+            exit_code = 1;
+        }
+    }
+
+    exit_code
+}
+
+fn css_color(color: Color32) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b())
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+fn stylesheet(palette: &CatppuccinPalette) -> String {
+    let c = css_color;
+    let mut css = format!(
+        "body {{ margin: 0; background: {base}; color: {text}; \
+         font-family: system-ui, -apple-system, \"Segoe UI\", sans-serif; line-height: 1.6; }}
+main {{ max-width: 50rem; margin: 0 auto; padding: 2rem 1.5rem; }}
+h1, h2 {{ border-bottom: 1px solid {surface1}; padding-bottom: 0.2em; }}
+h1 {{ color: {red}; }} h2 {{ color: {peach}; }} h3 {{ color: {yellow}; }}
+h4 {{ color: {green}; }} h5 {{ color: {sapphire}; }} h6 {{ color: {mauve}; }}
+a {{ color: {sapphire}; }}
+strong {{ color: {peach}; }}
+code, pre {{ font-family: ui-monospace, Menlo, Consolas, monospace; font-size: 0.9em; }}
+code {{ background: {surface0}; color: {green}; padding: 0.1em 0.3em; border-radius: 4px; }}
+pre {{ background: {mantle}; padding: 1rem; border-radius: 6px; overflow-x: auto; }}
+pre code {{ background: none; color: inherit; padding: 0; }}
+pre.source {{ background: none; padding: 0; white-space: pre-wrap; }}
+blockquote {{ margin: 0 0 1em; padding-left: 1rem; border-left: 3px solid {overlay0}; color: {overlay0}; }}
+table {{ border-collapse: collapse; margin-bottom: 1em; }}
+th, td {{ border: 1px solid {surface1}; padding: 0.3em 0.7em; }}
+th {{ background: {mantle}; color: {blue}; }}
+tr:nth-child(even) td {{ background: {mantle}; }}
+hr {{ border: none; border-top: 1px solid {surface1}; }}
+li::marker {{ color: {teal}; }}
+li.task {{ list-style: none; }}
+li.task input {{ margin: 0 0.5em 0 -1.3em; }}
+",
+        base = c(palette.base),
+        text = c(palette.text),
+        surface0 = c(palette.surface0),
+        surface1 = c(palette.surface1),
+        mantle = c(palette.mantle),
+        red = c(palette.red),
+        peach = c(palette.peach),
+        yellow = c(palette.yellow),
+        green = c(palette.green),
+        sapphire = c(palette.sapphire),
+        mauve = c(palette.mauve),
+        blue = c(palette.blue),
+        teal = c(palette.teal),
+        overlay0 = c(palette.overlay0),
+    );
+    for &token in Token::ALL {
+        let style = if token.is_italic() {
+            "; font-style: italic"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            css,
+            ".tk-{} {{ color: {}{} }}",
+            token.name(),
+            c(token.color(palette)),
+            style
+        );
+    }
+    css
+}
+
+/// A standalone HTML page for a document, styled with `palette`. Markdown
+/// is rendered; anything else is shown as highlighted source.
+pub fn html(title: &str, text: &str, language: Language, palette: &CatppuccinPalette) -> String {
+    let body = if language == Language::Markdown {
+        let mut body = String::new();
+        blocks_html(&markdown::parse(text), &mut body);
+        body
+    } else {
+        format!(
+            "<pre class=\"source\"><code>{}</code></pre>\n",
+            highlighted(text, language)
+        )
+    };
+
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<meta name=\"generator\" content=\"Rusty Notepad\">
+<title>{}</title>
+<style>
+{}</style>
+</head>
+<body>
+<main>
+{}</main>
+</body>
+</html>
+",
+        escape(title),
+        stylesheet(palette),
+        body
+    )
+}
+
+/// `code` with each highlighted token wrapped in a classed `<span>`
+fn highlighted(code: &str, language: Language) -> String {
+    let mut highlighter = Highlighter::default();
+    let mut out = String::with_capacity(code.len());
+    let mut last = 0;
+    for (range, token) in highlighter.highlight(language, code) {
+        out.push_str(&escape(&code[last..range.start]));
+        let _ = write!(
+            out,
+            "<span class=\"tk-{}\">{}</span>",
+            token.name(),
+            escape(&code[range.clone()])
+        );
+        last = range.end;
+    }
+    out.push_str(&escape(&code[last..]));
+    out
+}
+
+fn blocks_html(blocks: &[Block], out: &mut String) {
+    for block in blocks {
+        match &block.kind {
+            BlockKind::Heading { level, content } => {
+                let _ = writeln!(out, "<h{0}>{1}</h{0}>", level, inline_html(content));
+            }
+            BlockKind::Paragraph(content) => {
+                let _ = writeln!(out, "<p>{}</p>", inline_html(content));
+            }
+            BlockKind::List(items) => list_html(items, out),
+            BlockKind::Code { info, code } => {
+                let language = info.split_whitespace().next().unwrap_or("");
+                let class = if language.is_empty() {
+                    String::new()
+                } else {
+                    format!(" class=\"language-{}\"", escape(language))
+                };
+                let _ = writeln!(
+                    out,
+                    "<pre><code{}>{}</code></pre>",
+                    class,
+                    highlighted(code, Language::from_name(language))
+                );
+            }
+            BlockKind::Table {
+                alignments,
+                header,
+                rows,
+            } => {
+                let cells = |row: &[Vec<Inline>], tag: &str| -> String {
+                    row.iter()
+                        .zip(alignments)
+                        .map(|(cell, alignment)| {
+                            let align = match alignment {
+                                Alignment::Left => "",
+                                Alignment::Center => " style=\"text-align: center\"",
+                                Alignment::Right => " style=\"text-align: right\"",
+                            };
+                            format!("<{tag}{align}>{}</{tag}>", inline_html(cell))
+                        })
+                        .collect()
+                };
+                let _ = writeln!(
+                    out,
+                    "<table>\n<thead><tr>{}</tr></thead>\n<tbody>",
+                    cells(header, "th")
+                );
+                for row in rows {
+                    let _ = writeln!(out, "<tr>{}</tr>", cells(row, "td"));
+                }
+                out.push_str("</tbody>\n</table>\n");
+            }
+            BlockKind::Quote(blocks) => {
+                out.push_str("<blockquote>\n");
+                blocks_html(blocks, out);
+                out.push_str("</blockquote>\n");
+            }
+            BlockKind::Rule => out.push_str("<hr>\n"),
+        }
+    }
+}
+
+/// Nested `<ul>`/`<ol>` elements for a flat list of items with depths
+fn list_html(items: &[ListItem], out: &mut String) {
+    // The tag of each open list; each has an open `<li>` once it has items
+    let mut open: Vec<&str> = Vec::new();
+    for item in items {
+        let depth = item.depth.min(open.len());
+        while open.len() > depth + 1 {
+            let tag = open.pop().unwrap_or("ul");
+            let _ = write!(out, "</li>\n</{}>\n", tag);
+        }
+        if open.len() == depth + 1 {
+            out.push_str("</li>\n");
+        } else {
+            match item.number {
+                Some(1) => out.push_str("<ol>\n"),
+                Some(start) => {
+                    let _ = writeln!(out, "<ol start=\"{}\">", start);
+                }
+                None => out.push_str("<ul>\n"),
+            }
+            open.push(if item.number.is_some() { "ol" } else { "ul" });
+        }
+
+        match item.task {
+            Some(task) => {
+                let checked = if task.checked { " checked" } else { "" };
+                let _ = write!(
+                    out,
+                    "<li class=\"task\"><input type=\"checkbox\" disabled{}>",
+                    checked
+                );
+            }
+            None => out.push_str("<li>"),
+        }
+        out.push_str(&inline_html(&item.content));
+    }
+    while let Some(tag) = open.pop() {
+        let _ = write!(out, "</li>\n</{}>\n", tag);
+    }
+}
+
+fn inline_html(runs: &[Inline]) -> String {
+    let mut out = String::new();
+    for run in runs {
+        let mut text = escape(&run.text);
+        if run.code {
+            text = format!("<code>{}</code>", text);
+        }
+        if run.strike {
+            text = format!("<del>{}</del>", text);
+        }
+        if run.emphasis {
+            text = format!("<em>{}</em>", text);
+        }
+        if run.strong {
+            text = format!("<strong>{}</strong>", text);
+        }
+        if let Some(url) = &run.link {
+            // Scripts in links would run in the reader's browser
+            let url = if url.trim().to_ascii_lowercase().starts_with("javascript:") {
+                "#"
+            } else {
+                url
+            };
+            text = format!("<a href=\"{}\">{}</a>", escape(url), text);
+        }
+        out.push_str(&text);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::FileArg;
+
+    fn markdown_html(text: &str) -> String {
+        let mut out = String::new();
+        blocks_html(&markdown::parse(text), &mut out);
+        out
+    }
+
+    #[test]
+    fn text_and_titles_are_escaped() {
+        let page = html(
+            "<b>&'\"",
+            "<script>alert(1)</script>",
+            Language::Markdown,
+            &theme::palette(true),
+        );
+        assert!(page.contains("<title>&lt;b&gt;&amp;&#39;&quot;</title>"));
+        assert!(page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!page.contains("<script>"));
+
+        let source = html(
+            "a.rs",
+            "let s = \"<tag>\";",
+            Language::Rust,
+            &theme::palette(false),
+        );
+        assert!(source.contains("&lt;tag&gt;"));
+        assert!(!source.contains("<tag>"));
+    }
+
+    #[test]
+    fn script_links_are_neutralized() {
+        let out = markdown_html(
+            "[a](javascript:alert(1)) [b]( JavaScript:x) [c](https://ok?a=1&b=\"2\")",
+        );
+        assert!(!out.to_ascii_lowercase().contains("javascript:"));
+        assert_eq!(out.matches("<a href=\"#\">").count(), 2);
+        assert!(out.contains("<a href=\"https://ok?a=1&amp;b=&quot;2&quot;\">c</a>"));
+    }
+
+    #[test]
+    fn nested_lists_close_in_order() {
+        let out = markdown_html("- a\n  1. b\n  2. c\n     - [x] d\n- e");
+        assert_eq!(
+            out,
+            "<ul>\n<li>a<ol>\n<li>b</li>\n<li>c<ul>\n\
+             <li class=\"task\"><input type=\"checkbox\" disabled checked>d</li>\n</ul>\n\
+             </li>\n</ol>\n</li>\n<li>e</li>\n</ul>\n"
+        );
+
+        let out = markdown_html("3. three\n4. four");
+        assert!(out.starts_with("<ol start=\"3\">\n"));
+    }
+
+    #[test]
+    fn default_path_never_is_the_source() {
+        assert_eq!(
+            default_path(Path::new("notes.md"), ExportFormat::Html),
+            Path::new("notes.html")
+        );
+        assert_eq!(
+            default_path(Path::new("page.html"), ExportFormat::Html),
+            Path::new("page.export.html")
+        );
+    }
+
+    #[test]
+    fn cli_refuses_to_overwrite_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.html");
+        fs::write(&path, "<p>original</p>").unwrap();
+
+        let args = CliArgs {
+            files: vec![FileArg {
+                source: FileSource::Path(path.clone()),
+                line: None,
+                column: None,
+            }],
+            // The same file reached through another spelling
+            output: Some(dir.path().join(".").join("page.html")),
+            ..CliArgs::default()
+        };
+        assert_eq!(run_cli(ExportFormat::Html, &args, &Settings::default()), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "<p>original</p>");
+
+        let args = CliArgs {
+            output: None,
+            ..args
+        };
+        assert_eq!(run_cli(ExportFormat::Html, &args, &Settings::default()), 0);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "<p>original</p>");
+        assert!(dir.path().join("page.export.html").exists());
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, Metadata};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How often open files are checked for changes made by other programs
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What a file looked like on disk when it was last opened or saved
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskState {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+    /// Device and inode, used to find the file again after a rename
+    file_id: Option<(u64, u64)>,
+}
+
+impl DiskState {
+    /// State of the file at `path`, whose contents are `bytes`
+    pub fn new(path: &Path, bytes: &[u8]) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        Ok(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: hash_bytes(bytes),
+            file_id: file_id(&metadata),
+        })
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        Self::new(path, &bytes)
+    }
+}
+
+/// A change to an open file made by another program
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExternalChange {
+    Modified,
+    Deleted,
+    Renamed(PathBuf),
+}
+
+/// Result of comparing a file with its last known state
+pub enum DiskChange {
+    Unchanged,
+    /// The timestamp moved but the contents are the same
+    Touched(DiskState),
+    Changed(ExternalChange),
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Looks for a file that moved out of `path` within the same directory
+fn find_renamed(path: &Path, id: (u64, u64)) -> Option<PathBuf> {
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty())?;
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .find(|entry| {
+            entry
+                .metadata()
+                .is_ok_and(|m| m.is_file() && file_id(&m) == Some(id))
+        })
+        .map(|entry| entry.path())
+}
+
+/// Compares the file at `path` with `known`. The contents are only read
+/// when the size or timestamp differ.
+pub fn check(path: &Path, known: &DiskState) -> DiskChange {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let renamed = known.file_id.and_then(|id| find_renamed(path, id));
+            return DiskChange::Changed(match renamed {
+                Some(new_path) => ExternalChange::Renamed(new_path),
+                None => ExternalChange::Deleted,
+            });
+        }
+        // Unreadable for now (permissions, network drive): try again later
+        Err(_) => return DiskChange::Unchanged,
+    };
+
+    if metadata.modified().ok() == known.modified && metadata.len() == known.len {
+        return DiskChange::Unchanged;
+    }
+
+    match fs::read(path) {
+        Ok(bytes) if hash_bytes(&bytes) == known.hash => DiskChange::Touched(DiskState {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: known.hash,
+            file_id: file_id(&metadata),
+        }),
+        Ok(_) => DiskChange::Changed(ExternalChange::Modified),
+        Err(_) => DiskChange::Unchanged,
+    }
+}
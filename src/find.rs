@@ -0,0 +1,269 @@
+use regex::{Captures, Regex, RegexBuilder};
+use std::ops::Range;
+
+use crate::document::Document;
+
+/// State of the Find/Replace bar, shared by all tabs
+#[derive(Default)]
+pub struct FindState {
+    pub open: bool,
+    pub show_replace: bool,
+    pub focus_query: bool,
+    pub query: String,
+    pub replacement: String,
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub use_regex: bool,
+    pub error: Option<String>,
+    /// Byte ranges of every non-empty match in the active document
+    pub matches: Vec<Range<usize>>,
+    pub current: Option<usize>,
+    regex: Option<Regex>,
+    /// (document id, document revision) the matches were computed for
+    searched: Option<(u64, u64)>,
+}
+
+impl FindState {
+    pub fn open(&mut self, show_replace: bool) {
+        self.open = true;
+        self.show_replace = show_replace;
+        self.focus_query = true;
+    }
+
+    /// Forces a new search, e.g. after the query or an option changed
+    pub fn invalidate(&mut self) {
+        self.searched = None;
+        self.current = None;
+    }
+
+    fn build_regex(&self) -> Result<Regex, regex::Error> {
+        let mut pattern = if self.use_regex {
+            self.query.clone()
+        } else {
+            regex::escape(&self.query)
+        };
+        if self.whole_word {
+            pattern = format!(r"\b(?:{})\b", pattern);
+        }
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .multi_line(true)
+            .build()
+    }
+
+    /// Re-runs the search if the query or the document changed since the
+    /// last call. `caret` picks the first match to step from.
+    pub fn update(&mut self, doc: &Document, caret: usize) {
+        let key = (doc.id, doc.revision);
+        if self.searched == Some(key) {
+            return;
+        }
+        let keep_current = self.searched.map(|(id, _)| id) == Some(doc.id);
+        self.searched = Some(key);
+        self.matches.clear();
+        self.error = None;
+        self.regex = None;
+
+        if self.query.is_empty() {
+            self.current = None;
+            return;
+        }
+
+        match self.build_regex() {
+            Ok(re) => {
+                self.matches = re
+                    .find_iter(&doc.text)
+                    .filter(|m| !m.is_empty())
+                    .map(|m| m.range())
+                    .collect();
+                self.regex = Some(re);
+            }
+            Err(e) => {
+                self.error = Some(e.to_string());
+                self.current = None;
+                return;
+            }
+        }
+
+        self.current = match self.current {
+            Some(i) if keep_current && !self.matches.is_empty() => {
+                Some(i.min(self.matches.len() - 1))
+            }
+            _ => {
+                let caret = byte_offset(&doc.text, caret);
+                self.matches
+                    .iter()
+                    .position(|m| m.start >= caret)
+                    .or_else(|| (!self.matches.is_empty()).then_some(0))
+            }
+        };
+    }
+
+    pub fn current_match(&self) -> Option<Range<usize>> {
+        self.current.and_then(|i| self.matches.get(i).cloned())
+    }
+
+    pub fn step(&mut self, forward: bool) {
+        let len = self.matches.len();
+        if len == 0 {
+            return;
+        }
+        self.current = Some(match self.current {
+            Some(i) if forward => (i + 1) % len,
+            Some(i) => (i + len - 1) % len,
+            None if forward => 0,
+            None => len - 1,
+        });
+    }
+
+    fn expand(&self, caps: &Captures, dst: &mut String) {
+        if self.use_regex {
+            caps.expand(&self.replacement, dst);
+        } else {
+            dst.push_str(&self.replacement);
+        }
+    }
+
+    /// Replacement text for the current match, with capture groups expanded
+    pub fn replace_current(&self, text: &str) -> Option<(Range<usize>, String)> {
+        let range = self.current_match()?;
+        let re = self.regex.as_ref()?;
+        let caps = re.captures_at(text, range.start)?;
+        if caps.get(0)?.range() != range {
+            return None;
+        }
+        let mut replacement = String::new();
+        self.expand(&caps, &mut replacement);
+        Some((range, replacement))
+    }
+
+    /// Returns `text` with every match replaced, and the number of replacements
+    pub fn replace_all(&self, text: &str) -> Option<(String, usize)> {
+        let re = self.regex.as_ref()?;
+        let mut result = String::with_capacity(text.len());
+        let mut last = 0;
+        let mut count = 0;
+
+        for caps in re.captures_iter(text) {
+            let Some(m) = caps.get(0) else {
+                continue;
+            };
+            if m.is_empty() {
+                continue;
+            }
+            result.push_str(&text[last..m.start()]);
+            self.expand(&caps, &mut result);
+            last = m.end();
+            count += 1;
+        }
+        result.push_str(&text[last..]);
+
+        Some((result, count))
+    }
+}
+
+/// Byte offset of the char at `char_index`, or the end of `text`
+pub fn byte_offset(text: &str, char_index: usize) -> usize {
+    text.char_indices()
+        .nth(char_index)
+        .map(|(i, _)| i)
+        .unwrap_or(text.len())
+}
+
+/// Char index of the char at `byte_offset`, which must be on a char
+/// boundary
+pub fn char_index(text: &str, byte_offset: usize) -> usize {
+    text[..byte_offset].chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(find: &mut FindState, text: &str) -> Document {
+        let mut doc = Document::new();
+        doc.text = text.to_string();
+        find.invalidate();
+        find.update(&doc, 0);
+        doc
+    }
+
+    #[test]
+    fn regex_replacement_expands_captures() {
+        let mut find = FindState {
+            query: r"(\w+)@(\w+)".to_string(),
+            replacement: "$2 at $1".to_string(),
+            use_regex: true,
+            ..FindState::default()
+        };
+        let doc = search(&mut find, "me@home, you@work");
+        assert_eq!(
+            find.replace_current(&doc.text),
+            Some((0..7, "home at me".to_string()))
+        );
+        assert_eq!(
+            find.replace_all(&doc.text),
+            Some(("home at me, work at you".to_string(), 2))
+        );
+    }
+
+    #[test]
+    fn plain_replacement_is_literal() {
+        let mut find = FindState {
+            query: "(a)".to_string(),
+            replacement: "$1".to_string(),
+            ..FindState::default()
+        };
+        let doc = search(&mut find, "(a) a (a)");
+        assert_eq!(find.matches, vec![0..3, 6..9]);
+        assert_eq!(
+            find.replace_all(&doc.text),
+            Some(("$1 a $1".to_string(), 2))
+        );
+    }
+
+    #[test]
+    fn whole_word_and_case_options() {
+        let text = "Cat cat catalog";
+        let mut find = FindState {
+            query: "cat".to_string(),
+            ..FindState::default()
+        };
+        search(&mut find, text);
+        assert_eq!(find.matches, vec![0..3, 4..7, 8..11]);
+
+        find.whole_word = true;
+        search(&mut find, text);
+        assert_eq!(find.matches, vec![0..3, 4..7]);
+
+        find.case_sensitive = true;
+        search(&mut find, text);
+        assert_eq!(find.matches, vec![4..7]);
+    }
+
+    #[test]
+    fn replace_all_skips_empty_matches() {
+        let mut find = FindState {
+            query: "x*".to_string(),
+            replacement: "-".to_string(),
+            use_regex: true,
+            ..FindState::default()
+        };
+        let doc = search(&mut find, "axxbx");
+        assert_eq!(find.matches, vec![1..3, 4..5]);
+        assert_eq!(find.replace_all(&doc.text), Some(("a-b-".to_string(), 2)));
+    }
+
+    #[test]
+    fn invalid_regex_reports_an_error() {
+        let mut find = FindState {
+            query: "(".to_string(),
+            use_regex: true,
+            ..FindState::default()
+        };
+        let doc = search(&mut find, "(");
+        assert!(find.error.is_some());
+        assert!(find.matches.is_empty());
+        assert_eq!(find.replace_all(&doc.text), None);
+    }
+}
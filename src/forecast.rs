@@ -0,0 +1,188 @@
+use chrono::NaiveDate;
+use eframe::egui::{self, Align2, FontId, Pos2, Rect, Sense, Shape, Stroke};
+
+use crate::theme::CatppuccinPalette;
+use crate::weather::{self, DailyForecast, HourlyForecast, TemperatureUnit};
+
+const CHART_WIDTH: f32 = 520.0;
+const CHART_HEIGHT: f32 = 170.0;
+/// Room for the temperature labels on the left and precipitation on the
+/// right
+const AXIS_WIDTH: f32 = 40.0;
+/// Room for the hour labels under the plot
+const HOUR_LABEL_HEIGHT: f32 = 16.0;
+/// The precipitation scale never goes below this, so a drizzle does not
+/// fill the chart
+const MIN_PRECIPITATION_SCALE: f32 = 2.0;
+
+/// Temperature line over precipitation bars for the coming hours
+pub fn hourly_chart(
+    ui: &mut egui::Ui,
+    hours: &[HourlyForecast],
+    unit: TemperatureUnit,
+    palette: &CatppuccinPalette,
+) {
+    if hours.is_empty() {
+        ui.weak("No hourly forecast");
+        return;
+    }
+
+    let (response, painter) =
+        ui.allocate_painter(egui::vec2(CHART_WIDTH, CHART_HEIGHT), Sense::hover());
+    let outer = response.rect;
+    let plot = Rect::from_min_max(
+        egui::pos2(outer.left() + AXIS_WIDTH, outer.top() + 6.0),
+        egui::pos2(
+            outer.right() - AXIS_WIDTH,
+            outer.bottom() - HOUR_LABEL_HEIGHT,
+        ),
+    );
+    let small = FontId::proportional(11.0);
+    let weak = ui.visuals().weak_text_color();
+
+    let temperatures: Vec<f32> = hours
+        .iter()
+        .map(|hour| unit.convert_celsius(hour.temperature_c) as f32)
+        .collect();
+    let mut low = temperatures.iter().copied().fold(f32::INFINITY, f32::min);
+    let mut high = temperatures
+        .iter()
+        .copied()
+        .fold(f32::NEG_INFINITY, f32::max);
+    // Keep a flat day from drawing as a line along the edge
+    if high - low < 4.0 {
+        let middle = (high + low) / 2.0;
+        low = middle - 2.0;
+        high = middle + 2.0;
+    }
+    let wettest = hours
+        .iter()
+        .map(|hour| hour.precipitation_mm as f32)
+        .fold(MIN_PRECIPITATION_SCALE, f32::max);
+
+    let slot = plot.width() / hours.len() as f32;
+    let x_at = |i: usize| plot.left() + slot * (i as f32 + 0.5);
+    let y_at = |t: f32| egui::remap(t, low..=high, plot.bottom()..=plot.top());
+
+    painter.rect_filled(plot, 4.0, palette.mantle);
+    for t in [low, (low + high) / 2.0, high] {
+        let y = y_at(t);
+        painter.hline(plot.x_range(), y, Stroke::new(1.0, palette.surface0));
+        painter.text(
+            egui::pos2(plot.left() - 4.0, y),
+            Align2::RIGHT_CENTER,
+            format!("{:.0}{}", t, unit.symbol()),
+            small.clone(),
+            weak,
+        );
+    }
+    painter.text(
+        egui::pos2(plot.right() + 4.0, plot.top()),
+        Align2::LEFT_TOP,
+        format!("{:.0} mm", wettest),
+        small.clone(),
+        palette.sapphire,
+    );
+
+    for (i, hour) in hours.iter().enumerate() {
+        let height = plot.height() * hour.precipitation_mm as f32 / wettest;
+        if height > 0.0 {
+            let bar = Rect::from_min_max(
+                egui::pos2(x_at(i) - slot * 0.35, plot.bottom() - height),
+                egui::pos2(x_at(i) + slot * 0.35, plot.bottom()),
+            );
+            painter.rect_filled(bar, 1.0, palette.sapphire.gamma_multiply(0.6));
+        }
+        if i % 3 == 0 {
+            painter.text(
+                egui::pos2(x_at(i), plot.bottom() + 2.0),
+                Align2::CENTER_TOP,
+                hour_label(&hour.time),
+                small.clone(),
+                weak,
+            );
+        }
+    }
+
+    let points: Vec<Pos2> = temperatures
+        .iter()
+        .enumerate()
+        .map(|(i, &t)| egui::pos2(x_at(i), y_at(t)))
+        .collect();
+    painter.add(Shape::line(points.clone(), Stroke::new(2.0, palette.peach)));
+    for &point in &points {
+        painter.circle_filled(point, 2.5, palette.peach);
+    }
+
+    // Details of the hour under the pointer
+    if let Some(pointer) = response.hover_pos().filter(|pos| plot.contains(*pos)) {
+        let i = (((pointer.x - plot.left()) / slot) as usize).min(hours.len() - 1);
+        let hour = &hours[i];
+        painter.vline(x_at(i), plot.y_range(), Stroke::new(1.0, palette.overlay0));
+        painter.circle_stroke(points[i], 4.5, Stroke::new(1.5, palette.text));
+        let (description, icon) = weather::describe(hour.weather_code);
+        response.on_hover_text_at_pointer(format!(
+            "{}  {} {}\n{:.1}{}  {:.1} mm",
+            hour_label(&hour.time),
+            icon,
+            description,
+            temperatures[i],
+            unit.symbol(),
+            hour.precipitation_mm
+        ));
+    }
+}
+
+/// Daily high and low with the weather icon for each day
+pub fn daily_table(ui: &mut egui::Ui, days: &[DailyForecast], unit: TemperatureUnit) {
+    if days.is_empty() {
+        ui.weak("No daily forecast");
+        return;
+    }
+
+    egui::Grid::new("daily_forecast")
+        .striped(true)
+        .num_columns(5)
+        .spacing([16.0, 4.0])
+        .show(ui, |ui| {
+            ui.strong("Day");
+            ui.label("");
+            ui.label("");
+            ui.strong("High");
+            ui.strong("Low");
+            ui.end_row();
+
+            for (i, day) in days.iter().enumerate() {
+                let (description, icon) = weather::describe(day.weather_code);
+                ui.label(day_label(&day.date, i));
+                ui.label(egui::RichText::new(icon).size(18.0));
+                ui.label(description);
+                ui.label(format!(
+                    "{:.0}{}",
+                    unit.convert_celsius(day.high_c),
+                    unit.symbol()
+                ));
+                ui.weak(format!(
+                    "{:.0}{}",
+                    unit.convert_celsius(day.low_c),
+                    unit.symbol()
+                ));
+                ui.end_row();
+            }
+        });
+}
+
+/// `HH:MM` from a `YYYY-MM-DDTHH:MM` time
+fn hour_label(time: &str) -> &str {
+    time.get(11..16).unwrap_or(time)
+}
+
+/// "Today", then short weekday and date like "Wed 3"
+fn day_label(date: &str, index: usize) -> String {
+    if index == 0 {
+        return "Today".to_string();
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|date| date.format("%a %-d").to_string())
+        .unwrap_or_else(|_| date.to_string())
+}
@@ -0,0 +1,114 @@
+use eframe::egui::text_edit::TextEditOutput;
+use eframe::egui::{self, text::CCursor, Align2, Rect, Sense, Shape};
+
+use crate::theme::CatppuccinPalette;
+
+/// Space between the line numbers and the gutter's edges
+const GUTTER_PADDING: f32 = 8.0;
+/// Line numbers get at least this many digits of room, so the text does not
+/// shift while a short document grows
+const MIN_DIGITS: usize = 3;
+
+/// Width of the line number gutter for a document with `line_count` lines
+pub fn width(ui: &egui::Ui, font: &egui::FontId, line_count: usize) -> f32 {
+    let digits = line_count.to_string().len().max(MIN_DIGITS);
+    let digit_width = ui.fonts(|f| f.glyph_width(font, '0'));
+    digit_width * digits as f32 + GUTTER_PADDING * 2.0
+}
+
+/// Paints the editor background into `background`, a shape slot reserved
+/// before the editor was drawn so it ends up beneath the text. With a gutter
+/// this also paints the line numbers in the editor's left margin and a band
+/// behind the caret's line. The gutter stays at the left edge of the view
+/// when the editor scrolls sideways. Returns the 0-based line whose number
+/// was clicked.
+pub fn paint(
+    ui: &egui::Ui,
+    background: egui::layers::ShapeIdx,
+    output: &TextEditOutput,
+    gutter_width: Option<f32>,
+    font: &egui::FontId,
+    caret: usize,
+    palette: &CatppuccinPalette,
+) -> Option<usize> {
+    let visuals = ui.visuals();
+    let rect = output.response.rect;
+    let rounding = visuals.widgets.inactive.rounding;
+    let stroke = if output.response.has_focus() {
+        visuals.selection.stroke
+    } else {
+        visuals.widgets.inactive.bg_stroke
+    };
+    let mut shapes = vec![Shape::rect_filled(rect, rounding, visuals.extreme_bg_color)];
+
+    let clicked_line = gutter_width.and_then(|gutter_width| {
+        let galley = &output.galley;
+        let origin = output.galley_pos.to_vec2();
+        let clip = ui.clip_rect();
+        let left = rect.min.x.max(clip.min.x);
+        let gutter = Rect::from_min_max(
+            egui::pos2(left, rect.min.y),
+            egui::pos2(left + gutter_width, rect.max.y),
+        );
+        let current_line = galley.from_ccursor(CCursor::new(caret)).pcursor.paragraph;
+
+        // Painted over the text, which slides under it when scrolled sideways
+        ui.painter().rect_filled(gutter, rounding, palette.mantle);
+
+        // A wrapped line spans several rows; only its first row is numbered
+        let mut line = 0;
+        let mut starts_line = true;
+        let mut band: Option<Rect> = None;
+        for row in &galley.rows {
+            let row_rect = row.rect.translate(origin);
+            if row_rect.min.y > clip.max.y && line > current_line {
+                break;
+            }
+            if line == current_line {
+                band = Some(band.map_or(row_rect, |band| band.union(row_rect)));
+            }
+            if starts_line && row_rect.max.y >= clip.min.y && row_rect.min.y <= clip.max.y {
+                let color = if line == current_line {
+                    palette.text
+                } else {
+                    palette.surface2
+                };
+                ui.painter().text(
+                    egui::pos2(gutter.max.x - GUTTER_PADDING, row_rect.min.y),
+                    Align2::RIGHT_TOP,
+                    (line + 1).to_string(),
+                    font.clone(),
+                    color,
+                );
+            }
+            starts_line = row.ends_with_newline;
+            if row.ends_with_newline {
+                line += 1;
+            }
+        }
+
+        if let Some(band) = band {
+            let band = Rect::from_x_y_ranges(rect.x_range(), band.y_range());
+            shapes.push(Shape::rect_filled(
+                band,
+                0.0,
+                palette.surface0.gamma_multiply(0.5),
+            ));
+        }
+
+        let response = ui.interact(gutter, ui.id().with("line_number_gutter"), Sense::click());
+        let pointer = response
+            .interact_pointer_pos()
+            .filter(|_| response.clicked())?;
+        Some(
+            galley
+                .cursor_from_pos((pointer - origin).to_vec2())
+                .pcursor
+                .paragraph,
+        )
+    });
+
+    shapes.push(Shape::rect_stroke(rect, rounding, stroke));
+    ui.painter().set(background, Shape::Vec(shapes));
+    clicked_line
+}
@@ -0,0 +1,469 @@
+use std::time::{Duration, Instant};
+
+use crate::find::char_index;
+
+/// Maximum number of undo steps kept per document
+const MAX_STEPS: usize = 500;
+/// Typing pauses longer than this start a new undo step
+const GROUP_TIMEOUT: Duration = Duration::from_millis(1500);
+/// Longest snippet of text shown in a step label
+const LABEL_MAX_CHARS: usize = 24;
+
+/// A single replacement of `removed` by `inserted` at byte `offset`
+#[derive(Debug, Clone)]
+struct Edit {
+    offset: usize,
+    removed: String,
+    inserted: String,
+}
+
+impl Edit {
+    /// Smallest edit that turns `old` into `new`, found by trimming the
+    /// common prefix and suffix.
+    fn diff(old: &str, new: &str) -> Option<Self> {
+        if old == new {
+            return None;
+        }
+
+        let mut prefix = old
+            .bytes()
+            .zip(new.bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+        while !old.is_char_boundary(prefix) || !new.is_char_boundary(prefix) {
+            prefix -= 1;
+        }
+
+        let max_suffix = old.len().min(new.len()) - prefix;
+        let mut suffix = old
+            .bytes()
+            .rev()
+            .zip(new.bytes().rev())
+            .take(max_suffix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        while !old.is_char_boundary(old.len() - suffix) || !new.is_char_boundary(new.len() - suffix)
+        {
+            suffix -= 1;
+        }
+
+        Some(Self {
+            offset: prefix,
+            removed: old[prefix..old.len() - suffix].to_string(),
+            inserted: new[prefix..new.len() - suffix].to_string(),
+        })
+    }
+
+    fn is_typing(&self) -> bool {
+        self.removed.is_empty() && self.inserted.chars().count() == 1
+    }
+
+    fn is_deleting(&self) -> bool {
+        self.inserted.is_empty() && self.removed.chars().count() == 1
+    }
+
+    fn apply(&self, text: &mut String) {
+        text.replace_range(
+            self.offset..self.offset + self.removed.len(),
+            &self.inserted,
+        );
+    }
+
+    fn revert(&self, text: &mut String) {
+        text.replace_range(
+            self.offset..self.offset + self.inserted.len(),
+            &self.removed,
+        );
+    }
+}
+
+/// One entry in the undo stack: a group of edits undone together
+#[derive(Debug, Clone)]
+struct Step {
+    edits: Vec<Edit>,
+    label: Option<String>,
+}
+
+impl Step {
+    fn label(&self) -> String {
+        if let Some(ref label) = self.label {
+            return label.clone();
+        }
+
+        let first = &self.edits[0];
+        let last = &self.edits[self.edits.len() - 1];
+        if first.removed.is_empty() && last.is_typing() {
+            let typed: String = self.edits.iter().map(|e| e.inserted.as_str()).collect();
+            format!("Typing \"{}\"", snippet(&typed))
+        } else if first.inserted.is_empty() {
+            // Backspacing prepends to the group, so the newest edit holds the start
+            let mut removed: Vec<&str> = self.edits.iter().map(|e| e.removed.as_str()).collect();
+            if self.edits.len() > 1 && last.offset < first.offset {
+                removed.reverse();
+            }
+            format!("Delete \"{}\"", snippet(&removed.concat()))
+        } else if first.removed.is_empty() {
+            format!("Insert \"{}\"", snippet(&first.inserted))
+        } else {
+            format!("Replace \"{}\"", snippet(&first.removed))
+        }
+    }
+}
+
+fn snippet(text: &str) -> String {
+    let flat: String = text
+        .chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .collect();
+    if flat.chars().count() > LABEL_MAX_CHARS {
+        let short: String = flat.chars().take(LABEL_MAX_CHARS).collect();
+        format!("{}\u{2026}", short)
+    } else {
+        flat
+    }
+}
+
+/// Undo/redo history for a document buffer.
+///
+/// The history keeps a copy of the text as of the last recorded change and
+/// diffs the live buffer against it, so any widget may edit the buffer freely
+/// as long as [`EditHistory::record`] is called afterwards.
+pub struct EditHistory {
+    undo_stack: Vec<Step>,
+    redo_stack: Vec<Step>,
+    shadow: String,
+    group_open: bool,
+    last_edit: Instant,
+    saved_depth: Option<usize>,
+}
+
+impl EditHistory {
+    pub fn new(text: &str) -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            shadow: text.to_string(),
+            group_open: false,
+            last_edit: Instant::now(),
+            saved_depth: Some(0),
+        }
+    }
+
+    /// Records whatever changed in `text` since the last call, merging
+    /// consecutive keystrokes into word-sized steps.
+    pub fn record(&mut self, text: &str) {
+        let Some(edit) = Edit::diff(&self.shadow, text) else {
+            return;
+        };
+        self.shadow.clear();
+        self.shadow.push_str(text);
+
+        let recent = self.last_edit.elapsed() < GROUP_TIMEOUT;
+        self.last_edit = Instant::now();
+
+        if self.group_open && recent && self.try_merge(&edit) {
+            return;
+        }
+
+        self.group_open = edit.is_typing() || edit.is_deleting();
+        self.push(Step {
+            edits: vec![edit],
+            label: None,
+        });
+    }
+
+    /// Records the change in `text` as one standalone step with a fixed label,
+    /// e.g. "Replace All".
+    pub fn record_step(&mut self, label: &str, text: &str) {
+        let Some(edit) = Edit::diff(&self.shadow, text) else {
+            return;
+        };
+        self.shadow.clear();
+        self.shadow.push_str(text);
+        self.group_open = false;
+        self.push(Step {
+            edits: vec![edit],
+            label: Some(label.to_string()),
+        });
+    }
+
+    fn try_merge(&mut self, edit: &Edit) -> bool {
+        let Some(step) = self.undo_stack.last_mut() else {
+            return false;
+        };
+        let last = &step.edits[step.edits.len() - 1];
+
+        let merges = if edit.is_typing() && last.is_typing() {
+            // Start a new word after whitespace
+            let after_space = last.inserted.chars().all(char::is_whitespace);
+            let word_char = !edit.inserted.chars().all(char::is_whitespace);
+            edit.offset == last.offset + last.inserted.len() && !(after_space && word_char)
+        } else if edit.is_deleting() && last.is_deleting() {
+            let backspace = edit.offset + edit.removed.len() == last.offset;
+            let forward = edit.offset == last.offset;
+            let crosses_word = last.removed.chars().all(char::is_whitespace)
+                != edit.removed.chars().all(char::is_whitespace);
+            (backspace || forward) && !crosses_word
+        } else {
+            false
+        };
+
+        if merges {
+            step.edits.push(edit.clone());
+        }
+        merges
+    }
+
+    fn push(&mut self, step: Step) {
+        if let Some(depth) = self.saved_depth {
+            if depth > self.undo_stack.len() {
+                // The saved state lived on the redo stack, which is now gone
+                self.saved_depth = None;
+            }
+        }
+        self.redo_stack.clear();
+        self.undo_stack.push(step);
+
+        if self.undo_stack.len() > MAX_STEPS {
+            self.undo_stack.remove(0);
+            self.saved_depth = self.saved_depth.and_then(|d| d.checked_sub(1));
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Reverts the most recent step in `text` and returns the char index
+    /// where the caret should be placed.
+    pub fn undo(&mut self, text: &mut String) -> Option<usize> {
+        let step = self.undo_stack.pop()?;
+        for edit in step.edits.iter().rev() {
+            edit.revert(text);
+        }
+        let first = &step.edits[0];
+        let cursor = char_index(text, first.offset + first.removed.len());
+
+        self.shadow.clone_from(text);
+        self.group_open = false;
+        self.redo_stack.push(step);
+        Some(cursor)
+    }
+
+    /// Re-applies the most recently undone step in `text` and returns the
+    /// char index where the caret should be placed.
+    pub fn redo(&mut self, text: &mut String) -> Option<usize> {
+        let step = self.redo_stack.pop()?;
+        for edit in &step.edits {
+            edit.apply(text);
+        }
+        let last = &step.edits[step.edits.len() - 1];
+        let cursor = char_index(text, last.offset + last.inserted.len());
+
+        self.shadow.clone_from(text);
+        self.group_open = false;
+        self.undo_stack.push(step);
+        Some(cursor)
+    }
+
+    /// Labels of the undoable steps, most recent first
+    pub fn undo_labels(&self) -> impl Iterator<Item = String> + '_ {
+        self.undo_stack.iter().rev().map(Step::label)
+    }
+
+    /// Labels of the redoable steps, next redo first
+    pub fn redo_labels(&self) -> impl Iterator<Item = String> + '_ {
+        self.redo_stack.iter().rev().map(Step::label)
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_depth = Some(self.undo_stack.len());
+        self.group_open = false;
+    }
+
+    /// Flags the current contents as never saved, e.g. text read from stdin
+    pub fn mark_unsaved(&mut self) {
+        self.saved_depth = None;
+    }
+
+    pub fn is_at_saved(&self) -> bool {
+        self.saved_depth == Some(self.undo_stack.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_all_undoes_in_one_step() {
+        let original = "a cat and a cat";
+        let mut history = EditHistory::new(original);
+        let mut text = "a dog and a dog".to_string();
+        history.record_step("Replace All", &text);
+
+        assert_eq!(history.undo_labels().collect::<Vec<_>>(), ["Replace All"]);
+        assert!(history.undo(&mut text).is_some());
+        assert_eq!(text, original);
+        assert!(!history.can_undo());
+
+        assert!(history.redo(&mut text).is_some());
+        assert_eq!(text, "a dog and a dog");
+    }
+
+    #[test]
+    fn replace_all_does_not_merge_with_typing() {
+        let mut history = EditHistory::new("cat");
+        let mut text = "cats".to_string();
+        history.record(&text);
+        text = "dogs".to_string();
+        history.record_step("Replace All", &text);
+        text.push('!');
+        history.record(&text);
+
+        assert_eq!(
+            history.undo_labels().collect::<Vec<_>>(),
+            ["Typing \"!\"", "Replace All", "Typing \"s\""]
+        );
+        history.undo(&mut text);
+        history.undo(&mut text);
+        assert_eq!(text, "cats");
+    }
+
+    #[test]
+    fn typing_groups_by_word() {
+        let mut history = EditHistory::new("");
+        let mut text = String::new();
+        for c in "hi there".chars() {
+            text.push(c);
+            history.record(&text);
+        }
+
+        assert_eq!(
+            history.undo_labels().collect::<Vec<_>>(),
+            ["Typing \"there\"", "Typing \"hi \""]
+        );
+        history.undo(&mut text);
+        assert_eq!(text, "hi ");
+    }
+
+    /// Records each string in turn as the next state of the buffer
+    fn record_all(history: &mut EditHistory, states: &[&str]) -> String {
+        for state in states {
+            history.record(state);
+        }
+        states.last().map_or(String::new(), |s| s.to_string())
+    }
+
+    #[test]
+    fn backspacing_merges_into_one_step() {
+        let mut history = EditHistory::new("hello world");
+        let mut text = record_all(&mut history, &["hello worl", "hello wor", "hello wo"]);
+
+        assert_eq!(
+            history.undo_labels().collect::<Vec<_>>(),
+            ["Delete \"rld\""]
+        );
+        history.undo(&mut text);
+        assert_eq!(text, "hello world");
+    }
+
+    #[test]
+    fn forward_deleting_merges_into_one_step() {
+        let mut history = EditHistory::new("abcdef");
+        let mut text = record_all(&mut history, &["bcdef", "cdef", "def"]);
+
+        assert_eq!(
+            history.undo_labels().collect::<Vec<_>>(),
+            ["Delete \"abc\""]
+        );
+        history.undo(&mut text);
+        assert_eq!(text, "abcdef");
+    }
+
+    #[test]
+    fn deleting_into_whitespace_starts_a_new_step() {
+        let mut history = EditHistory::new("ab cd");
+        let mut text = record_all(&mut history, &["ab c", "ab ", "ab"]);
+
+        assert_eq!(
+            history.undo_labels().collect::<Vec<_>>(),
+            ["Delete \" \"", "Delete \"cd\""]
+        );
+        history.undo(&mut text);
+        assert_eq!(text, "ab ");
+    }
+
+    #[test]
+    fn typing_pause_starts_a_new_step() {
+        let mut history = EditHistory::new("");
+        let mut text = record_all(&mut history, &["a", "ab"]);
+        history.last_edit -= GROUP_TIMEOUT;
+        text.push('c');
+        history.record(&text);
+
+        assert_eq!(
+            history.undo_labels().collect::<Vec<_>>(),
+            ["Typing \"c\"", "Typing \"ab\""]
+        );
+    }
+
+    #[test]
+    fn dropping_old_steps_keeps_the_saved_point() {
+        let mut history = EditHistory::new("");
+        let mut text = String::new();
+        for i in 0..10 {
+            text = i.to_string();
+            history.record_step("Step", &text);
+        }
+        history.mark_saved();
+        let saved = text.clone();
+        for i in 10..MAX_STEPS + 1 {
+            text = i.to_string();
+            history.record_step("Step", &text);
+        }
+        assert_eq!(history.undo_labels().count(), MAX_STEPS);
+
+        for _ in 0..MAX_STEPS - 9 {
+            assert!(history.undo(&mut text).is_some());
+        }
+        assert_eq!(text, saved);
+        assert!(history.is_at_saved());
+    }
+
+    #[test]
+    fn saved_point_is_lost_once_old_steps_are_dropped() {
+        let mut history = EditHistory::new("");
+        let mut text = String::new();
+        for i in 0..MAX_STEPS + 1 {
+            text = i.to_string();
+            history.record_step("Step", &text);
+        }
+        while history.undo(&mut text).is_some() {}
+        assert!(!history.is_at_saved());
+    }
+
+    #[test]
+    fn edit_after_undoing_past_the_save_is_unsaved() {
+        let mut history = EditHistory::new("a");
+        let mut text = record_all(&mut history, &["ab"]);
+        history.mark_saved();
+        assert!(history.is_at_saved());
+
+        history.undo(&mut text);
+        assert!(!history.is_at_saved());
+        text = "ac".to_string();
+        history.record(&text);
+        assert!(!history.is_at_saved());
+        assert!(!history.can_redo());
+
+        // Undoing the new edit returns to a state that was never saved
+        history.undo(&mut text);
+        assert_eq!(text, "a");
+        assert!(!history.is_at_saved());
+    }
+}
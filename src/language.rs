@@ -0,0 +1,59 @@
+use std::path::Path;
+
+/// The kind of text in a document, guessed from its file extension or
+/// picked in the status bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    PlainText,
+    Rust,
+    Toml,
+    Json,
+    Yaml,
+    Shell,
+    Markdown,
+}
+
+impl Language {
+    pub const ALL: &'static [Language] = &[
+        Language::PlainText,
+        Language::Rust,
+        Language::Toml,
+        Language::Json,
+        Language::Yaml,
+        Language::Shell,
+        Language::Markdown,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Language::PlainText => "Plain Text",
+            Language::Rust => "Rust",
+            Language::Toml => "TOML",
+            Language::Json => "JSON",
+            Language::Yaml => "YAML",
+            Language::Shell => "Shell",
+            Language::Markdown => "Markdown",
+        }
+    }
+
+    pub fn from_path(path: Option<&Path>) -> Self {
+        let extension = path
+            .and_then(|p| p.extension())
+            .map(|ext| ext.to_string_lossy())
+            .unwrap_or_default();
+        Self::from_name(&extension)
+    }
+
+    /// The language for a file extension or a code fence tag like `rust`
+    pub fn from_name(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "rs" | "rust" => Language::Rust,
+            "toml" => Language::Toml,
+            "json" => Language::Json,
+            "yaml" | "yml" => Language::Yaml,
+            "sh" | "bash" | "zsh" | "shell" => Language::Shell,
+            "md" | "markdown" => Language::Markdown,
+            _ => Language::PlainText,
+        }
+    }
+}
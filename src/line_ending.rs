@@ -0,0 +1,141 @@
+use std::borrow::Cow;
+
+/// Line terminator a document is written with. Buffers always hold `\n`
+/// internally; the style is only applied on save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl LineEnding {
+    pub const ALL: &'static [LineEnding] = &[LineEnding::Lf, LineEnding::Crlf, LineEnding::Cr];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+            LineEnding::Cr => "CR",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF (Unix, macOS)",
+            LineEnding::Crlf => "CRLF (Windows)",
+            LineEnding::Cr => "CR (classic Mac OS)",
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /// Style for new documents on this platform
+    pub fn native() -> Self {
+        if cfg!(windows) {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+/// Finds the dominant line ending in `text` and whether other styles are
+/// mixed in. Text without any line breaks gets the platform default.
+pub fn detect(text: &str) -> (LineEnding, bool) {
+    let (mut lf, mut crlf, mut cr) = (0usize, 0usize, 0usize);
+    let mut bytes = text.bytes().peekable();
+
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\r' if bytes.peek() == Some(&b'\n') => {
+                bytes.next();
+                crlf += 1;
+            }
+            b'\r' => cr += 1,
+            b'\n' => lf += 1,
+            _ => {}
+        }
+    }
+
+    let styles_used = [lf, crlf, cr].iter().filter(|&&n| n > 0).count();
+    let dominant = if lf == 0 && crlf == 0 && cr == 0 {
+        LineEnding::native()
+    } else if crlf >= lf && crlf >= cr {
+        LineEnding::Crlf
+    } else if lf >= cr {
+        LineEnding::Lf
+    } else {
+        LineEnding::Cr
+    };
+
+    (dominant, styles_used > 1)
+}
+
+/// Converts every `\r\n` and lone `\r` to `\n`
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    if !text.contains('\r') {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n"))
+}
+
+/// Converts a normalized buffer to `ending` for writing
+pub fn apply(text: &str, ending: LineEnding) -> Cow<'_, str> {
+    match ending {
+        LineEnding::Lf => Cow::Borrowed(text),
+        _ => Cow::Owned(text.replace('\n', ending.as_str())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_each_style() {
+        assert_eq!(detect("a\nb\n"), (LineEnding::Lf, false));
+        assert_eq!(detect("a\r\nb\r\n"), (LineEnding::Crlf, false));
+        assert_eq!(detect("a\rb\r"), (LineEnding::Cr, false));
+        assert_eq!(detect("no breaks"), (LineEnding::native(), false));
+    }
+
+    #[test]
+    fn mixed_endings_pick_the_most_common() {
+        assert_eq!(detect("a\r\nb\r\nc\nd"), (LineEnding::Crlf, true));
+        assert_eq!(detect("a\nb\nc\r\nd\re"), (LineEnding::Lf, true));
+        assert_eq!(detect("a\rb\rc\n"), (LineEnding::Cr, true));
+    }
+
+    #[test]
+    fn lone_trailing_cr_is_a_line_break() {
+        assert_eq!(detect("a\r"), (LineEnding::Cr, false));
+        assert_eq!(detect("a\nb\r"), (LineEnding::Lf, true));
+        assert_eq!(normalize("a\r\nb\r"), "a\nb\n");
+    }
+
+    #[test]
+    fn normalize_leaves_lf_text_borrowed() {
+        assert!(matches!(normalize("a\nb"), Cow::Borrowed("a\nb")));
+        assert_eq!(normalize("a\r\nb\rc\n"), "a\nb\nc\n");
+    }
+
+    #[test]
+    fn normalize_then_apply_round_trips() {
+        for (ending, text) in [
+            (LineEnding::Lf, "one\ntwo\n\nthree"),
+            (LineEnding::Crlf, "one\r\ntwo\r\n\r\nthree"),
+            (LineEnding::Cr, "one\rtwo\r\rthree"),
+        ] {
+            let normalized = normalize(text);
+            assert_eq!(normalized, "one\ntwo\n\nthree");
+            assert_eq!(apply(&normalized, ending), text);
+        }
+    }
+}
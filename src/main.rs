@@ -1,8 +1,33 @@
 mod app;
+mod atomic_file;
+mod cli;
+mod commands;
+mod diff;
+mod document;
+mod encoding;
+mod export;
+mod file_watch;
+mod find;
+mod forecast;
+mod gutter;
+mod history;
+mod language;
+mod line_ending;
+mod markdown;
+mod navigation;
+mod pdf;
+mod preview;
+mod recovery;
+mod session;
+mod settings;
+mod split;
+mod stats;
+mod syntax;
 mod system_monitor;
 mod theme;
 mod ui;
 mod weather;
+mod wrap;
 
 use eframe::egui;
 use std::time::Duration;
@@ -10,20 +35,56 @@ use std::time::Duration;
 use app::NotepadApp;
 
 fn main() -> eframe::Result<()> {
+    let args = match cli::parse(std::env::args().skip(1)) {
+        Ok(cli::Command::Run(args)) => args,
+        Ok(cli::Command::Help) => {
+            print!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(cli::Command::Version) => {
+            println!("rusty-notepad {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("rusty-notepad: {}", e);
+            eprintln!("Try 'rusty-notepad --help' for more information.");
+            std::process::exit(2);
+        }
+    };
+
+    let (settings, settings_warning) = settings::load();
+
+    if let Some(format) = args.export {
+        if let Some(warning) = settings_warning {
+            eprintln!("rusty-notepad: {}", warning);
+        }
+        std::process::exit(export::run_cli(format, &args, &settings));
+    }
+
+    let mut viewport = egui::ViewportBuilder::default()
+        .with_inner_size([settings.window.width, settings.window.height])
+        .with_min_inner_size([400.0, 300.0])
+        .with_fullsize_content_view(true)
+        .with_titlebar_shown(false)
+        .with_title_shown(false);
+    if let (Some(x), Some(y)) = (settings.window.x, settings.window.y) {
+        viewport = viewport.with_position([x, y]);
+    }
+
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_inner_size([800.0, 600.0])
-            .with_min_inner_size([400.0, 300.0])
-            .with_fullsize_content_view(true)
-            .with_titlebar_shown(false)
-            .with_title_shown(false),
+        viewport,
         ..Default::default()
     };
 
     eframe::run_native(
         "Rusty Notepad",
         options,
-        Box::new(|cc| Ok(Box::new(NotepadApp::new(cc)))),
+        Box::new(|cc| {
+            let mut app = NotepadApp::new(cc, settings, settings_warning);
+            app.restore_session(&cc.egui_ctx);
+            app.apply_cli_args(&cc.egui_ctx, args);
+            Ok(Box::new(app))
+        }),
     )
 }
 
@@ -33,13 +94,36 @@ impl eframe::App for NotepadApp {
 
         self.refresh_weather_if_needed();
         self.refresh_system_info();
+        self.check_external_changes();
         self.handle_close_request(ctx);
         self.handle_keyboard_shortcuts(ctx);
         self.handle_unsaved_dialog(ctx);
+        self.handle_overwrite_dialog(ctx);
+        self.render_recovery_dialog(ctx);
+        self.render_diff_window(ctx);
+        self.render_stats_window(ctx);
+        self.render_forecast_window(ctx);
 
         self.render_title_bar(ctx);
         self.render_menu_bar(ctx);
+        self.render_tab_bar(ctx);
+        self.render_change_banner(ctx);
+        self.render_find_bar(ctx);
+        self.render_go_to_dialog(ctx);
+        self.render_command_palette(ctx);
         self.render_status_bar(ctx);
+        self.render_markdown_preview(ctx);
         self.render_text_editor(ctx);
+
+        self.update_recovery();
+        self.persist_settings_if_needed(ctx);
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if self.settings != self.saved_settings {
+            self.save_settings();
+        }
+        self.save_session();
+        self.journal.clear();
     }
 }
@@ -0,0 +1,739 @@
+/// A run of inline text sharing one style
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Inline {
+    pub text: String,
+    pub strong: bool,
+    pub emphasis: bool,
+    pub strike: bool,
+    pub code: bool,
+    pub link: Option<String>,
+}
+
+impl Inline {
+    fn same_style(&self, other: &Inline) -> bool {
+        self.strong == other.strong
+            && self.emphasis == other.emphasis
+            && self.strike == other.strike
+            && self.code == other.code
+            && self.link == other.link
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+/// A `[ ]` or `[x]` box in front of a list item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Task {
+    pub checked: bool,
+    /// Byte offset of the character between the brackets in the source
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem {
+    /// 0-based source line
+    pub line: usize,
+    /// Nesting level, 0 for top-level items
+    pub depth: usize,
+    /// Number of an ordered item, `None` for bullets
+    pub number: Option<u64>,
+    pub task: Option<Task>,
+    pub content: Vec<Inline>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockKind {
+    Heading {
+        level: u8,
+        content: Vec<Inline>,
+    },
+    Paragraph(Vec<Inline>),
+    List(Vec<ListItem>),
+    Code {
+        info: String,
+        code: String,
+    },
+    Table {
+        alignments: Vec<Alignment>,
+        header: Vec<Vec<Inline>>,
+        rows: Vec<Vec<Vec<Inline>>>,
+    },
+    Quote(Vec<Block>),
+    Rule,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// 0-based source line the block starts on
+    pub line: usize,
+    pub kind: BlockKind,
+}
+
+/// One line of the source with where it sits in the whole document
+#[derive(Clone, Copy)]
+struct SourceLine<'a> {
+    number: usize,
+    /// Byte offset of `text` in the document
+    offset: usize,
+    text: &'a str,
+}
+
+/// Parses the subset of Markdown the preview and the HTML export render:
+/// ATX headings, paragraphs, nested and task lists, fenced code, pipe
+/// tables, block quotes and rules
+pub fn parse(text: &str) -> Vec<Block> {
+    let mut offset = 0;
+    let lines: Vec<SourceLine> = text
+        .split('\n')
+        .enumerate()
+        .map(|(number, line)| {
+            let source = SourceLine {
+                number,
+                offset,
+                text: line.strip_suffix('\r').unwrap_or(line),
+            };
+            offset += line.len() + 1;
+            source
+        })
+        .collect();
+    parse_lines(&lines)
+}
+
+fn parse_lines(lines: &[SourceLine]) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.text.trim_start();
+        let start = line.number;
+
+        if trimmed.is_empty() {
+            i += 1;
+        } else if let Some((marker, len)) = fence(trimmed) {
+            let info = trimmed[len..].trim().to_string();
+            let mut code = Vec::new();
+            i += 1;
+            while i < lines.len() {
+                let text = lines[i].text.trim_start();
+                i += 1;
+                if fence(text).is_some_and(|(m, l)| m == marker && l >= len)
+                    && text.trim_start_matches(marker as char).trim().is_empty()
+                {
+                    break;
+                }
+                code.push(lines[i - 1].text);
+            }
+            blocks.push(Block {
+                line: start,
+                kind: BlockKind::Code {
+                    info,
+                    code: code.join("\n"),
+                },
+            });
+        } else if let Some((level, content)) = heading(trimmed) {
+            blocks.push(Block {
+                line: start,
+                kind: BlockKind::Heading {
+                    level,
+                    content: parse_inline(content),
+                },
+            });
+            i += 1;
+        } else if is_rule(trimmed) {
+            blocks.push(Block {
+                line: start,
+                kind: BlockKind::Rule,
+            });
+            i += 1;
+        } else if trimmed.starts_with('>') {
+            let mut inner = Vec::new();
+            while i < lines.len() && lines[i].text.trim_start().starts_with('>') {
+                let line = lines[i];
+                let indent = line.text.len() - line.text.trim_start().len();
+                let mut rest = &line.text[indent + 1..];
+                let mut skipped = indent + 1;
+                if rest.starts_with(' ') {
+                    rest = &rest[1..];
+                    skipped += 1;
+                }
+                inner.push(SourceLine {
+                    number: line.number,
+                    offset: line.offset + skipped,
+                    text: rest,
+                });
+                i += 1;
+            }
+            blocks.push(Block {
+                line: start,
+                kind: BlockKind::Quote(parse_lines(&inner)),
+            });
+        } else if let Some(alignments) = lines
+            .get(i + 1)
+            .filter(|_| line.text.contains('|'))
+            .and_then(|next| table_alignments(next.text))
+        {
+            let header = table_cells(line.text);
+            i += 2;
+            let mut rows = Vec::new();
+            while i < lines.len() && lines[i].text.contains('|') && !lines[i].text.trim().is_empty()
+            {
+                rows.push(table_cells(lines[i].text));
+                i += 1;
+            }
+            blocks.push(Block {
+                line: start,
+                kind: BlockKind::Table {
+                    alignments,
+                    header,
+                    rows,
+                },
+            });
+        } else if list_marker(line.text).is_some() {
+            let (items, next) = parse_list(lines, i);
+            blocks.push(Block {
+                line: start,
+                kind: BlockKind::List(items),
+            });
+            i = next;
+        } else {
+            let mut text = vec![trimmed];
+            i += 1;
+            while i < lines.len() && !starts_block(lines[i].text) {
+                text.push(lines[i].text.trim());
+                i += 1;
+            }
+            blocks.push(Block {
+                line: start,
+                kind: BlockKind::Paragraph(parse_inline(&text.join(" "))),
+            });
+        }
+    }
+
+    blocks
+}
+
+/// Fence character and length if `text` opens or closes a code fence
+fn fence(text: &str) -> Option<(u8, usize)> {
+    let marker = *text.as_bytes().first()?;
+    if marker != b'`' && marker != b'~' {
+        return None;
+    }
+    let len = text.bytes().take_while(|&b| b == marker).count();
+    (len >= 3).then_some((marker, len))
+}
+
+fn heading(text: &str) -> Option<(u8, &str)> {
+    let level = text.bytes().take_while(|&b| b == b'#').count();
+    let rest = &text[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    // Closing hashes are decoration
+    let content = rest.trim().trim_end_matches('#').trim_end();
+    Some((level as u8, content))
+}
+
+/// `---`, `***` or `___`, possibly with spaces in between
+fn is_rule(text: &str) -> bool {
+    let marks: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3 && matches!(marks[0], '-' | '*' | '_') && marks.iter().all(|&c| c == marks[0])
+}
+
+fn starts_block(text: &str) -> bool {
+    let trimmed = text.trim_start();
+    trimmed.is_empty()
+        || fence(trimmed).is_some()
+        || heading(trimmed).is_some()
+        || is_rule(trimmed)
+        || trimmed.starts_with('>')
+        || list_marker(text).is_some()
+}
+
+/// Indent, marker length and number (for ordered items) of a list item
+/// line
+fn list_marker(text: &str) -> Option<(usize, usize, Option<u64>)> {
+    let indent = text.len() - text.trim_start().len();
+    let rest = &text[indent..];
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    let (len, number) = match rest.as_bytes().get(digits) {
+        Some(b'.' | b')') if (1..=9).contains(&digits) => (digits + 1, rest[..digits].parse().ok()),
+        Some(b'-' | b'*' | b'+') if digits == 0 => (1, None),
+        _ => return None,
+    };
+    let after = &rest[len..];
+    (after.is_empty() || after.starts_with(' ')).then_some((indent, len, number))
+}
+
+fn parse_list(lines: &[SourceLine], mut i: usize) -> (Vec<ListItem>, usize) {
+    let mut items: Vec<ListItem> = Vec::new();
+    let mut indents: Vec<usize> = Vec::new();
+    let mut content = String::new();
+
+    let finish = |items: &mut Vec<ListItem>, content: &mut String| {
+        if let Some(item) = items.last_mut() {
+            item.content = parse_inline(content);
+        }
+        content.clear();
+    };
+
+    while i < lines.len() {
+        let line = lines[i];
+        if let Some((indent, len, number)) = list_marker(line.text) {
+            // A bullet list right after a numbered one (or the other way
+            // round) is a separate list
+            let first = items
+                .first()
+                .map(|item| (indents[0], item.number.is_some()));
+            if first.is_some_and(|(first_indent, ordered)| {
+                indent <= first_indent && number.is_some() != ordered
+            }) {
+                break;
+            }
+            finish(&mut items, &mut content);
+            while indents.last().is_some_and(|&top| top > indent) {
+                indents.pop();
+            }
+            if indents.last() != Some(&indent) {
+                indents.push(indent);
+            }
+
+            let body_start = (indent + len + 1).min(line.text.len());
+            let mut body = &line.text[body_start..];
+            let mut task = None;
+            let bytes = body.as_bytes();
+            if bytes.len() >= 3 && bytes[0] == b'[' && bytes[2] == b']' {
+                let checked = match bytes[1] {
+                    b' ' => Some(false),
+                    b'x' | b'X' => Some(true),
+                    _ => None,
+                };
+                if let Some(checked) = checked.filter(|_| matches!(bytes.get(3), None | Some(b' ')))
+                {
+                    task = Some(Task {
+                        checked,
+                        offset: line.offset + body_start + 1,
+                    });
+                    body = body[3..].trim_start();
+                }
+            }
+
+            content.push_str(body.trim());
+            items.push(ListItem {
+                line: line.number,
+                depth: indents.len() - 1,
+                number,
+                task,
+                content: Vec::new(),
+            });
+            i += 1;
+        } else if !line.text.trim().is_empty() && !starts_block(line.text) {
+            content.push(' ');
+            content.push_str(line.text.trim());
+            i += 1;
+        } else if line.text.trim().is_empty()
+            && lines
+                .get(i + 1)
+                .is_some_and(|next| list_marker(next.text).is_some())
+        {
+            // A blank line between items keeps the list going
+            i += 1;
+        } else {
+            break;
+        }
+    }
+
+    finish(&mut items, &mut content);
+    (items, i)
+}
+
+fn table_cells(line: &str) -> Vec<Vec<Inline>> {
+    split_row(line)
+        .into_iter()
+        .map(|cell| parse_inline(&cell.trim().replace("\\|", "|")))
+        .collect()
+}
+
+/// Cells of a `| a | b |` row. Escaped pipes stay in the cell.
+fn split_row(line: &str) -> Vec<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    let mut cells = Vec::new();
+    let mut start = 0;
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'|' => {
+                cells.push(&line[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    cells.push(&line[start..]);
+    cells
+}
+
+/// Column alignments if `line` is a table delimiter row like `|:--|--:|`
+fn table_alignments(line: &str) -> Option<Vec<Alignment>> {
+    if !line.contains('-') {
+        return None;
+    }
+    split_row(line)
+        .into_iter()
+        .map(|cell| {
+            let cell = cell.trim();
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || !dashes.bytes().all(|b| b == b'-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => Alignment::Center,
+                (false, true) => Alignment::Right,
+                _ => Alignment::Left,
+            })
+        })
+        .collect()
+}
+
+/// Splits inline Markdown into styled runs: code spans, strong, emphasis,
+/// strikethrough, links, images (shown as their alt text) and autolinks
+pub fn parse_inline(text: &str) -> Vec<Inline> {
+    let mut runs = Vec::new();
+    inline_into(text, &Inline::default(), &mut runs);
+    runs
+}
+
+fn push_text(runs: &mut Vec<Inline>, style: &Inline, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match runs.last_mut() {
+        Some(last) if last.same_style(style) => last.text.push_str(text),
+        _ => runs.push(Inline {
+            text: text.to_string(),
+            ..style.clone()
+        }),
+    }
+}
+
+fn inline_into(text: &str, style: &Inline, runs: &mut Vec<Inline>) {
+    let bytes = text.as_bytes();
+    let mut plain_start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let rest = &text[i..];
+        let c = bytes[i];
+        // The inner text, style and length of a construct starting at `i`
+        let found: Option<(&str, Inline, usize)> = match c {
+            b'\\' if bytes.get(i + 1).is_some_and(u8::is_ascii_punctuation) => {
+                push_text(runs, style, &text[plain_start..i]);
+                push_text(runs, style, &text[i + 1..i + 2]);
+                i += 2;
+                plain_start = i;
+                continue;
+            }
+            b'`' => {
+                let ticks = rest.bytes().take_while(|&b| b == b'`').count();
+                let fence = &rest[..ticks];
+                rest[ticks..].find(fence).map(|end| {
+                    let code = &rest[ticks..ticks + end];
+                    let code = code
+                        .strip_prefix(' ')
+                        .and_then(|c| c.strip_suffix(' '))
+                        .unwrap_or(code);
+                    let len = ticks + end + ticks;
+                    (
+                        code,
+                        Inline {
+                            code: true,
+                            ..style.clone()
+                        },
+                        len,
+                    )
+                })
+            }
+            b'*' | b'_' | b'~' => {
+                let run = rest.bytes().take_while(|&b| b == c).count();
+                let width = if c == b'~' { 2 } else { run.min(2) };
+                let intraword = c == b'_' && i > 0 && bytes[i - 1].is_ascii_alphanumeric();
+                let delimiter = &rest[..width.min(run)];
+                let opens = run >= width
+                    && !intraword
+                    && rest[width..].starts_with(|ch: char| !ch.is_whitespace());
+                opens
+                    .then(|| rest[width..].find(delimiter))
+                    .flatten()
+                    .filter(|&end| end > 0)
+                    .map(|end| {
+                        let inner = &rest[width..width + end];
+                        let mut inner_style = style.clone();
+                        match (c, width) {
+                            (b'~', _) => inner_style.strike = true,
+                            (_, 2) => inner_style.strong = true,
+                            _ => inner_style.emphasis = true,
+                        }
+                        (inner, inner_style, width + end + width)
+                    })
+            }
+            b'[' | b'!' => {
+                let open = usize::from(c == b'!');
+                link(&rest[open..]).map(|(label, url, len)| {
+                    (
+                        label,
+                        Inline {
+                            link: Some(url.to_string()),
+                            ..style.clone()
+                        },
+                        open + len,
+                    )
+                })
+            }
+            b'<' => rest.find('>').and_then(|end| {
+                let url = &rest[1..end];
+                (url.starts_with("http://") || url.starts_with("https://") || url.contains('@'))
+                    .then(|| {
+                        (
+                            url,
+                            Inline {
+                                link: Some(url.to_string()),
+                                ..style.clone()
+                            },
+                            end + 1,
+                        )
+                    })
+            }),
+            _ => None,
+        };
+
+        match found {
+            Some((inner, inner_style, len)) => {
+                push_text(runs, style, &text[plain_start..i]);
+                if inner_style.code {
+                    push_text(runs, &inner_style, inner);
+                } else {
+                    inline_into(inner, &inner_style, runs);
+                }
+                i += len;
+                plain_start = i;
+            }
+            None => i += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+
+    push_text(runs, style, &text[plain_start..]);
+}
+
+/// Label, URL and length of `[label](url)` at the start of `text`
+fn link(text: &str) -> Option<(&str, &str, usize)> {
+    let text_rest = text.strip_prefix('[')?;
+    let mut depth = 1;
+    let close = text_rest.char_indices().find_map(|(i, c)| {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ => {}
+        }
+        (depth == 0).then_some(i)
+    })?;
+    let after = &text_rest[close + 1..];
+    let target = after.strip_prefix('(')?;
+    let end = target.find(')')?;
+    // A title after the URL is not shown
+    let url = target[..end].split_whitespace().next().unwrap_or("");
+    Some((&text_rest[..close], url, 1 + close + 1 + 1 + end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(runs: &[Inline]) -> String {
+        runs.iter().map(|run| run.text.as_str()).collect()
+    }
+
+    fn list_items(text: &str) -> Vec<ListItem> {
+        match parse(text).remove(0).kind {
+            BlockKind::List(items) => items,
+            other => panic!("expected a list, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn headings() {
+        let blocks = parse("# One\n### Three ###\n####### seven\n#nospace");
+        assert_eq!(
+            blocks[0].kind,
+            BlockKind::Heading {
+                level: 1,
+                content: parse_inline("One"),
+            }
+        );
+        assert_eq!(
+            blocks[1].kind,
+            BlockKind::Heading {
+                level: 3,
+                content: parse_inline("Three"),
+            }
+        );
+        // Too many hashes or no space make a paragraph
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(
+            blocks[2].kind,
+            BlockKind::Paragraph(parse_inline("####### seven #nospace"))
+        );
+    }
+
+    #[test]
+    fn nested_lists() {
+        let items = list_items("- one\n  - two\n    - three\n  - four\n- five\n  continued");
+        let depths: Vec<usize> = items.iter().map(|item| item.depth).collect();
+        assert_eq!(depths, [0, 1, 2, 1, 0]);
+        assert_eq!(plain(&items[4].content), "five continued");
+        assert_eq!(items[3].line, 3);
+    }
+
+    #[test]
+    fn ordered_and_bullet_lists_are_separate() {
+        let blocks = parse("1. one\n2) two\n- three");
+        assert_eq!(blocks.len(), 2);
+        let BlockKind::List(items) = &blocks[0].kind else {
+            panic!("expected a list");
+        };
+        let numbers: Vec<Option<u64>> = items.iter().map(|item| item.number).collect();
+        assert_eq!(numbers, [Some(1), Some(2)]);
+        assert_eq!(blocks[1].line, 2);
+    }
+
+    #[test]
+    fn task_offsets_point_into_the_source() {
+        let text = "é\n- [ ] one\n  - [x] two\n- [y] not a task";
+        let items = list_items(&text[text.find('-').unwrap()..]);
+        assert_eq!(items[2].task, None);
+
+        let blocks = parse(text);
+        let BlockKind::List(items) = &blocks[1].kind else {
+            panic!("expected a list");
+        };
+        let tasks: Vec<Task> = items.iter().filter_map(|item| item.task).collect();
+        assert_eq!(tasks.len(), 2);
+        assert!(!tasks[0].checked && tasks[1].checked);
+        assert_eq!(&text[tasks[0].offset..tasks[0].offset + 1], " ");
+        assert_eq!(&text[tasks[1].offset..tasks[1].offset + 1], "x");
+        assert_eq!(&text[tasks[1].offset - 1..tasks[1].offset + 2], "[x]");
+    }
+
+    #[test]
+    fn task_offsets_inside_quotes() {
+        let text = "intro\n\n> - [ ] quoted";
+        let blocks = parse(text);
+        let BlockKind::Quote(inner) = &blocks[1].kind else {
+            panic!("expected a quote");
+        };
+        let BlockKind::List(items) = &inner[0].kind else {
+            panic!("expected a list");
+        };
+        let task = items[0].task.unwrap();
+        assert_eq!(&text[task.offset - 1..task.offset + 2], "[ ]");
+    }
+
+    #[test]
+    fn fences_keep_their_content() {
+        let blocks = parse("```rust\nfn main() {}\n~~~\n# not a heading\n````\nafter");
+        assert_eq!(
+            blocks[0].kind,
+            BlockKind::Code {
+                info: "rust".to_string(),
+                code: "fn main() {}\n~~~\n# not a heading".to_string(),
+            }
+        );
+        assert_eq!(blocks[1].line, 5);
+
+        // An unclosed fence runs to the end
+        let blocks = parse("~~~\nopen");
+        assert_eq!(
+            blocks[0].kind,
+            BlockKind::Code {
+                info: String::new(),
+                code: "open".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn tables() {
+        let blocks = parse("| a | b | c |\n|:--|:-:|--:|\n| 1 | 2 \\| 3 | `x` |\n\nafter");
+        let BlockKind::Table {
+            alignments,
+            header,
+            rows,
+        } = &blocks[0].kind
+        else {
+            panic!("expected a table");
+        };
+        assert_eq!(
+            alignments,
+            &[Alignment::Left, Alignment::Center, Alignment::Right]
+        );
+        assert_eq!(plain(&header[2]), "c");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(plain(&rows[0][1]), "2 | 3");
+        assert!(rows[0][2][0].code);
+        assert_eq!(blocks.len(), 2);
+
+        // No delimiter row, no table
+        assert!(matches!(
+            parse("a | b\nc | d")[0].kind,
+            BlockKind::Paragraph(_)
+        ));
+    }
+
+    #[test]
+    fn inline_styles() {
+        let runs = parse_inline("a **b _c_** `d *e*` ~~f~~ [g](http://h \"t\") snake_case_name");
+        let styled = |text: &str| runs.iter().find(|run| run.text == text).unwrap();
+        assert!(styled("b ").strong && !styled("b ").emphasis);
+        assert!(styled("c").strong && styled("c").emphasis);
+        assert!(styled("d *e*").code);
+        assert!(styled("f").strike);
+        assert_eq!(styled("g").link.as_deref(), Some("http://h"));
+        assert_eq!(plain(&runs), "a b c d *e* f g snake_case_name");
+    }
+
+    #[test]
+    fn inline_escapes_and_autolinks() {
+        let runs = parse_inline(r"\*not em\* <https://example.com> <not a link> ![alt](x.png)");
+        assert_eq!(
+            plain(&runs),
+            "*not em* https://example.com <not a link> alt"
+        );
+        assert!(runs.iter().all(|run| !run.emphasis));
+        let link = runs.iter().find(|run| run.text == "https://example.com");
+        assert_eq!(
+            link.and_then(|run| run.link.as_deref()),
+            Some("https://example.com")
+        );
+    }
+
+    #[test]
+    fn quotes_and_rules() {
+        let blocks = parse("> # Title\n> text\n\n---\n* * *");
+        let BlockKind::Quote(inner) = &blocks[0].kind else {
+            panic!("expected a quote");
+        };
+        assert!(matches!(inner[0].kind, BlockKind::Heading { level: 1, .. }));
+        assert_eq!(inner[1].line, 1);
+        assert_eq!(blocks[1].kind, BlockKind::Rule);
+        assert_eq!(blocks[2].kind, BlockKind::Rule);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::cli;
+use crate::document::Document;
+
+/// Entries kept in each direction of the navigation history
+const MAX_LOCATIONS: usize = 100;
+
+/// A caret position in one of the open documents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub doc_id: u64,
+    /// Char index of the caret
+    pub cursor: usize,
+}
+
+/// Back/forward stacks of the places big jumps (go to line, search hits,
+/// opening files) left from
+#[derive(Debug, Default)]
+pub struct NavHistory {
+    back: Vec<Location>,
+    forward: Vec<Location>,
+}
+
+impl NavHistory {
+    /// Remembers `from` before a jump. A new jump drops the forward stack,
+    /// like in a browser.
+    pub fn record(&mut self, from: Location) {
+        if self.back.last() == Some(&from) {
+            return;
+        }
+        self.back.push(from);
+        if self.back.len() > MAX_LOCATIONS {
+            self.back.remove(0);
+        }
+        self.forward.clear();
+    }
+
+    /// The location to go back to from `current`, skipping locations in
+    /// documents for which `exists` returns false (closed tabs)
+    pub fn back(&mut self, current: Location, exists: impl Fn(u64) -> bool) -> Option<Location> {
+        let target = pop_valid(&mut self.back, &exists)?;
+        self.forward.push(current);
+        Some(target)
+    }
+
+    pub fn forward(&mut self, current: Location, exists: impl Fn(u64) -> bool) -> Option<Location> {
+        let target = pop_valid(&mut self.forward, &exists)?;
+        self.back.push(current);
+        Some(target)
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        !self.back.is_empty()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        !self.forward.is_empty()
+    }
+}
+
+fn pop_valid(stack: &mut Vec<Location>, exists: &impl Fn(u64) -> bool) -> Option<Location> {
+    while let Some(location) = stack.pop() {
+        if exists(location.doc_id) {
+            return Some(location);
+        }
+    }
+    None
+}
+
+/// State of the Go to Line dialog
+#[derive(Debug, Default)]
+pub struct GoToState {
+    pub open: bool,
+    pub input: String,
+    pub error: Option<String>,
+    /// Give the input field keyboard focus on the next frame
+    pub focus_input: bool,
+}
+
+/// Char index in `doc` for `line`, `line:col` or a percentage like `50%`
+pub fn resolve_go_to(input: &str, doc: &Document) -> Option<usize> {
+    let input = input.trim();
+
+    if let Some(percent) = input.strip_suffix('%') {
+        let percent: f32 = percent
+            .trim()
+            .parse()
+            .ok()
+            .filter(|p| (0.0..=100.0).contains(p))?;
+        let last_line = doc.text.matches('\n').count();
+        let line = (percent / 100.0 * last_line as f32).round() as usize + 1;
+        return Some(doc.char_index_at(line, 1));
+    }
+
+    let (line, column) = cli::parse_position(input)?;
+    Some(doc.char_index_at(line, column.unwrap_or(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(text: &str) -> Document {
+        let mut doc = Document::new();
+        doc.text = text.to_string();
+        doc
+    }
+
+    fn at(doc_id: u64, cursor: usize) -> Location {
+        Location { doc_id, cursor }
+    }
+
+    #[test]
+    fn percentages_pick_a_line_start() {
+        let doc = doc("one\ntwo\nthree\nfour\nfive");
+        assert_eq!(resolve_go_to("0%", &doc), Some(0));
+        assert_eq!(resolve_go_to("50%", &doc), Some(8));
+        assert_eq!(resolve_go_to(" 100 % ", &doc), Some(19));
+        assert_eq!(resolve_go_to("150%", &doc), None);
+        assert_eq!(resolve_go_to("-5%", &doc), None);
+        assert_eq!(resolve_go_to("half%", &doc), None);
+    }
+
+    #[test]
+    fn line_and_column() {
+        let doc = doc("one\ntwo\nthree");
+        assert_eq!(resolve_go_to("1", &doc), Some(0));
+        assert_eq!(resolve_go_to("2", &doc), Some(4));
+        assert_eq!(resolve_go_to("2:3", &doc), Some(6));
+        assert_eq!(resolve_go_to("3:1", &doc), Some(8));
+        assert_eq!(resolve_go_to("0", &doc), None);
+        assert_eq!(resolve_go_to("2:0", &doc), None);
+        assert_eq!(resolve_go_to("two", &doc), None);
+    }
+
+    #[test]
+    fn positions_past_the_end_are_clamped() {
+        let doc = doc("one\ntwö\nthree");
+        // Columns stop at the end of their line, counted in chars
+        assert_eq!(resolve_go_to("2:99", &doc), Some(7));
+        assert_eq!(resolve_go_to("99", &doc), Some(13));
+        assert_eq!(resolve_go_to("99:5", &doc), Some(13));
+    }
+
+    #[test]
+    fn back_and_forward_retrace_jumps() {
+        let mut history = NavHistory::default();
+        history.record(at(1, 10));
+        history.record(at(1, 10));
+        history.record(at(1, 50));
+        assert_eq!(history.back(at(1, 90), |_| true), Some(at(1, 50)));
+        assert_eq!(history.back(at(1, 50), |_| true), Some(at(1, 10)));
+        assert_eq!(history.back(at(1, 10), |_| true), None);
+        assert_eq!(history.forward(at(1, 10), |_| true), Some(at(1, 50)));
+        assert_eq!(history.forward(at(1, 50), |_| true), Some(at(1, 90)));
+        assert!(!history.can_go_forward());
+    }
+
+    #[test]
+    fn a_new_jump_drops_the_forward_stack() {
+        let mut history = NavHistory::default();
+        history.record(at(1, 10));
+        history.record(at(1, 20));
+        history.back(at(1, 30), |_| true);
+        assert!(history.can_go_forward());
+        history.record(at(1, 5));
+        assert!(!history.can_go_forward());
+        assert_eq!(history.forward(at(1, 40), |_| true), None);
+    }
+
+    #[test]
+    fn closed_documents_are_skipped() {
+        let mut history = NavHistory::default();
+        history.record(at(1, 10));
+        history.record(at(2, 20));
+        history.record(at(2, 30));
+        assert_eq!(history.back(at(3, 0), |id| id != 2), Some(at(1, 10)));
+        assert!(!history.can_go_back());
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let mut history = NavHistory::default();
+        for cursor in 0..MAX_LOCATIONS + 20 {
+            history.record(at(1, cursor));
+        }
+        let mut current = at(1, usize::MAX);
+        let mut steps = 0;
+        while let Some(previous) = history.back(current, |_| true) {
+            current = previous;
+            steps += 1;
+        }
+        assert_eq!(steps, MAX_LOCATIONS);
+        // The oldest entries were the ones dropped
+        assert_eq!(current, at(1, 20));
+    }
+}
@@ -0,0 +1,427 @@
+use encoding_rs::WINDOWS_1252;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+use crate::settings::PdfSettings;
+
+const POINTS_PER_MM: f32 = 72.0 / 25.4;
+/// Columns a tab advances to
+const TAB_WIDTH: usize = 4;
+/// Header and footer text size relative to the body text
+const HEADER_SCALE: f32 = 0.8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageSize {
+    A4,
+    A5,
+    Letter,
+    Legal,
+}
+
+impl PageSize {
+    pub const ALL: &'static [PageSize] = &[
+        PageSize::A4,
+        PageSize::A5,
+        PageSize::Letter,
+        PageSize::Legal,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PageSize::A4 => "A4",
+            PageSize::A5 => "A5",
+            PageSize::Letter => "US Letter",
+            PageSize::Legal => "US Legal",
+        }
+    }
+
+    /// Width and height in points
+    fn dimensions(self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (595.0, 842.0),
+            PageSize::A5 => (420.0, 595.0),
+            PageSize::Letter => (612.0, 792.0),
+            PageSize::Legal => (612.0, 1008.0),
+        }
+    }
+}
+
+/// One of the standard PDF fonts, which every viewer has built in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PdfFont {
+    Courier,
+    Helvetica,
+    Times,
+}
+
+/// Glyph widths of Helvetica for the printable ASCII range, in 1/1000 em
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// Glyph widths of Times-Roman for the printable ASCII range, in 1/1000 em
+const TIMES_WIDTHS: [u16; 95] = [
+    250, 333, 408, 500, 500, 833, 778, 180, 333, 333, 500, 564, 250, 333, 250, 278, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 278, 278, 564, 564, 564, 444, 921, 722, 667, 667, 722, 611,
+    556, 722, 722, 333, 389, 722, 611, 889, 722, 722, 556, 722, 667, 556, 611, 722, 722, 944, 722,
+    722, 611, 333, 278, 333, 469, 500, 333, 444, 500, 444, 500, 444, 333, 500, 500, 278, 278, 500,
+    278, 778, 500, 500, 500, 500, 333, 389, 278, 500, 500, 722, 500, 500, 444, 480, 200, 480, 541,
+];
+
+impl PdfFont {
+    pub const ALL: &'static [PdfFont] = &[PdfFont::Courier, PdfFont::Helvetica, PdfFont::Times];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PdfFont::Courier => "Courier (monospace)",
+            PdfFont::Helvetica => "Helvetica",
+            PdfFont::Times => "Times",
+        }
+    }
+
+    fn base_font(self) -> &'static str {
+        match self {
+            PdfFont::Courier => "Courier",
+            PdfFont::Helvetica => "Helvetica",
+            PdfFont::Times => "Times-Roman",
+        }
+    }
+
+    /// Width of a WinAnsi-encoded byte in 1/1000 em. Accented letters and
+    /// symbols get a typical lowercase width.
+    fn glyph_width(self, byte: u8) -> u16 {
+        let table = match self {
+            PdfFont::Courier => return 600,
+            PdfFont::Helvetica => &HELVETICA_WIDTHS,
+            PdfFont::Times => &TIMES_WIDTHS,
+        };
+        match byte {
+            32..=126 => table[usize::from(byte - 32)],
+            _ => table[usize::from(b'o' - 32)],
+        }
+    }
+
+    fn text_width(self, text: &[u8], size: f32) -> f32 {
+        let units: u32 = text.iter().map(|&b| u32::from(self.glyph_width(b))).sum();
+        units as f32 * size / 1000.0
+    }
+}
+
+/// Encodes `text` for a font using WinAnsiEncoding. Characters it cannot
+/// represent become `?`.
+fn win_ansi(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for ch in text.chars() {
+        if ch.is_ascii() {
+            bytes.push(ch as u8);
+            continue;
+        }
+        let mut buffer = [0; 4];
+        let (encoded, _, had_errors) = WINDOWS_1252.encode(ch.encode_utf8(&mut buffer));
+        match *encoded {
+            [byte] if !had_errors => bytes.push(byte),
+            _ => bytes.push(b'?'),
+        }
+    }
+    bytes
+}
+
+/// A PDF string literal, with everything outside printable ASCII escaped
+fn pdf_string(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() + 2);
+    out.push('(');
+    for &b in bytes {
+        match b {
+            b'(' | b')' | b'\\' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            32..=126 => out.push(b as char),
+            _ => {
+                let _ = write!(out, "\\{:03o}", b);
+            }
+        }
+    }
+    out.push(')');
+    out
+}
+
+fn expand_tabs(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut column = 0;
+    for ch in line.chars() {
+        if ch == '\t' {
+            let spaces = TAB_WIDTH - column % TAB_WIDTH;
+            out.push_str(&" ".repeat(spaces));
+            column += spaces;
+        } else {
+            out.push(ch);
+            column += 1;
+        }
+    }
+    out
+}
+
+/// Breaks an encoded line into pieces no wider than `max_width`, at spaces
+/// where possible
+fn wrap(line: &[u8], font: PdfFont, size: f32, max_width: f32) -> Vec<Vec<u8>> {
+    let mut rows = Vec::new();
+    let mut rest = line;
+    while font.text_width(rest, size) > max_width {
+        // The longest prefix that fits, but at least one character
+        let mut width = 0.0;
+        let mut fits = 0;
+        for &b in rest {
+            width += f32::from(font.glyph_width(b)) * size / 1000.0;
+            if width > max_width {
+                break;
+            }
+            fits += 1;
+        }
+        let fits = fits.max(1);
+        let split = rest[..fits]
+            .iter()
+            .rposition(|&b| b == b' ')
+            .filter(|&space| space > 0)
+            .map_or(fits, |space| space + 1);
+        rows.push(rest[..split].to_vec());
+        rest = &rest[split..];
+    }
+    rows.push(rest.to_vec());
+    rows
+}
+
+fn number(value: f32) -> String {
+    let text = format!("{:.2}", value);
+    match text.trim_end_matches('0').trim_end_matches('.') {
+        "" | "-" => "0".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Lays out `text` on pages and returns the PDF file. With a header and
+/// footer each page shows `title` and `date` at the top and its page number
+/// at the bottom.
+pub fn render(title: &str, date: &str, text: &str, settings: &PdfSettings) -> Vec<u8> {
+    let font = settings.font;
+    let size = settings.font_size.clamp(4.0, 72.0);
+    let leading = size * 1.25;
+    let (page_width, page_height) = settings.page_size.dimensions();
+    let margin = (settings.margin_mm * POINTS_PER_MM).clamp(0.0, page_width / 3.0);
+    let small = size * HEADER_SCALE;
+    let reserved = if settings.header_footer {
+        small * 2.5
+    } else {
+        0.0
+    };
+    let top = page_height - margin - reserved;
+    let bottom = margin + reserved;
+    let lines_per_page = (((top - bottom) / leading).floor() as usize).max(1);
+    let max_width = page_width - 2.0 * margin;
+
+    let rows: Vec<Vec<u8>> = text
+        .split('\n')
+        .flat_map(|line| {
+            let line = win_ansi(&expand_tabs(line.trim_end_matches('\r')));
+            wrap(&line, font, size, max_width)
+        })
+        .collect();
+    let pages: Vec<&[Vec<u8>]> = rows.chunks(lines_per_page).collect();
+    let page_count = pages.len();
+
+    let mut contents = Vec::with_capacity(page_count);
+    for (index, page) in pages.iter().enumerate() {
+        let mut content = String::new();
+        let _ = write!(
+            content,
+            "BT /F1 {} Tf {} TL {} {} Td",
+            number(size),
+            number(leading),
+            number(margin),
+            number(top - size)
+        );
+        for row in page.iter() {
+            let _ = write!(content, " {} Tj T*", pdf_string(row));
+        }
+        content.push_str(" ET\n");
+
+        if settings.header_footer {
+            let title = win_ansi(title);
+            let date = win_ansi(date);
+            let footer = win_ansi(&format!("Page {} of {}", index + 1, page_count));
+            let header_y = page_height - margin - small;
+            let rule_y = header_y - small * 0.6;
+            let footer_y = margin;
+            let date_x = page_width - margin - font.text_width(&date, small);
+            let footer_x = (page_width - font.text_width(&footer, small)) / 2.0;
+            let _ = writeln!(
+                content,
+                "0.35 g BT /F1 {small} Tf {mx} {hy} Td {title} Tj ET \
+                 BT /F1 {small} Tf {dx} {hy} Td {date} Tj ET \
+                 BT /F1 {small} Tf {fx} {fy} Td {footer} Tj ET \
+                 0.6 G 0.5 w {mx} {ry} m {rx} {ry} l S",
+                small = number(small),
+                mx = number(margin),
+                hy = number(header_y),
+                title = pdf_string(&title),
+                dx = number(date_x),
+                date = pdf_string(&date),
+                fx = number(footer_x),
+                fy = number(footer_y),
+                footer = pdf_string(&footer),
+                ry = number(rule_y),
+                rx = number(page_width - margin),
+            );
+        }
+        contents.push(content);
+    }
+
+    // Objects: 1 catalog, 2 page tree, 3 font, 4 info, then a page and its
+    // content stream for each page
+    let page_ids: Vec<usize> = (0..page_count).map(|i| 5 + i * 2).collect();
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            page_ids
+                .iter()
+                .map(|id| format!("{id} 0 R"))
+                .collect::<Vec<_>>()
+                .join(" "),
+            page_count
+        ),
+        format!(
+            "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+            font.base_font()
+        ),
+        format!(
+            "<< /Title {} /Producer (Rusty Notepad) >>",
+            pdf_string(&win_ansi(title))
+        ),
+    ];
+    for (content, page_id) in contents.iter().zip(&page_ids) {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+             /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            number(page_width),
+            number(page_height),
+            page_id + 1
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            content.len(),
+            content
+        ));
+    }
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        let _ = write!(pdf, "{} 0 obj\n{}\nendobj\n", index + 1, object);
+    }
+    let xref = pdf.len();
+    let _ = write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(pdf, "{:010} 00000 n ", offset);
+    }
+    let _ = write!(
+        pdf,
+        "trailer\n<< /Size {} /Root 1 0 R /Info 4 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    );
+    pdf.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that every xref entry points at its object and returns the
+    /// page count from the page tree
+    fn check_structure(pdf: &[u8]) -> usize {
+        let text = String::from_utf8(pdf.to_vec()).unwrap();
+        assert!(text.starts_with("%PDF-1.4\n"));
+        assert!(text.ends_with("%%EOF\n"));
+
+        let startxref = text.rfind("startxref\n").unwrap();
+        let xref: usize = text[startxref + 10..]
+            .lines()
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(text[xref..].starts_with("xref\n"));
+
+        let mut lines = text[xref..].lines().skip(1);
+        let size: usize = lines.next().unwrap()[2..].parse().unwrap();
+        assert_eq!(lines.next(), Some("0000000000 65535 f "));
+        for id in 1..size {
+            let entry = lines.next().unwrap();
+            assert_eq!(entry.len(), 19);
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(text[offset..].starts_with(&format!("{id} 0 obj\n")));
+        }
+        assert!(text.contains(&format!("/Size {size} ")));
+
+        let count = text.find("/Count ").unwrap();
+        let pages = text[count + 7..]
+            .split(' ')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(text.matches("/Type /Page ").count(), pages);
+        pages
+    }
+
+    #[test]
+    fn empty_text_is_one_page() {
+        let pdf = render("empty", "2024-01-01", "", &PdfSettings::default());
+        assert_eq!(check_structure(&pdf), 1);
+    }
+
+    #[test]
+    fn long_text_spans_pages() {
+        let text = (1..=200)
+            .map(|n| format!("line {n}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let settings = PdfSettings::default();
+        let pdf = render("long", "2024-01-01", &text, &settings);
+        let pages = check_structure(&pdf);
+        assert!(pages > 1);
+        let footer = format!("(Page {pages} of {pages})");
+        assert!(String::from_utf8_lossy(&pdf).contains(&footer));
+
+        let smaller = PdfSettings {
+            font_size: 5.0,
+            header_footer: false,
+            ..settings
+        };
+        let pdf = render("long", "2024-01-01", &text, &smaller);
+        assert!(check_structure(&pdf) < pages);
+        assert!(!String::from_utf8_lossy(&pdf).contains("(Page 1 of"));
+    }
+
+    #[test]
+    fn text_outside_ascii_is_escaped() {
+        let pdf = render(
+            "café (1)",
+            "2024-01-01",
+            "naïve → \\",
+            &PdfSettings::default(),
+        );
+        check_structure(&pdf);
+        let text = String::from_utf8(pdf).unwrap();
+        assert!(text.contains("/Title (caf\\351 \\(1\\))"));
+        assert!(text.contains("(na\\357ve ? \\\\)"));
+    }
+}
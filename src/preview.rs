@@ -0,0 +1,384 @@
+use eframe::egui::epaint::text::cursor::PCursor;
+use eframe::egui::{self, Galley, RichText};
+use std::collections::HashMap;
+
+use crate::document::Document;
+use crate::language::Language;
+use crate::markdown::{self, Alignment, Block, BlockKind, Inline, ListItem};
+use crate::syntax::{self, Highlighter, Span};
+use crate::theme::CatppuccinPalette;
+
+/// Indentation per nesting level of a list
+const LIST_INDENT: f32 = 18.0;
+/// Heading sizes relative to the body text, from `#` to `######`
+const HEADING_SCALE: [f32; 6] = [1.8, 1.5, 1.3, 1.15, 1.05, 1.0];
+
+/// The rendered Markdown next to the editor, and what keeps the two
+/// scrolled to the same place
+#[derive(Default)]
+pub struct PreviewState {
+    /// Document id and revision `blocks` were parsed from
+    parsed: Option<(u64, u64)>,
+    blocks: Vec<Block>,
+    /// Lines in the parsed text
+    line_count: usize,
+    /// Syntax colors of each fenced code block, by its source line, so
+    /// blocks are highlighted once per parse rather than every frame
+    code_spans: HashMap<usize, Vec<Span>>,
+    /// Source line and y offset of each rendered block, as of the last frame
+    block_tops: Vec<(usize, f32)>,
+    content_height: f32,
+    last_offset: f32,
+    /// Source line at the top of the editor (fractional within the line),
+    /// as of the last frame
+    pub editor_top_line: f32,
+    /// Line the editor should scroll to because the preview was scrolled
+    pub scroll_editor_to: Option<f32>,
+}
+
+impl PreviewState {
+    /// Shows the preview for `doc`. Returns the revision the preview was
+    /// parsed from and the byte offset of a task box that was clicked, so
+    /// the caller can toggle it in the source.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        doc: &Document,
+        font_size: f32,
+        palette: &CatppuccinPalette,
+    ) -> Option<(u64, usize)> {
+        // Unlike the statistics this is never throttled: task offsets must
+        // point into the text as it is now
+        let key = (doc.id, doc.revision);
+        if self.parsed != Some(key) {
+            self.blocks = markdown::parse(&doc.text);
+            self.line_count = doc.text.matches('\n').count() + 1;
+            self.code_spans.clear();
+            self.parsed = Some(key);
+        }
+        let line_count = self.line_count;
+        let driven_by_preview = ui.ui_contains_pointer();
+
+        let mut scroll_area = egui::ScrollArea::vertical()
+            .id_salt("markdown_preview")
+            .auto_shrink([false, false]);
+        if !driven_by_preview {
+            let target = interpolate(
+                &self.block_tops,
+                (line_count as f32, self.content_height),
+                self.editor_top_line,
+                |(line, y)| (*line as f32, *y),
+            );
+            if (target - self.last_offset).abs() > 0.5 {
+                scroll_area = scroll_area.vertical_scroll_offset(target);
+            }
+        }
+
+        let mut renderer = Renderer {
+            font_size,
+            palette,
+            code_spans: &mut self.code_spans,
+            block_tops: Vec::new(),
+            top: 0.0,
+            toggled: None,
+        };
+        let output = scroll_area.show(ui, |ui| {
+            renderer.top = ui.min_rect().top();
+            for block in &self.blocks {
+                renderer.block(ui, block);
+            }
+            ui.min_rect().height()
+        });
+
+        self.block_tops = renderer.block_tops;
+        self.content_height = output.inner;
+        let offset = output.state.offset.y;
+        if driven_by_preview && (offset - self.last_offset).abs() > 0.5 {
+            self.scroll_editor_to = Some(interpolate(
+                &self.block_tops,
+                (line_count as f32, self.content_height),
+                offset,
+                |(line, y)| (*y, *line as f32),
+            ));
+        }
+        self.last_offset = offset;
+        renderer.toggled.map(|offset| (doc.revision, offset))
+    }
+}
+
+/// Source line at `scroll_top` (relative to the galley), with the fraction
+/// of that line scrolled past
+pub fn top_line(galley: &Galley, scroll_top: f32) -> f32 {
+    let line = galley
+        .cursor_from_pos(egui::vec2(0.0, scroll_top.max(0.0)))
+        .pcursor
+        .paragraph;
+    let (start, end) = line_span(galley, line);
+    let fraction = if end > start {
+        ((scroll_top - start) / (end - start)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    line as f32 + fraction
+}
+
+/// Y offset in `galley` of a fractional source line
+pub fn line_offset(galley: &Galley, line: f32) -> f32 {
+    let index = line.floor().max(0.0) as usize;
+    let (start, end) = line_span(galley, index);
+    start + (end - start) * line.fract()
+}
+
+/// Top and bottom of a source line in `galley`, across all its rows
+fn line_span(galley: &Galley, line: usize) -> (f32, f32) {
+    let top = |paragraph| {
+        let pcursor = PCursor {
+            paragraph,
+            offset: 0,
+            prefer_next_row: false,
+        };
+        galley.pos_from_pcursor(pcursor).top()
+    };
+    let start = top(line);
+    // Past the last line the galley reports its end, which is not below
+    let end = top(line + 1);
+    (
+        start,
+        if end > start {
+            end
+        } else {
+            galley.rect.bottom()
+        },
+    )
+}
+
+/// Maps `value` through the piecewise linear function given by `points`
+/// (and a final `end` point), which `key` turns into (input, output) pairs
+fn interpolate<T>(
+    points: &[T],
+    end: (f32, f32),
+    value: f32,
+    key: impl Fn(&T) -> (f32, f32),
+) -> f32 {
+    let mut previous = (0.0, 0.0);
+    for point in points.iter().map(key).chain(std::iter::once(end)) {
+        if point.0 > value {
+            let span = point.0 - previous.0;
+            let t = if span > 0.0 {
+                (value - previous.0) / span
+            } else {
+                0.0
+            };
+            return previous.1 + t * (point.1 - previous.1);
+        }
+        previous = point;
+    }
+    previous.1
+}
+
+struct Renderer<'a> {
+    font_size: f32,
+    palette: &'a CatppuccinPalette,
+    code_spans: &'a mut HashMap<usize, Vec<Span>>,
+    block_tops: Vec<(usize, f32)>,
+    /// Top of the scrolled content, to turn positions into offsets
+    top: f32,
+    toggled: Option<usize>,
+}
+
+impl Renderer<'_> {
+    fn mark(&mut self, ui: &egui::Ui, line: usize) {
+        if self.block_tops.last().is_some_and(|&(l, _)| l >= line) {
+            return;
+        }
+        self.block_tops.push((line, ui.cursor().top() - self.top));
+    }
+
+    fn block(&mut self, ui: &mut egui::Ui, block: &Block) {
+        self.mark(ui, block.line);
+        let palette = self.palette;
+
+        match &block.kind {
+            BlockKind::Heading { level, content } => {
+                let index = usize::from(*level - 1);
+                let color = [
+                    palette.red,
+                    palette.peach,
+                    palette.yellow,
+                    palette.green,
+                    palette.sapphire,
+                    palette.mauve,
+                ][index];
+                ui.add_space(self.font_size * 0.4);
+                self.inline(ui, content, self.font_size * HEADING_SCALE[index], color);
+                if *level <= 2 {
+                    ui.separator();
+                }
+            }
+            BlockKind::Paragraph(content) => {
+                self.inline(ui, content, self.font_size, palette.text);
+            }
+            BlockKind::List(items) => {
+                for item in items {
+                    self.list_item(ui, item);
+                }
+            }
+            BlockKind::Code { info, code } => self.code(ui, block.line, info, code),
+            BlockKind::Table {
+                alignments,
+                header,
+                rows,
+            } => {
+                egui::Grid::new(("markdown_table", block.line))
+                    .striped(true)
+                    .spacing([self.font_size, self.font_size * 0.3])
+                    .show(ui, |ui| {
+                        for (cell, alignment) in header.iter().zip(alignments) {
+                            self.cell(ui, cell, *alignment, palette.blue);
+                        }
+                        ui.end_row();
+                        for row in rows {
+                            for (cell, alignment) in row.iter().zip(alignments) {
+                                self.cell(ui, cell, *alignment, palette.text);
+                            }
+                            ui.end_row();
+                        }
+                    });
+            }
+            BlockKind::Quote(blocks) => {
+                let response = egui::Frame::none()
+                    .inner_margin(egui::Margin {
+                        left: 12.0,
+                        ..Default::default()
+                    })
+                    .show(ui, |ui| {
+                        for block in blocks {
+                            self.block(ui, block);
+                        }
+                    })
+                    .response;
+                let rect = response.rect;
+                ui.painter().vline(
+                    rect.left() + 2.0,
+                    rect.y_range(),
+                    egui::Stroke::new(3.0, palette.overlay0),
+                );
+            }
+            BlockKind::Rule => {
+                ui.separator();
+            }
+        }
+        ui.add_space(self.font_size * 0.5);
+    }
+
+    fn list_item(&mut self, ui: &mut egui::Ui, item: &ListItem) {
+        self.mark(ui, item.line);
+        ui.horizontal(|ui| {
+            ui.add_space(LIST_INDENT * item.depth as f32);
+            match (item.task, item.number) {
+                (Some(task), _) => {
+                    let mut checked = task.checked;
+                    if ui.checkbox(&mut checked, "").clicked() {
+                        self.toggled = Some(task.offset);
+                    }
+                }
+                (None, Some(number)) => {
+                    ui.label(
+                        RichText::new(format!("{number}."))
+                            .size(self.font_size)
+                            .color(self.palette.teal),
+                    );
+                }
+                (None, None) => {
+                    ui.label(
+                        RichText::new("\u{2022}")
+                            .size(self.font_size)
+                            .color(self.palette.teal),
+                    );
+                }
+            }
+            let color = if item.task.is_some_and(|t| t.checked) {
+                self.palette.overlay0
+            } else {
+                self.palette.text
+            };
+            self.inline(ui, &item.content, self.font_size, color);
+        });
+    }
+
+    fn cell(
+        &mut self,
+        ui: &mut egui::Ui,
+        cell: &[Inline],
+        alignment: Alignment,
+        color: egui::Color32,
+    ) {
+        let align = match alignment {
+            Alignment::Left => egui::Align::Min,
+            Alignment::Center => egui::Align::Center,
+            Alignment::Right => egui::Align::Max,
+        };
+        ui.with_layout(egui::Layout::top_down(align), |ui| {
+            self.inline(ui, cell, self.font_size, color);
+        });
+    }
+
+    fn code(&mut self, ui: &mut egui::Ui, line: usize, info: &str, code: &str) {
+        let font = egui::FontId::monospace(self.font_size * 0.9);
+        let language = Language::from_name(info.split_whitespace().next().unwrap_or(""));
+        let spans = self
+            .code_spans
+            .entry(line)
+            .or_insert_with(|| Highlighter::default().highlight(language, code).to_vec());
+        let job = syntax::layout_job(code, &font, self.palette, spans, &[], None);
+
+        egui::Frame::none()
+            .fill(self.palette.mantle)
+            .rounding(4.0)
+            .inner_margin(8.0)
+            .show(ui, |ui| {
+                ui.set_min_width(ui.available_width());
+                egui::ScrollArea::horizontal()
+                    .id_salt(("markdown_code", line))
+                    .show(ui, |ui| {
+                        ui.add(egui::Label::new(job).extend());
+                    });
+            });
+    }
+
+    /// Inline runs flowing as one wrapped paragraph; links open in the
+    /// browser
+    fn inline(&self, ui: &mut egui::Ui, runs: &[Inline], size: f32, color: egui::Color32) {
+        ui.horizontal_wrapped(|ui| {
+            ui.spacing_mut().item_spacing.x = 0.0;
+            for run in runs {
+                let mut text = RichText::new(&run.text).size(size).color(color);
+                if run.strong {
+                    text = text.color(self.palette.peach);
+                }
+                if run.emphasis {
+                    text = text.italics();
+                }
+                if run.strike {
+                    text = text.strikethrough();
+                }
+                if run.code {
+                    text = text
+                        .monospace()
+                        .size(size * 0.9)
+                        .color(self.palette.green)
+                        .background_color(self.palette.surface0);
+                }
+                match &run.link {
+                    Some(url) => {
+                        ui.hyperlink_to(text.color(self.palette.sapphire), url)
+                            .on_hover_text(url);
+                    }
+                    None => {
+                        ui.label(text);
+                    }
+                }
+            }
+        });
+    }
+}
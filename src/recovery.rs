@@ -0,0 +1,211 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use sysinfo::{Pid, System};
+
+use crate::atomic_file::{self, BackupMode};
+use crate::document::Document;
+use crate::encoding::TextEncoding;
+use crate::line_ending::LineEnding;
+use crate::settings;
+
+const RECOVERY_DIR: &str = "recovery";
+
+/// How often dirty buffers are written to the journal
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The unsaved contents of one document, as written to the journal
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub file_path: Option<PathBuf>,
+    pub text: String,
+    pub encoding: String,
+    pub line_ending: String,
+    /// Unix timestamp of when the snapshot was taken
+    pub taken_at: i64,
+}
+
+impl Snapshot {
+    fn of(doc: &Document) -> Self {
+        Self {
+            file_path: doc.file_path.clone(),
+            text: doc.text.clone(),
+            encoding: doc.encoding.label().to_string(),
+            line_ending: doc.line_ending.label().to_string(),
+            taken_at: Local::now().timestamp(),
+        }
+    }
+
+    pub fn title(&self) -> String {
+        self.file_path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Untitled".to_string())
+    }
+
+    pub fn encoding(&self) -> TextEncoding {
+        TextEncoding::ALL
+            .iter()
+            .copied()
+            .find(|e| e.label() == self.encoding)
+            .unwrap_or(TextEncoding::Utf8)
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        LineEnding::ALL
+            .iter()
+            .copied()
+            .find(|e| e.label() == self.line_ending)
+            .unwrap_or_else(LineEnding::native)
+    }
+
+    /// When the snapshot was taken, in local time
+    pub fn taken_at_text(&self) -> String {
+        DateTime::from_timestamp(self.taken_at, 0)
+            .map(|t| {
+                t.with_timezone(&Local)
+                    .format("%b %d, %I:%M %p")
+                    .to_string()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// A snapshot left behind by a session that did not exit cleanly
+pub struct Recovered {
+    /// The journal file, deleted once the snapshot is restored or discarded
+    pub journal_path: PathBuf,
+    pub snapshot: Snapshot,
+}
+
+pub fn recovery_dir() -> Option<PathBuf> {
+    settings::state_dir().map(|dir| dir.join(RECOVERY_DIR))
+}
+
+/// Journal files are named `<pid>-<document id>.json`, so several running
+/// instances never touch each other's snapshots
+fn journal_path(dir: &Path, doc_id: u64) -> PathBuf {
+    dir.join(format!("{}-{}.json", std::process::id(), doc_id))
+}
+
+fn owner_pid(path: &Path) -> Option<u32> {
+    let stem = path.file_stem()?.to_str()?;
+    stem.split_once('-')?.0.parse().ok()
+}
+
+/// Snapshots whose writing process is no longer running, oldest first
+pub fn leftovers(system: &System) -> Vec<Recovered> {
+    let Some(entries) = recovery_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+
+    let mut recovered: Vec<Recovered> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter(|path| match owner_pid(path) {
+            Some(pid) => system.process(Pid::from_u32(pid)).is_none(),
+            None => false,
+        })
+        .filter_map(|journal_path| {
+            let contents = fs::read_to_string(&journal_path).ok()?;
+            let snapshot = serde_json::from_str(&contents).ok()?;
+            Some(Recovered {
+                journal_path,
+                snapshot,
+            })
+        })
+        .collect();
+
+    recovered.sort_by_key(|r| r.snapshot.taken_at);
+    recovered
+}
+
+pub fn discard(recovered: &Recovered) {
+    let _ = fs::remove_file(&recovered.journal_path);
+}
+
+/// Keeps a snapshot of every dirty document in the recovery directory and
+/// removes it once the document is saved or closed
+pub struct Journal {
+    dir: Option<PathBuf>,
+    /// Revision last written for each document with a snapshot on disk
+    written: HashMap<u64, u64>,
+    last_run: Instant,
+    last_error: Option<String>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Self {
+            dir: recovery_dir(),
+            written: HashMap::new(),
+            last_run: Instant::now(),
+            last_error: None,
+        }
+    }
+
+    /// Writes snapshots for documents that changed since the last run.
+    /// Returns an error message the first time writing starts failing.
+    pub fn update(&mut self, documents: &[Document]) -> Option<String> {
+        if self.last_run.elapsed() < SNAPSHOT_INTERVAL {
+            return None;
+        }
+        self.last_run = Instant::now();
+        let dir = self.dir.clone()?;
+
+        let stale: Vec<u64> = self
+            .written
+            .keys()
+            .copied()
+            .filter(|id| !documents.iter().any(|d| d.id == *id && d.dirty))
+            .collect();
+        for id in stale {
+            let _ = fs::remove_file(journal_path(&dir, id));
+            self.written.remove(&id);
+        }
+
+        let mut error = None;
+        for doc in documents.iter().filter(|d| d.dirty) {
+            if self.written.get(&doc.id) == Some(&doc.revision) {
+                continue;
+            }
+            let result = fs::create_dir_all(&dir)
+                .map_err(|e| e.to_string())
+                .and_then(|_| serde_json::to_vec(&Snapshot::of(doc)).map_err(|e| e.to_string()))
+                .and_then(|bytes| {
+                    atomic_file::write(&journal_path(&dir, doc.id), &bytes, BackupMode::None)
+                        .map_err(|e| e.to_string())
+                });
+            match result {
+                Ok(_) => {
+                    self.written.insert(doc.id, doc.revision);
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+
+        let report = match &error {
+            Some(e) if self.last_error.is_none() => {
+                Some(format!("Could not write recovery snapshot: {}", e))
+            }
+            _ => None,
+        };
+        self.last_error = error;
+        report
+    }
+
+    /// Removes every snapshot of this session, on a clean exit
+    pub fn clear(&mut self) {
+        if let Some(dir) = &self.dir {
+            for id in self.written.keys() {
+                let _ = fs::remove_file(journal_path(dir, *id));
+            }
+        }
+        self.written.clear();
+    }
+}
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::atomic_file::{self, BackupMode};
+use crate::settings;
+
+const SESSION_FILE: &str = "session.json";
+
+/// What was open when the app last exited, plus the recent files list
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub recent_files: Vec<PathBuf>,
+    pub documents: Vec<SessionDocument>,
+    pub active_doc: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionDocument {
+    pub file_path: Option<PathBuf>,
+    /// Contents of an untitled buffer. Documents with a path are read back
+    /// from disk instead.
+    pub text: Option<String>,
+    /// Caret position as a char index
+    pub cursor: usize,
+    pub scroll: [f32; 2],
+    pub read_only: bool,
+}
+
+pub fn session_path() -> Option<PathBuf> {
+    settings::state_dir().map(|dir| dir.join(SESSION_FILE))
+}
+
+/// Loads the last session. Recent files that no longer exist are dropped.
+pub fn load() -> Session {
+    let mut session: Session = session_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default();
+    session.recent_files.retain(|path| path.exists());
+    session
+}
+
+pub fn save(session: &Session) -> io::Result<()> {
+    let path = session_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no state directory"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let contents = serde_json::to_vec_pretty(session).map_err(io::Error::other)?;
+    atomic_file::write(&path, &contents, BackupMode::None)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(())
+}
+
+/// Moves `path` to the front of the recent files list, keeping at most
+/// `limit` entries
+pub fn push_recent(recent_files: &mut Vec<PathBuf>, path: &Path, limit: usize) {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    recent_files.retain(|p| *p != path);
+    recent_files.insert(0, path);
+    recent_files.truncate(limit);
+}
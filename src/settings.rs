@@ -0,0 +1,526 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::atomic_file::{self, BackupMode};
+use crate::commands::Command;
+use crate::pdf::{PageSize, PdfFont};
+use crate::weather::{self, LocationSource, TemperatureUnit, WindUnit};
+use crate::wrap::WrapMode;
+
+/// Bump when the file layout changes and add a step to [`migrate`]
+pub const SETTINGS_VERSION: i64 = 2;
+
+const APP_DIR: &str = "rusty-notepad";
+const SETTINGS_FILE: &str = "settings.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Mocha,
+    Latte,
+}
+
+impl Theme {
+    pub fn is_dark(self) -> bool {
+        self == Theme::Mocha
+    }
+
+    pub fn from_dark_mode(dark_mode: bool) -> Self {
+        if dark_mode {
+            Theme::Mocha
+        } else {
+            Theme::Latte
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub width: f32,
+    pub height: f32,
+    pub x: Option<f32>,
+    pub y: Option<f32>,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            width: 800.0,
+            height: 600.0,
+            x: None,
+            y: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WeatherSettings {
+    pub enabled: bool,
+    pub refresh_secs: u64,
+    pub location: LocationSource,
+    /// Looked up when `location` is `place`
+    pub place: String,
+    /// Used when `location` is `coordinates`
+    pub latitude: f64,
+    pub longitude: f64,
+    pub temperature_unit: TemperatureUnit,
+    pub wind_unit: WindUnit,
+    /// Base URLs of the weather services, read at startup
+    pub forecast_url: String,
+    pub geocoding_url: String,
+    pub ip_location_url: String,
+}
+
+impl Default for WeatherSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            refresh_secs: 600,
+            location: LocationSource::Place,
+            place: String::new(),
+            latitude: 0.0,
+            longitude: 0.0,
+            temperature_unit: TemperatureUnit::Celsius,
+            wind_unit: WindUnit::Kmh,
+            forecast_url: weather::FORECAST_URL.to_string(),
+            geocoding_url: weather::GEOCODING_URL.to_string(),
+            ip_location_url: weather::IP_LOCATION_URL.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonitorSettings {
+    pub enabled: bool,
+    pub refresh_ms: u64,
+}
+
+impl Default for MonitorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            refresh_ms: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileSettings {
+    pub backup: BackupMode,
+    /// Periodically save documents that already have a path
+    pub autosave: bool,
+    pub autosave_secs: u64,
+    /// Length of the File > Open Recent list
+    pub recent_limit: usize,
+    /// Reopen the last session's documents on startup
+    pub restore_session: bool,
+}
+
+impl Default for FileSettings {
+    fn default() -> Self {
+        Self {
+            backup: BackupMode::None,
+            autosave: false,
+            autosave_secs: 30,
+            recent_limit: 10,
+            restore_session: true,
+        }
+    }
+}
+
+/// Page setup for File > Export > PDF
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PdfSettings {
+    pub page_size: PageSize,
+    pub margin_mm: f32,
+    pub font: PdfFont,
+    pub font_size: f32,
+    /// File name and date at the top of each page, page number at the bottom
+    pub header_footer: bool,
+}
+
+impl Default for PdfSettings {
+    fn default() -> Self {
+        Self {
+            page_size: PageSize::A4,
+            margin_mm: 20.0,
+            font: PdfFont::Helvetica,
+            font_size: 10.0,
+            header_footer: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EditorSettings {
+    pub line_numbers: bool,
+    /// Show rendered Markdown next to Markdown documents
+    pub markdown_preview: bool,
+    pub wrap: WrapMode,
+    /// Column for `WrapMode::Column`, the ruler and hard wrapping
+    pub wrap_column: usize,
+    pub show_ruler: bool,
+}
+
+impl Default for EditorSettings {
+    fn default() -> Self {
+        Self {
+            line_numbers: true,
+            markdown_preview: false,
+            wrap: WrapMode::Window,
+            wrap_column: 80,
+            show_ruler: true,
+        }
+    }
+}
+
+/// Everything stored in `settings.toml`. Missing keys fall back to their
+/// defaults, so older files keep loading as fields are added.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: i64,
+    pub font_size: f32,
+    pub theme: Theme,
+    pub editor: EditorSettings,
+    pub window: WindowSettings,
+    pub weather: WeatherSettings,
+    pub monitor: MonitorSettings,
+    pub files: FileSettings,
+    pub pdf: PdfSettings,
+    pub keybindings: BTreeMap<String, String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            font_size: 14.0,
+            theme: Theme::Mocha,
+            editor: EditorSettings::default(),
+            window: WindowSettings::default(),
+            weather: WeatherSettings::default(),
+            monitor: MonitorSettings::default(),
+            files: FileSettings::default(),
+            pdf: PdfSettings::default(),
+            keybindings: Command::ALL
+                .iter()
+                .filter_map(|command| {
+                    let keys = command.default_shortcut()?;
+                    Some((command.id(), keys.to_string()))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Settings {
+    /// Shortcuts bound to `command`, first one preferred for display,
+    /// falling back to the default bindings when the user's entry is
+    /// missing or none of it can be parsed
+    pub fn shortcuts(&self, command: Command) -> Vec<egui::KeyboardShortcut> {
+        let bound = self
+            .keybindings
+            .get(&command.id())
+            .map(|keys| parse_shortcuts(keys))
+            .unwrap_or_default();
+        if !bound.is_empty() {
+            return bound;
+        }
+        command
+            .default_shortcut()
+            .map(parse_shortcuts)
+            .unwrap_or_default()
+    }
+}
+
+/// Parses a comma-separated list of shortcuts such as
+/// `Ctrl+Shift+Z, Ctrl+Y`, skipping entries that are not valid
+pub fn parse_shortcuts(text: &str) -> Vec<egui::KeyboardShortcut> {
+    text.split(',').filter_map(parse_shortcut).collect()
+}
+
+/// Parses shortcuts like `Ctrl+Shift+S`. `Ctrl` and `Cmd` both map to the
+/// platform command key.
+pub fn parse_shortcut(text: &str) -> Option<egui::KeyboardShortcut> {
+    let mut modifiers = egui::Modifiers::NONE;
+    let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+    let key = egui::Key::from_name(parts.pop()?)?;
+
+    for part in parts {
+        match part.to_ascii_lowercase().as_str() {
+            "ctrl" | "cmd" | "command" => modifiers = modifiers | egui::Modifiers::COMMAND,
+            "shift" => modifiers = modifiers | egui::Modifiers::SHIFT,
+            "alt" | "option" => modifiers = modifiers | egui::Modifiers::ALT,
+            _ => return None,
+        }
+    }
+
+    Some(egui::KeyboardShortcut::new(modifiers, key))
+}
+
+pub fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_DIR).join(SETTINGS_FILE))
+}
+
+/// Directory for session and recovery data, which is not configuration
+pub fn state_dir() -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|dir| dir.join(APP_DIR))
+}
+
+/// Upgrades an older settings table in place to [`SETTINGS_VERSION`]
+fn migrate(table: &mut toml::Table) {
+    let version = table
+        .get("version")
+        .and_then(toml::Value::as_integer)
+        .unwrap_or(0);
+
+    // Version 0 files predate the `version` key but otherwise match version
+    // 1, which bound redo to Ctrl+Shift+Z only. Add Ctrl+Y unless the user
+    // changed the binding.
+    if version < 2 {
+        if let Some(toml::Value::Table(keys)) = table.get_mut("keybindings") {
+            let redo = Command::Redo.id();
+            if keys.get(&redo).and_then(toml::Value::as_str) == Some("Ctrl+Shift+Z") {
+                if let Some(default) = Command::Redo.default_shortcut() {
+                    keys.insert(redo, toml::Value::String(default.to_string()));
+                }
+            }
+        }
+    }
+
+    if version < SETTINGS_VERSION {
+        table.insert(
+            "version".to_string(),
+            toml::Value::Integer(SETTINGS_VERSION),
+        );
+    }
+}
+
+/// Loads the settings file. A missing file yields the defaults; an unreadable
+/// or corrupt one is moved aside and also yields the defaults, along with a
+/// message for the status bar.
+pub fn load() -> (Settings, Option<String>) {
+    match settings_path() {
+        Some(path) => load_from(&path),
+        None => (Settings::default(), None),
+    }
+}
+
+fn load_from(path: &Path) -> (Settings, Option<String>) {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return (Settings::default(), None),
+        Err(e) => {
+            return (
+                Settings::default(),
+                Some(format!("Could not read settings: {}", e)),
+            )
+        }
+    };
+
+    let parsed = contents.parse::<toml::Table>().and_then(|mut table| {
+        migrate(&mut table);
+        toml::Value::Table(table).try_into::<Settings>()
+    });
+
+    match parsed {
+        // Keep the newer file's version so `save` leaves the file alone
+        Ok(settings) if settings.version > SETTINGS_VERSION => {
+            let message = format!(
+                "Settings are from a newer version (format {}); changes will not be saved",
+                settings.version
+            );
+            (settings, Some(message))
+        }
+        Ok(settings) => (settings, None),
+        Err(e) => {
+            let backup = path.with_extension("toml.corrupt");
+            let _ = fs::rename(path, &backup);
+            let message = format!(
+                "Settings file was invalid ({}); using defaults. Old file kept at {}",
+                e.message(),
+                backup.display()
+            );
+            (Settings::default(), Some(message))
+        }
+    }
+}
+
+/// Writes the settings file, unless it came from a newer version whose
+/// keys this one would drop
+pub fn save(settings: &Settings) -> io::Result<()> {
+    let path = settings_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+    save_to(&path, settings)
+}
+
+fn save_to(path: &Path, settings: &Settings) -> io::Result<()> {
+    if settings.version > SETTINGS_VERSION {
+        return Err(io::Error::other(
+            "the settings file is from a newer version and was left unchanged",
+        ));
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let contents = toml::to_string_pretty(settings).map_err(io::Error::other)?;
+    // A crash mid-write must not leave a truncated file that `load` would
+    // then discard as corrupt
+    atomic_file::write(path, contents.as_bytes(), BackupMode::None)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings_file(contents: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SETTINGS_FILE);
+        fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn missing_file_gives_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let (settings, message) = load_from(&dir.path().join(SETTINGS_FILE));
+        assert_eq!(settings, Settings::default());
+        assert_eq!(message, None);
+    }
+
+    #[test]
+    fn unversioned_file_is_migrated() {
+        let (_dir, path) = settings_file("font_size = 18.0\ntheme = \"latte\"\n");
+        let (settings, message) = load_from(&path);
+        assert_eq!(message, None);
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.font_size, 18.0);
+        assert_eq!(settings.theme, Theme::Latte);
+    }
+
+    #[test]
+    fn corrupt_file_is_moved_aside() {
+        let (dir, path) = settings_file("font_size = [oops");
+        let (settings, message) = load_from(&path);
+        assert_eq!(settings, Settings::default());
+        assert!(message.unwrap().contains("settings.toml.corrupt"));
+        assert!(!path.exists());
+        let backup = dir.path().join("settings.toml.corrupt");
+        assert_eq!(fs::read_to_string(backup).unwrap(), "font_size = [oops");
+    }
+
+    #[test]
+    fn newer_file_is_never_overwritten() {
+        let newer = format!(
+            "version = {}\nfont_size = 20.0\nfuture_key = 1\n",
+            SETTINGS_VERSION + 1
+        );
+        let (_dir, path) = settings_file(&newer);
+        let (mut settings, message) = load_from(&path);
+        assert!(message.unwrap().contains("newer version"));
+        assert_eq!(settings.version, SETTINGS_VERSION + 1);
+
+        settings.font_size = 12.0;
+        assert!(save_to(&path, &settings).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), newer);
+    }
+
+    #[test]
+    fn saved_settings_load_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(SETTINGS_FILE);
+        let settings = Settings {
+            font_size: 22.0,
+            ..Settings::default()
+        };
+        save_to(&path, &settings).unwrap();
+        assert_eq!(load_from(&path), (settings, None));
+    }
+
+    #[test]
+    fn parses_shortcuts() {
+        let shortcut = |modifiers, key| Some(egui::KeyboardShortcut::new(modifiers, key));
+        let command_shift = egui::Modifiers::COMMAND | egui::Modifiers::SHIFT;
+        assert_eq!(
+            parse_shortcut("Ctrl+Shift+S"),
+            shortcut(command_shift, egui::Key::S)
+        );
+        assert_eq!(
+            parse_shortcut("cmd + shift + s"),
+            shortcut(command_shift, egui::Key::S)
+        );
+        assert_eq!(
+            parse_shortcut("Alt+ArrowLeft"),
+            shortcut(egui::Modifiers::ALT, egui::Key::ArrowLeft)
+        );
+        assert_eq!(
+            parse_shortcut("F6"),
+            shortcut(egui::Modifiers::NONE, egui::Key::F6)
+        );
+        assert_eq!(parse_shortcut("Ctrl+Hyper+S"), None);
+        assert_eq!(parse_shortcut("Ctrl+NotAKey"), None);
+        assert_eq!(parse_shortcut(""), None);
+    }
+
+    #[test]
+    fn bad_binding_falls_back_to_the_default() {
+        let mut settings = Settings::default();
+        settings
+            .keybindings
+            .insert("save".to_string(), "Ctrl+Nonsense".to_string());
+        assert_eq!(
+            settings.shortcuts(Command::Save),
+            parse_shortcut("Ctrl+S").into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parses_alternative_shortcuts() {
+        let redo = parse_shortcuts("Ctrl+Shift+Z, Ctrl+Y");
+        assert_eq!(
+            redo,
+            [parse_shortcut("Ctrl+Shift+Z"), parse_shortcut("Ctrl+Y")]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(parse_shortcuts("Ctrl+Y, Bogus+Q").len(), 1);
+        assert!(parse_shortcuts("").is_empty());
+        assert_eq!(Settings::default().shortcuts(Command::Redo), redo);
+    }
+
+    #[test]
+    fn version_1_redo_binding_gains_ctrl_y() {
+        let (_dir, path) = settings_file(
+            "version = 1\n[keybindings]\nredo = \"Ctrl+Shift+Z\"\nundo = \"Ctrl+Z\"\n",
+        );
+        let (settings, _) = load_from(&path);
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.keybindings["redo"], "Ctrl+Shift+Z, Ctrl+Y");
+        assert_eq!(settings.keybindings["undo"], "Ctrl+Z");
+    }
+
+    #[test]
+    fn rebound_or_current_redo_binding_is_kept() {
+        let (_dir, path) = settings_file("version = 1\n[keybindings]\nredo = \"Ctrl+R\"\n");
+        assert_eq!(load_from(&path).0.keybindings["redo"], "Ctrl+R");
+
+        let (_dir, path) = settings_file("version = 2\n[keybindings]\nredo = \"Ctrl+Shift+Z\"\n");
+        assert_eq!(load_from(&path).0.keybindings["redo"], "Ctrl+Shift+Z");
+    }
+}
@@ -0,0 +1,35 @@
+use eframe::egui;
+
+/// Direction in which the editor is divided between two panes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitOrientation {
+    /// One pane above the other
+    Horizontal,
+    /// Panes side by side
+    Vertical,
+}
+
+impl SplitOrientation {
+    pub const ALL: &'static [SplitOrientation] =
+        &[SplitOrientation::Horizontal, SplitOrientation::Vertical];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SplitOrientation::Horizontal => "Split Horizontally",
+            SplitOrientation::Vertical => "Split Vertically",
+        }
+    }
+}
+
+/// A document shown in two editor panes at once. Pane 0 is the top or left
+/// one and keeps using the document's own scroll position; each pane has
+/// its own caret and selection.
+#[derive(Debug, Clone)]
+pub struct Split {
+    pub orientation: SplitOrientation,
+    /// Pane that has the keyboard focus and drives the status bar, find and
+    /// the other editing commands
+    pub focused: usize,
+    /// Scroll position of pane 1, as of the last frame
+    pub scroll_offset: egui::Vec2,
+}
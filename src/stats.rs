@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::document::Document;
+
+/// Average silent reading speed used for the reading time estimate
+const WORDS_PER_MINUTE: f32 = 230.0;
+/// Documents larger than this (in bytes) are recounted at most once per
+/// `LARGE_DOCUMENT_REFRESH` while they are being edited
+const LARGE_DOCUMENT: usize = 1 << 20;
+const LARGE_DOCUMENT_REFRESH: Duration = Duration::from_millis(500);
+/// Number of entries in the word frequency table
+pub const TOP_WORDS: usize = 10;
+
+/// Totals shown in the status bar
+#[derive(Debug, Clone, Default)]
+pub struct DocStats {
+    pub lines: usize,
+    pub words: usize,
+    pub chars: usize,
+}
+
+impl DocStats {
+    pub fn count(text: &str) -> Self {
+        Self {
+            lines: text.matches('\n').count() + 1,
+            words: text.split_whitespace().count(),
+            // A CRLF line break counts as one character, as it does once
+            // the document is loaded
+            chars: text.chars().filter(|&c| c != '\r').count(),
+        }
+    }
+}
+
+/// Everything shown in the statistics window
+#[derive(Debug, Clone, Default)]
+pub struct DetailedStats {
+    pub totals: DocStats,
+    pub chars_without_spaces: usize,
+    pub paragraphs: usize,
+    pub reading_minutes: f32,
+    /// Most frequent words, lowercased, with their counts
+    pub top_words: Vec<(String, usize)>,
+}
+
+impl DetailedStats {
+    pub fn count(text: &str) -> Self {
+        let totals = DocStats::count(text);
+
+        let mut paragraphs = 0;
+        let mut in_paragraph = false;
+        for line in text.lines() {
+            let blank = line.trim().is_empty();
+            if !blank && !in_paragraph {
+                paragraphs += 1;
+            }
+            in_paragraph = !blank;
+        }
+
+        let mut frequency: HashMap<String, usize> = HashMap::new();
+        for word in text
+            .split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .map(|w| w.trim_matches('\''))
+            .filter(|w| !w.is_empty())
+        {
+            *frequency.entry(word.to_lowercase()).or_default() += 1;
+        }
+        let mut top_words: Vec<(String, usize)> = frequency.into_iter().collect();
+        top_words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_words.truncate(TOP_WORDS);
+
+        Self {
+            chars_without_spaces: text.chars().filter(|c| !c.is_whitespace()).count(),
+            paragraphs,
+            reading_minutes: totals.words as f32 / WORDS_PER_MINUTE,
+            top_words,
+            totals,
+        }
+    }
+}
+
+/// Keeps the last computed stats and only recomputes them when the
+/// document changes, so counting does not happen on every frame
+pub struct StatsCache<T> {
+    /// Document id and revision the value was computed for
+    key: Option<(u64, u64)>,
+    last_run: Instant,
+    value: T,
+}
+
+impl<T: Default> Default for StatsCache<T> {
+    fn default() -> Self {
+        Self {
+            key: None,
+            last_run: Instant::now(),
+            value: T::default(),
+        }
+    }
+}
+
+impl<T: Default> StatsCache<T> {
+    pub fn get(&mut self, doc: &Document, compute: impl FnOnce(&str) -> T) -> &T {
+        let key = (doc.id, doc.revision);
+        let same_doc = self.key.is_some_and(|(id, _)| id == doc.id);
+        // While a large document is being edited, show slightly stale
+        // numbers instead of recounting on every keystroke
+        let throttled = same_doc
+            && doc.text.len() > LARGE_DOCUMENT
+            && self.last_run.elapsed() < LARGE_DOCUMENT_REFRESH;
+
+        if self.key != Some(key) && !throttled {
+            self.value = compute(&doc.text);
+            self.key = Some(key);
+            self.last_run = Instant::now();
+        }
+        &self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn empty_document() {
+        let stats = DetailedStats::count("");
+        assert_eq!(
+            (stats.totals.lines, stats.totals.words, stats.totals.chars),
+            (1, 0, 0)
+        );
+        assert_eq!(stats.paragraphs, 0);
+        assert_eq!(stats.reading_minutes, 0.0);
+        assert!(stats.top_words.is_empty());
+    }
+
+    #[test]
+    fn crlf_counts_like_lf() {
+        let lf = DetailedStats::count("one two\n\nthree\n");
+        let crlf = DetailedStats::count("one two\r\n\r\nthree\r\n");
+        for stats in [lf, crlf] {
+            assert_eq!(
+                (stats.totals.lines, stats.totals.words, stats.totals.chars),
+                (4, 3, 15)
+            );
+            assert_eq!(stats.chars_without_spaces, 11);
+            assert_eq!(stats.paragraphs, 2);
+        }
+    }
+
+    #[test]
+    fn multi_byte_text_counts_chars() {
+        let stats = DetailedStats::count("Héllo wörld 日本語\nÜber über ÜBER don't");
+        assert_eq!(
+            (stats.totals.lines, stats.totals.words, stats.totals.chars),
+            (2, 7, 36)
+        );
+        assert_eq!(stats.chars_without_spaces, 30);
+        assert_eq!(stats.top_words[0], ("über".to_string(), 3));
+        assert!(stats.top_words.contains(&("don't".to_string(), 1)));
+        assert!(stats.top_words.contains(&("日本語".to_string(), 1)));
+    }
+
+    #[test]
+    fn top_words_are_capped_and_ordered() {
+        let text: String = (0..20).map(|n| format!("w{n:02} ")).collect();
+        let stats = DetailedStats::count(&format!("{text} b a b"));
+        assert_eq!(stats.top_words.len(), TOP_WORDS);
+        assert_eq!(stats.top_words[0], ("b".to_string(), 2));
+        assert_eq!(stats.top_words[1], ("a".to_string(), 1));
+        assert_eq!(stats.top_words[2], ("w00".to_string(), 1));
+    }
+
+    #[test]
+    fn cache_recounts_on_new_revisions() {
+        let runs = Cell::new(0);
+        let count = |text: &str| {
+            runs.set(runs.get() + 1);
+            DocStats::count(text)
+        };
+        let mut cache = StatsCache::default();
+        let mut doc = Document::new();
+        doc.text = "one two".to_string();
+
+        assert_eq!(cache.get(&doc, count).words, 2);
+        assert_eq!(cache.get(&doc, count).words, 2);
+        assert_eq!(runs.get(), 1);
+
+        doc.text.push_str(" three");
+        doc.revision += 1;
+        assert_eq!(cache.get(&doc, count).words, 3);
+        assert_eq!(runs.get(), 2);
+
+        let other = Document::new();
+        assert_eq!(cache.get(&other, count).words, 0);
+        assert_eq!(runs.get(), 3);
+    }
+
+    #[test]
+    fn cache_throttles_large_documents() {
+        let runs = Cell::new(0);
+        let count = |text: &str| {
+            runs.set(runs.get() + 1);
+            DocStats::count(text)
+        };
+        let mut cache = StatsCache::default();
+        let mut doc = Document::new();
+        doc.text = "word ".repeat(LARGE_DOCUMENT / 5 + 1);
+        let words = cache.get(&doc, count).words;
+        assert_eq!(runs.get(), 1);
+
+        // Edits right after a count show the stale numbers
+        doc.text.push_str("more");
+        doc.revision += 1;
+        assert_eq!(cache.get(&doc, count).words, words);
+        assert_eq!(runs.get(), 1);
+
+        // Once the refresh interval has passed they are recounted
+        cache.last_run = Instant::now() - LARGE_DOCUMENT_REFRESH;
+        assert_eq!(cache.get(&doc, count).words, words + 1);
+        assert_eq!(runs.get(), 2);
+
+        // Switching to another document is never throttled
+        let other = Document::new();
+        cache.get(&other, count);
+        assert_eq!(runs.get(), 3);
+    }
+}
@@ -0,0 +1,1177 @@
+use eframe::egui::{self, text::LayoutJob, TextFormat};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+use crate::language::Language;
+use crate::theme::CatppuccinPalette;
+
+/// What a highlighted piece of text is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Keyword,
+    Type,
+    Function,
+    Macro,
+    Attribute,
+    String,
+    Number,
+    Constant,
+    Comment,
+    Key,
+    Variable,
+    Heading,
+    Strong,
+    Emphasis,
+    Code,
+    Link,
+    ListMarker,
+    Quote,
+}
+
+/// A highlighted byte range
+pub type Span = (Range<usize>, Token);
+
+/// Where a line ends up for constructs that continue on the next line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+enum State {
+    #[default]
+    Normal,
+    /// Inside a Rust block comment, with its nesting depth
+    BlockComment(u8),
+    /// Inside a Rust string; raw strings carry their number of `#`s
+    RustString { raw_hashes: Option<u8> },
+    /// Inside a TOML multi-line string or a multi-line shell string
+    MultiString { quote: u8, triple: bool },
+    /// Inside a fenced Markdown code block
+    CodeFence { marker: u8, len: u8 },
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "type", "unsafe", "use",
+    "where", "while", "yield",
+];
+
+const RUST_PRIMITIVES: &[&str] = &[
+    "bool", "char", "str", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64",
+    "u128", "usize", "f32", "f64",
+];
+
+const SHELL_KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+    "in", "function", "select", "return", "local", "export", "readonly", "declare", "unset",
+    "shift", "break", "continue", "exit", "source",
+];
+
+impl Token {
+    pub const ALL: &'static [Token] = &[
+        Token::Keyword,
+        Token::Type,
+        Token::Function,
+        Token::Macro,
+        Token::Attribute,
+        Token::String,
+        Token::Number,
+        Token::Constant,
+        Token::Comment,
+        Token::Key,
+        Token::Variable,
+        Token::Heading,
+        Token::Strong,
+        Token::Emphasis,
+        Token::Code,
+        Token::Link,
+        Token::ListMarker,
+        Token::Quote,
+    ];
+
+    /// Name used for the token's CSS class in HTML exports
+    pub fn name(self) -> &'static str {
+        match self {
+            Token::Keyword => "keyword",
+            Token::Type => "type",
+            Token::Function => "function",
+            Token::Macro => "macro",
+            Token::Attribute => "attribute",
+            Token::String => "string",
+            Token::Number => "number",
+            Token::Constant => "constant",
+            Token::Comment => "comment",
+            Token::Key => "key",
+            Token::Variable => "variable",
+            Token::Heading => "heading",
+            Token::Strong => "strong",
+            Token::Emphasis => "emphasis",
+            Token::Code => "code",
+            Token::Link => "link",
+            Token::ListMarker => "list-marker",
+            Token::Quote => "quote",
+        }
+    }
+
+    pub fn color(self, palette: &CatppuccinPalette) -> egui::Color32 {
+        match self {
+            Token::Keyword => palette.mauve,
+            Token::Type | Token::Attribute => palette.yellow,
+            Token::Function | Token::Key => palette.blue,
+            Token::Macro | Token::Variable | Token::ListMarker => palette.teal,
+            Token::String | Token::Code => palette.green,
+            Token::Number | Token::Constant | Token::Strong => palette.peach,
+            Token::Comment | Token::Quote => palette.overlay0,
+            Token::Heading => palette.red,
+            Token::Link => palette.sapphire,
+            Token::Emphasis => palette.text,
+        }
+    }
+
+    pub fn is_italic(self) -> bool {
+        matches!(self, Token::Comment | Token::Emphasis | Token::Quote)
+    }
+}
+
+fn text_format(
+    token: Option<Token>,
+    font: &egui::FontId,
+    palette: &CatppuccinPalette,
+) -> TextFormat {
+    let mut format = TextFormat::simple(font.clone(), palette.text);
+    let Some(token) = token else {
+        return format;
+    };
+    format.color = token.color(palette);
+    format.italics = token.is_italic();
+    if token == Token::Link {
+        format.underline = egui::Stroke::new(1.0, palette.sapphire);
+    }
+    format
+}
+
+/// Lays out `text` with syntax colors from `spans` and a background behind
+/// every find match. Match ranges that no longer fit the text (it may have
+/// been edited this frame) are skipped.
+pub fn layout_job(
+    text: &str,
+    font: &egui::FontId,
+    palette: &CatppuccinPalette,
+    spans: &[Span],
+    matches: &[Range<usize>],
+    current: Option<&Range<usize>>,
+) -> LayoutJob {
+    let matches: Vec<&Range<usize>> = matches
+        .iter()
+        .filter(|r| {
+            r.start <= r.end
+                && r.end <= text.len()
+                && text.is_char_boundary(r.start)
+                && text.is_char_boundary(r.end)
+        })
+        .collect();
+
+    let mut bounds = vec![0, text.len()];
+    for range in spans.iter().map(|(r, _)| r).chain(matches.iter().copied()) {
+        bounds.push(range.start);
+        bounds.push(range.end);
+    }
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut job = LayoutJob::default();
+    let (mut span, mut found) = (0, 0);
+    for pair in bounds.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        while span < spans.len() && spans[span].0.end <= start {
+            span += 1;
+        }
+        while found < matches.len() && matches[found].end <= start {
+            found += 1;
+        }
+
+        let token = spans
+            .get(span)
+            .filter(|(r, _)| r.start <= start)
+            .map(|(_, t)| *t);
+        let mut format = text_format(token, font, palette);
+        if let Some(range) = matches.get(found).filter(|r| r.start <= start) {
+            format.background = if current == Some(*range) {
+                palette.sapphire.gamma_multiply(0.6)
+            } else {
+                palette.surface2
+            };
+        }
+        job.append(&text[start..end], 0.0, format);
+    }
+
+    if job.sections.is_empty() {
+        job.append("", 0.0, text_format(None, font, palette));
+    }
+    job
+}
+
+fn hash_str(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Tokens of one line, reusable wherever the same line starts in the same
+/// state
+struct CachedLine {
+    end: State,
+    spans: Vec<Span>,
+}
+
+/// Highlights a document line by line, caching each line's tokens so only
+/// edited lines (and lines whose starting state changed) are re-tokenized
+#[derive(Default)]
+pub struct Highlighter {
+    language: Option<Language>,
+    text_hash: u64,
+    /// (document id, revision) `spans` were computed for, when known
+    revision: Option<(u64, u64)>,
+    lines: HashMap<(u64, State), CachedLine>,
+    spans: Vec<Span>,
+}
+
+impl Highlighter {
+    /// Like [`Highlighter::highlight`] for `text` at `revision` (document
+    /// id, revision) of a document, skipping even the whole-text hash while
+    /// the revision is unchanged. Pass `None` when the text may have changed
+    /// without the revision being bumped yet.
+    pub fn highlight_revision(
+        &mut self,
+        language: Language,
+        revision: Option<(u64, u64)>,
+        text: &str,
+    ) -> &[Span] {
+        if revision.is_none() || self.revision != revision || self.language != Some(language) {
+            self.highlight(language, text);
+            self.revision = revision;
+        }
+        &self.spans
+    }
+
+    pub fn highlight(&mut self, language: Language, text: &str) -> &[Span] {
+        self.revision = None;
+        if language == Language::PlainText {
+            self.spans.clear();
+            self.language = Some(language);
+            return &self.spans;
+        }
+
+        let text_hash = hash_str(text);
+        if self.language == Some(language) && self.text_hash == text_hash {
+            return &self.spans;
+        }
+        if self.language != Some(language) {
+            self.lines.clear();
+        }
+
+        let mut previous = std::mem::take(&mut self.lines);
+        self.spans.clear();
+        let mut state = State::Normal;
+        let mut offset = 0;
+
+        for line in text.split('\n') {
+            let key = (hash_str(line), state);
+            let cached = self.lines.entry(key).or_insert_with(|| {
+                previous.remove(&key).unwrap_or_else(|| {
+                    let mut spans = Vec::new();
+                    let end = tokenize_line(language, line, state, &mut spans);
+                    CachedLine { end, spans }
+                })
+            });
+            self.spans.extend(
+                cached
+                    .spans
+                    .iter()
+                    .map(|(r, t)| (r.start + offset..r.end + offset, *t)),
+            );
+            state = cached.end;
+            offset += line.len() + 1;
+        }
+
+        self.language = Some(language);
+        self.text_hash = text_hash;
+        &self.spans
+    }
+}
+
+fn tokenize_line(language: Language, line: &str, state: State, out: &mut Vec<Span>) -> State {
+    match language {
+        Language::PlainText => State::Normal,
+        Language::Rust => rust_line(line, state, out),
+        Language::Toml => toml_line(line, state, out),
+        Language::Json => json_line(line, out),
+        Language::Yaml => yaml_line(line, out),
+        Language::Shell => shell_line(line, state, out),
+        Language::Markdown => markdown_line(line, state, out),
+    }
+}
+
+fn push(out: &mut Vec<Span>, range: Range<usize>, token: Token) {
+    if !range.is_empty() {
+        out.push((range, token));
+    }
+}
+
+fn is_ident_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_' || b >= 0x80
+}
+
+fn is_ident(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80
+}
+
+fn ident_end(b: &[u8], mut i: usize) -> usize {
+    while i < b.len() && is_ident(b[i]) {
+        i += 1;
+    }
+    i
+}
+
+fn skip_spaces(b: &[u8], mut i: usize) -> usize {
+    while i < b.len() && (b[i] == b' ' || b[i] == b'\t') {
+        i += 1;
+    }
+    i
+}
+
+fn number_end(b: &[u8], mut i: usize) -> usize {
+    while i < b.len() {
+        let c = b[i];
+        let decimal_point = c == b'.' && b.get(i + 1).is_some_and(u8::is_ascii_digit);
+        if c.is_ascii_alphanumeric() || c == b'_' || decimal_point {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    i
+}
+
+/// Index just past the closing `quote` at or after `i`, honoring backslash
+/// escapes if `escapes` is set
+fn quoted_end(b: &[u8], mut i: usize, quote: u8, escapes: bool) -> Option<usize> {
+    while i < b.len() {
+        if escapes && b[i] == b'\\' {
+            i += 2;
+        } else if b[i] == quote {
+            return Some(i + 1);
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+fn block_comment_end(b: &[u8], mut i: usize, mut depth: u8) -> (usize, u8) {
+    while i < b.len() && depth > 0 {
+        if b[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+        } else if b[i..].starts_with(b"/*") {
+            depth = depth.saturating_add(1);
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    (i.min(b.len()), depth)
+}
+
+fn rust_string_end(b: &[u8], i: usize, raw_hashes: Option<u8>) -> Option<usize> {
+    match raw_hashes {
+        None => quoted_end(b, i, b'"', true),
+        Some(hashes) => {
+            let hashes = hashes as usize;
+            (i..b.len())
+                .find(|&j| {
+                    b[j] == b'"'
+                        && b.len() >= j + 1 + hashes
+                        && b[j + 1..j + 1 + hashes].iter().all(|&c| c == b'#')
+                })
+                .map(|j| j + 1 + hashes)
+        }
+    }
+}
+
+fn rust_line(line: &str, state: State, out: &mut Vec<Span>) -> State {
+    let b = line.as_bytes();
+    let mut i = 0;
+
+    match state {
+        State::BlockComment(depth) => {
+            let (end, depth) = block_comment_end(b, 0, depth);
+            push(out, 0..end, Token::Comment);
+            if depth > 0 {
+                return State::BlockComment(depth);
+            }
+            i = end;
+        }
+        State::RustString { raw_hashes } => match rust_string_end(b, 0, raw_hashes) {
+            Some(end) => {
+                push(out, 0..end, Token::String);
+                i = end;
+            }
+            None => {
+                push(out, 0..b.len(), Token::String);
+                return state;
+            }
+        },
+        _ => {}
+    }
+
+    while i < b.len() {
+        let c = b[i];
+        let start = i;
+
+        if b[i..].starts_with(b"//") {
+            push(out, i..b.len(), Token::Comment);
+            break;
+        } else if b[i..].starts_with(b"/*") {
+            let (end, depth) = block_comment_end(b, i + 2, 1);
+            push(out, start..end, Token::Comment);
+            if depth > 0 {
+                return State::BlockComment(depth);
+            }
+            i = end;
+        } else if let Some((quote, raw_hashes)) = rust_string_start(b, i) {
+            match rust_string_end(b, quote + 1, raw_hashes) {
+                Some(end) => {
+                    push(out, start..end, Token::String);
+                    i = end;
+                }
+                None => {
+                    push(out, start..b.len(), Token::String);
+                    return State::RustString { raw_hashes };
+                }
+            }
+        } else if c == b'\'' {
+            i = rust_quote(line, i, out);
+        } else if c == b'#' && matches!(b.get(i + 1), Some(b'[') | Some(b'!')) {
+            let mut depth = 0;
+            while i < b.len() {
+                match b[i] {
+                    b'[' => depth += 1,
+                    b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            i += 1;
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            push(out, start..i, Token::Attribute);
+        } else if c.is_ascii_digit() {
+            i = number_end(b, i);
+            push(out, start..i, Token::Number);
+        } else if is_ident_start(c) {
+            i = ident_end(b, i);
+            let word = &line[start..i];
+            let next = skip_spaces(b, i);
+            if b.get(i) == Some(&b'!') && !RUST_KEYWORDS.contains(&word) {
+                i += 1;
+                push(out, start..i, Token::Macro);
+            } else if RUST_KEYWORDS.contains(&word) {
+                push(out, start..i, Token::Keyword);
+            } else if word == "true" || word == "false" {
+                push(out, start..i, Token::Constant);
+            } else if RUST_PRIMITIVES.contains(&word) || word.starts_with(char::is_uppercase) {
+                push(out, start..i, Token::Type);
+            } else if b.get(next) == Some(&b'(') {
+                push(out, start..i, Token::Function);
+            }
+        } else {
+            i += 1;
+        }
+    }
+
+    State::Normal
+}
+
+/// If a Rust string literal starts at `i`, returns the index of its opening
+/// quote and, for raw strings, the number of `#`s
+fn rust_string_start(b: &[u8], i: usize) -> Option<(usize, Option<u8>)> {
+    // Only at the start of a token, so `bar"` is not read as a raw string
+    if i > 0 && is_ident(b[i - 1]) {
+        return None;
+    }
+    let mut j = i;
+    if b.get(j) == Some(&b'b') {
+        j += 1;
+    }
+    if b.get(j) == Some(&b'"') {
+        return Some((j, None));
+    }
+    if b.get(j) != Some(&b'r') {
+        return None;
+    }
+    j += 1;
+    let hashes_start = j;
+    while b.get(j) == Some(&b'#') {
+        j += 1;
+    }
+    (b.get(j) == Some(&b'"')).then(|| (j, Some((j - hashes_start) as u8)))
+}
+
+/// A char literal or a lifetime starting at the quote at `i`. Returns the
+/// index just past it.
+fn rust_quote(line: &str, i: usize, out: &mut Vec<Span>) -> usize {
+    let b = line.as_bytes();
+    if b.get(i + 1) == Some(&b'\\') {
+        let end = quoted_end(b, i + 2, b'\'', false).unwrap_or(b.len());
+        push(out, i..end, Token::String);
+        return end;
+    }
+    if let Some(ch) = line[i + 1..].chars().next() {
+        let after = i + 1 + ch.len_utf8();
+        if b.get(after) == Some(&b'\'') {
+            push(out, i..after + 1, Token::String);
+            return after + 1;
+        }
+    }
+    let end = ident_end(b, i + 1);
+    push(out, i..end, Token::Attribute);
+    end.max(i + 1)
+}
+
+/// Scalars shared by TOML and JSON: strings, numbers and constants. Bare
+/// words and strings followed by `key_separator` are keys.
+fn scan_values(line: &str, mut i: usize, key_separator: u8, out: &mut Vec<Span>) {
+    let b = line.as_bytes();
+    while i < b.len() {
+        let c = b[i];
+        let start = i;
+        if c == b'#' && key_separator == b'=' || b[i..].starts_with(b"//") && key_separator == b':'
+        {
+            push(out, i..b.len(), Token::Comment);
+            return;
+        } else if c == b'"' || c == b'\'' {
+            i = quoted_end(b, i + 1, c, c == b'"').unwrap_or(b.len());
+            let token = if b.get(skip_spaces(b, i)) == Some(&key_separator) {
+                Token::Key
+            } else {
+                Token::String
+            };
+            push(out, start..i, token);
+        } else if c.is_ascii_digit()
+            || (c == b'-' || c == b'+') && b.get(i + 1).is_some_and(u8::is_ascii_digit)
+        {
+            i += 1;
+            while i < b.len() && (is_ident(b[i]) || matches!(b[i], b'.' | b':' | b'-' | b'+')) {
+                i += 1;
+            }
+            push(out, start..i, Token::Number);
+        } else if is_ident_start(c) {
+            while i < b.len() && (is_ident(b[i]) || b[i] == b'-' || b[i] == b'.') {
+                i += 1;
+            }
+            let token = match &line[start..i] {
+                _ if b.get(skip_spaces(b, i)) == Some(&key_separator) => Some(Token::Key),
+                "true" | "false" | "null" => Some(Token::Constant),
+                "inf" | "nan" => Some(Token::Number),
+                _ => None,
+            };
+            if let Some(token) = token {
+                push(out, start..i, token);
+            }
+        } else {
+            i += 1;
+        }
+    }
+}
+
+fn toml_line(line: &str, state: State, out: &mut Vec<Span>) -> State {
+    let b = line.as_bytes();
+    let mut i = 0;
+
+    if let State::MultiString { quote, .. } = state {
+        let closing = [quote; 3];
+        match b.windows(3).position(|w| w == closing) {
+            Some(pos) => {
+                push(out, 0..pos + 3, Token::String);
+                i = pos + 3;
+            }
+            None => {
+                push(out, 0..b.len(), Token::String);
+                return state;
+            }
+        }
+    }
+
+    let first = skip_spaces(b, i);
+    if i == 0 && b.get(first) == Some(&b'[') {
+        let end = b
+            .iter()
+            .rposition(|&c| c == b']')
+            .map_or(b.len(), |p| p + 1);
+        push(out, first..end, Token::Attribute);
+        i = end;
+    }
+
+    // Multi-line strings are found first; the rest is plain scalars
+    let mut scan_from = i;
+    while let Some(pos) =
+        (scan_from..b.len().saturating_sub(2)).find(|&j| matches!(&b[j..j + 3], b"\"\"\"" | b"'''"))
+    {
+        let comment = (scan_from..pos).find(|&j| b[j] == b'#');
+        if comment.is_some() {
+            break;
+        }
+        scan_values(line, scan_from, b'=', out);
+        out.retain(|(r, _)| r.end <= pos);
+        let quote = b[pos];
+        match b[pos + 3..].windows(3).position(|w| w == [quote; 3]) {
+            Some(close) => {
+                let end = pos + 3 + close + 3;
+                push(out, pos..end, Token::String);
+                scan_from = end;
+            }
+            None => {
+                push(out, pos..b.len(), Token::String);
+                return State::MultiString {
+                    quote,
+                    triple: true,
+                };
+            }
+        }
+    }
+
+    scan_values(line, scan_from, b'=', out);
+    State::Normal
+}
+
+fn json_line(line: &str, out: &mut Vec<Span>) -> State {
+    scan_values(line, 0, b':', out);
+    State::Normal
+}
+
+fn yaml_line(line: &str, out: &mut Vec<Span>) -> State {
+    let b = line.as_bytes();
+    let mut i = skip_spaces(b, 0);
+
+    if line.starts_with("---") || line.starts_with("...") {
+        push(out, 0..3, Token::Keyword);
+        i = 3;
+    }
+
+    while b.get(i) == Some(&b'-') && matches!(b.get(i + 1), None | Some(b' ')) {
+        push(out, i..i + 1, Token::ListMarker);
+        i = skip_spaces(b, i + 1);
+    }
+
+    // `key:` or `"key":`, where the colon is followed by a space or the end
+    let key_end = if matches!(b.get(i), Some(b'"') | Some(b'\'')) {
+        quoted_end(b, i + 1, b[i], b[i] == b'"').filter(|&end| b.get(end) == Some(&b':'))
+    } else {
+        (i..b.len())
+            .take_while(|&j| b[j] != b'#')
+            .find(|&j| b[j] == b':' && matches!(b.get(j + 1), None | Some(b' ')))
+    };
+    if let Some(end) = key_end.filter(|&end| end > i) {
+        push(out, i..end, Token::Key);
+        i = end + 1;
+    }
+
+    while i < b.len() {
+        i = skip_spaces(b, i);
+        let Some(&c) = b.get(i) else {
+            break;
+        };
+        let start = i;
+        match c {
+            b'#' => {
+                push(out, i..b.len(), Token::Comment);
+                break;
+            }
+            b'"' | b'\'' => {
+                i = quoted_end(b, i + 1, c, c == b'"').unwrap_or(b.len());
+                push(out, start..i, Token::String);
+            }
+            b'&' | b'*' | b'!' => {
+                i += 1;
+                while i < b.len() && !matches!(b[i], b' ' | b',' | b']' | b'}') {
+                    i += 1;
+                }
+                let token = if c == b'!' {
+                    Token::Type
+                } else {
+                    Token::Variable
+                };
+                push(out, start..i, token);
+            }
+            b'|' | b'>' => {
+                i += 1;
+                push(out, start..i, Token::Keyword);
+            }
+            b'[' | b']' | b'{' | b'}' | b',' => i += 1,
+            _ => {
+                // A plain scalar runs to a comment or the end of the line
+                let end = (i..b.len())
+                    .find(|&j| b[j] == b'#' && b[j - 1] == b' ')
+                    .unwrap_or(b.len());
+                let value = line[start..end].trim_end();
+                let token = match value {
+                    "true" | "false" | "yes" | "no" | "on" | "off" | "null" | "~" => {
+                        Token::Constant
+                    }
+                    _ if value.parse::<f64>().is_ok() => Token::Number,
+                    _ => Token::String,
+                };
+                push(out, start..start + value.len(), token);
+                i = end;
+            }
+        }
+    }
+
+    State::Normal
+}
+
+fn shell_line(line: &str, state: State, out: &mut Vec<Span>) -> State {
+    let b = line.as_bytes();
+    let mut i = 0;
+
+    if let State::MultiString { quote, .. } = state {
+        match quoted_end(b, 0, quote, quote == b'"') {
+            Some(end) => {
+                push(out, 0..end, Token::String);
+                i = end;
+            }
+            None => {
+                push(out, 0..b.len(), Token::String);
+                return state;
+            }
+        }
+    }
+
+    while i < b.len() {
+        let c = b[i];
+        let start = i;
+        if c == b'#' && (i == 0 || b[i - 1] == b' ' || b[i - 1] == b'\t') {
+            push(out, i..b.len(), Token::Comment);
+            break;
+        } else if c == b'"' || c == b'\'' {
+            match quoted_end(b, i + 1, c, c == b'"') {
+                Some(end) => {
+                    push(out, start..end, Token::String);
+                    i = end;
+                }
+                None => {
+                    push(out, start..b.len(), Token::String);
+                    return State::MultiString {
+                        quote: c,
+                        triple: false,
+                    };
+                }
+            }
+        } else if c == b'$' {
+            i += 1;
+            match b.get(i) {
+                Some(b'{') => {
+                    i = b[i..]
+                        .iter()
+                        .position(|&c| c == b'}')
+                        .map_or(b.len(), |p| i + p + 1);
+                }
+                Some(&c) if is_ident(c) => i = ident_end(b, i),
+                Some(b'@' | b'*' | b'#' | b'?' | b'$' | b'!' | b'-') => i += 1,
+                _ => {}
+            }
+            push(out, start..i, Token::Variable);
+        } else if c.is_ascii_digit() && (i == 0 || !is_ident(b[i - 1])) {
+            i = number_end(b, i);
+            push(out, start..i, Token::Number);
+        } else if is_ident_start(c) {
+            i = ident_end(b, i);
+            let word = &line[start..i];
+            if b.get(i) == Some(&b'=') {
+                push(out, start..i, Token::Variable);
+            } else if SHELL_KEYWORDS.contains(&word) {
+                push(out, start..i, Token::Keyword);
+            } else if b.get(skip_spaces(b, i)) == Some(&b'(') && b.get(i + 1) != Some(&b'(') {
+                push(out, start..i, Token::Function);
+            }
+        } else {
+            i += 1;
+        }
+    }
+
+    State::Normal
+}
+
+/// Length of the run of `c` starting at `i`
+fn run_length(b: &[u8], i: usize, c: u8) -> usize {
+    b[i..].iter().take_while(|&&x| x == c).count()
+}
+
+fn markdown_line(line: &str, state: State, out: &mut Vec<Span>) -> State {
+    let b = line.as_bytes();
+    let indent = skip_spaces(b, 0);
+
+    if let State::CodeFence { marker, len } = state {
+        push(out, 0..b.len(), Token::Code);
+        let closing = run_length(b, indent, marker);
+        let closes = closing >= len as usize && line[indent + closing..].trim().is_empty();
+        return if closes { State::Normal } else { state };
+    }
+
+    if let Some(&marker) = b.get(indent).filter(|&&c| c == b'`' || c == b'~') {
+        let len = run_length(b, indent, marker);
+        if len >= 3 {
+            push(out, 0..b.len(), Token::Code);
+            return State::CodeFence {
+                marker,
+                len: len.min(u8::MAX as usize) as u8,
+            };
+        }
+    }
+
+    let hashes = run_length(b, indent, b'#');
+    if (1..=6).contains(&hashes) && matches!(b.get(indent + hashes), None | Some(b' ')) {
+        push(out, 0..b.len(), Token::Heading);
+        return State::Normal;
+    }
+
+    if b.get(indent) == Some(&b'>') {
+        push(out, 0..b.len(), Token::Quote);
+        return State::Normal;
+    }
+
+    // A thematic break: three or more of the same marker, maybe spaced out
+    let marks: Vec<u8> = b
+        .iter()
+        .copied()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    let rule = marks.len() >= 3
+        && matches!(marks[0], b'-' | b'*' | b'_')
+        && marks.iter().all(|&c| c == marks[0]);
+    if rule {
+        push(out, 0..b.len(), Token::ListMarker);
+        return State::Normal;
+    }
+
+    let mut i = indent;
+    let digits = b[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+    let marker_len = match b.get(i + digits) {
+        Some(b'.' | b')') if digits > 0 => digits + 1,
+        Some(b'-' | b'*' | b'+') if digits == 0 => 1,
+        _ => 0,
+    };
+    if marker_len > 0 && matches!(b.get(i + marker_len), None | Some(b' ')) {
+        push(out, i..i + marker_len, Token::ListMarker);
+        i = skip_spaces(b, i + marker_len);
+        let task = &b[i..];
+        if task.len() >= 3 && task[0] == b'[' && task[2] == b']' && b" xX".contains(&task[1]) {
+            push(out, i..i + 3, Token::Keyword);
+            i += 3;
+        }
+    }
+
+    markdown_inline(line, i, out);
+    State::Normal
+}
+
+fn markdown_inline(line: &str, mut i: usize, out: &mut Vec<Span>) {
+    let b = line.as_bytes();
+    while i < b.len() {
+        let c = b[i];
+        let start = i;
+        match c {
+            b'`' => {
+                let ticks = run_length(b, i, b'`');
+                let fence = &line[i..i + ticks];
+                match line[i + ticks..].find(fence) {
+                    Some(pos) => {
+                        i += ticks + pos + ticks;
+                        push(out, start..i, Token::Code);
+                    }
+                    None => i += ticks,
+                }
+            }
+            b'*' | b'_' => {
+                let run = run_length(b, i, c).min(2);
+                let word_inside = c == b'_' && i > 0 && is_ident(b[i - 1]);
+                let delimiter = &line[i..i + run];
+                let close = line[i + run..].find(delimiter).filter(|&p| p > 0);
+                match close.filter(|_| !word_inside) {
+                    Some(pos) => {
+                        i += run + pos + run;
+                        let token = if run == 2 {
+                            Token::Strong
+                        } else {
+                            Token::Emphasis
+                        };
+                        push(out, start..i, token);
+                    }
+                    None => i += run,
+                }
+            }
+            b'[' | b'!' => {
+                let open = if c == b'!' { i + 1 } else { i };
+                let link_end = (b.get(open) == Some(&b'['))
+                    .then(|| line[open..].find("](").map(|p| open + p + 2))
+                    .flatten()
+                    .and_then(|url| line[url..].find(')').map(|p| url + p + 1));
+                match link_end {
+                    Some(end) => {
+                        push(out, start..end, Token::Link);
+                        i = end;
+                    }
+                    None => i += 1,
+                }
+            }
+            b'<' if line[i..].starts_with("<http") => {
+                i = line[i..].find('>').map_or(b.len(), |p| i + p + 1);
+                push(out, start..i, Token::Link);
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Token::*;
+
+    fn tokens(language: Language, text: &str) -> Vec<(&str, Token)> {
+        Highlighter::default()
+            .highlight(language, text)
+            .iter()
+            .map(|(range, token)| (&text[range.clone()], *token))
+            .collect()
+    }
+
+    #[test]
+    fn rust_strings_and_comments() {
+        assert_eq!(
+            tokens(
+                Language::Rust,
+                "let s = \"open\nstill\"; // done\n/* a /* b */ c */ fn f() {}"
+            ),
+            [
+                ("let", Keyword),
+                ("\"open", String),
+                ("still\"", String),
+                ("// done", Comment),
+                ("/* a /* b */ c */", Comment),
+                ("fn", Keyword),
+                ("f", Function),
+            ]
+        );
+        assert_eq!(
+            tokens(Language::Rust, "/* open\nlet x = 1;\n*/ true"),
+            [
+                ("/* open", Comment),
+                ("let x = 1;", Comment),
+                ("*/", Comment),
+                ("true", Constant),
+            ]
+        );
+    }
+
+    #[test]
+    fn rust_raw_strings() {
+        assert_eq!(
+            tokens(
+                Language::Rust,
+                "r#\"a \"b\" c\"# x\nr##\"one\n\"# two\n\"##;"
+            ),
+            [
+                ("r#\"a \"b\" c\"#", String),
+                ("r##\"one", String),
+                ("\"# two", String),
+                ("\"##", String),
+            ]
+        );
+        // Backslashes do not escape in raw strings
+        assert_eq!(
+            tokens(Language::Rust, "br\"a\\\" b\"c\""),
+            [("br\"a\\\"", String), ("b\"c\"", String)]
+        );
+    }
+
+    #[test]
+    fn rust_multi_byte_text() {
+        assert_eq!(
+            tokens(
+                Language::Rust,
+                "let café = 'é'; fn f<'a>() -> Über { \"ñ\" } // ü"
+            ),
+            [
+                ("let", Keyword),
+                ("'é'", String),
+                ("fn", Keyword),
+                ("'a", Attribute),
+                ("Über", Type),
+                ("\"ñ\"", String),
+                ("// ü", Comment),
+            ]
+        );
+    }
+
+    #[test]
+    fn toml() {
+        assert_eq!(
+            tokens(
+                Language::Toml,
+                "[table]\nkey = \"v # not\" # c\nmulti = '''\nline\n'''\n名前 = \"open"
+            ),
+            [
+                ("[table]", Attribute),
+                ("key", Key),
+                ("\"v # not\"", String),
+                ("# c", Comment),
+                ("multi", Key),
+                ("'''", String),
+                ("line", String),
+                ("'''", String),
+                ("名前", Key),
+                ("\"open", String),
+            ]
+        );
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            tokens(
+                Language::Json,
+                "{\"kéy\": \"välue\", \"n\": -1.5, \"ok\": true, \"x\": null, \"s\": \"open"
+            ),
+            [
+                ("\"kéy\"", Key),
+                ("\"välue\"", String),
+                ("\"n\"", Key),
+                ("-1.5", Number),
+                ("\"ok\"", Key),
+                ("true", Constant),
+                ("\"x\"", Key),
+                ("null", Constant),
+                ("\"s\"", Key),
+                ("\"open", String),
+            ]
+        );
+    }
+
+    #[test]
+    fn yaml() {
+        assert_eq!(
+            tokens(
+                Language::Yaml,
+                "---\nname: Zoë # who\nlist:\n  - \"open\n  - 3.5\n  - yes\nanchor: &a !!str"
+            ),
+            [
+                ("---", Keyword),
+                ("name", Key),
+                ("Zoë", String),
+                ("# who", Comment),
+                ("list", Key),
+                ("-", ListMarker),
+                ("\"open", String),
+                ("-", ListMarker),
+                ("3.5", Number),
+                ("-", ListMarker),
+                ("yes", Constant),
+                ("anchor", Key),
+                ("&a", Variable),
+                ("!!str", Type),
+            ]
+        );
+    }
+
+    #[test]
+    fn shell() {
+        assert_eq!(
+            tokens(
+                Language::Shell,
+                "echo \"$HOME 'x'\" # note\nname=välue $1\nif [ -n \"${X}\" ]; then\nmsg='open\nstill' 2\nf() {"
+            ),
+            [
+                ("\"$HOME 'x'\"", String),
+                ("# note", Comment),
+                ("name", Variable),
+                ("$1", Variable),
+                ("if", Keyword),
+                ("\"${X}\"", String),
+                ("then", Keyword),
+                ("msg", Variable),
+                ("'open", String),
+                ("still'", String),
+                ("2", Number),
+                ("f", Function),
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown() {
+        assert_eq!(
+            tokens(
+                Language::Markdown,
+                "# Héad\n```rust\nlet x = \"*no*\";\n```\n- [ ] **bold** `code`\n> quote\n_em_ [l](u) snake_case"
+            ),
+            [
+                ("# Héad", Heading),
+                ("```rust", Code),
+                ("let x = \"*no*\";", Code),
+                ("```", Code),
+                ("-", ListMarker),
+                ("[ ]", Keyword),
+                ("**bold**", Strong),
+                ("`code`", Code),
+                ("> quote", Quote),
+                ("_em_", Emphasis),
+                ("[l](u)", Link),
+            ]
+        );
+        // An unclosed fence runs to the end
+        assert_eq!(
+            tokens(Language::Markdown, "~~~~\n# not a heading\n~~~"),
+            [("~~~~", Code), ("# not a heading", Code), ("~~~", Code),]
+        );
+    }
+
+    #[test]
+    fn edits_invalidate_the_cache() {
+        let mut highlighter = Highlighter::default();
+        let before = "fn a() {}\nlet b = 2;";
+        let after = "/* fn a() {}\nlet b = 2;";
+        let spans = highlighter.highlight(Language::Rust, before).to_vec();
+        assert_eq!(spans.last(), Some(&(18..19, Number)));
+
+        // An edit on the first line changes how the unchanged second line
+        // starts
+        let spans = highlighter.highlight(Language::Rust, after).to_vec();
+        assert_eq!(spans, [(0..12, Comment), (13..23, Comment)]);
+
+        let spans = highlighter.highlight(Language::Rust, before).to_vec();
+        assert_eq!(spans.last(), Some(&(18..19, Number)));
+        assert_eq!(highlighter.highlight(Language::PlainText, before), []);
+    }
+
+    #[test]
+    fn revisions_skip_rehighlighting_until_they_change() {
+        let mut highlighter = Highlighter::default();
+        let spans = highlighter.highlight_revision(Language::Rust, Some((1, 1)), "let a;");
+        assert_eq!(spans, [(0..3, Keyword)]);
+
+        // Same revision: the cached spans are trusted
+        let spans = highlighter.highlight_revision(Language::Rust, Some((1, 1)), "// a");
+        assert_eq!(spans, [(0..3, Keyword)]);
+
+        let spans = highlighter.highlight_revision(Language::Rust, Some((1, 2)), "// a");
+        assert_eq!(spans, [(0..4, Comment)]);
+        let spans = highlighter.highlight_revision(Language::Rust, None, "fn");
+        assert_eq!(spans, [(0..2, Keyword)]);
+        let spans = highlighter.highlight_revision(Language::Toml, Some((1, 2)), "a = 1");
+        assert_eq!(spans, [(0..1, Key), (4..5, Number)]);
+    }
+}
@@ -11,6 +11,13 @@ pub struct CatppuccinPalette {
     pub surface2: egui::Color32,
     pub blue: egui::Color32,
     pub sapphire: egui::Color32,
+    pub green: egui::Color32,
+    pub red: egui::Color32,
+    pub mauve: egui::Color32,
+    pub peach: egui::Color32,
+    pub yellow: egui::Color32,
+    pub teal: egui::Color32,
+    pub overlay0: egui::Color32,
     pub text: egui::Color32,
     pub selection_alpha: f32,
     pub is_dark: bool,
@@ -27,6 +34,13 @@ impl CatppuccinPalette {
             surface2: egui::Color32::from_rgb(172, 176, 190),
             blue: egui::Color32::from_rgb(30, 102, 245),
             sapphire: egui::Color32::from_rgb(32, 159, 181),
+            green: egui::Color32::from_rgb(64, 160, 43),
+            red: egui::Color32::from_rgb(210, 15, 57),
+            mauve: egui::Color32::from_rgb(136, 57, 239),
+            peach: egui::Color32::from_rgb(254, 100, 11),
+            yellow: egui::Color32::from_rgb(223, 142, 29),
+            teal: egui::Color32::from_rgb(23, 146, 153),
+            overlay0: egui::Color32::from_rgb(156, 160, 176),
             text: egui::Color32::from_rgb(76, 79, 105),
             selection_alpha: 0.3,
             is_dark: false,
@@ -43,6 +57,13 @@ impl CatppuccinPalette {
             surface2: egui::Color32::from_rgb(88, 91, 112),
             blue: egui::Color32::from_rgb(137, 180, 250),
             sapphire: egui::Color32::from_rgb(116, 199, 236),
+            green: egui::Color32::from_rgb(166, 227, 161),
+            red: egui::Color32::from_rgb(243, 139, 168),
+            mauve: egui::Color32::from_rgb(203, 166, 247),
+            peach: egui::Color32::from_rgb(250, 179, 135),
+            yellow: egui::Color32::from_rgb(249, 226, 175),
+            teal: egui::Color32::from_rgb(148, 226, 213),
+            overlay0: egui::Color32::from_rgb(108, 112, 134),
             text: egui::Color32::from_rgb(138, 173, 244),
             selection_alpha: 0.4,
             is_dark: true,
@@ -129,11 +150,15 @@ pub fn apply_palette(ctx: &egui::Context, palette: &CatppuccinPalette) {
     ctx.set_style(style);
 }
 
-pub fn get_theme_colors(dark_mode: bool) -> (egui::Color32, egui::Color32) {
-    let p = if dark_mode {
+pub fn palette(dark_mode: bool) -> CatppuccinPalette {
+    if dark_mode {
         CatppuccinPalette::mocha()
     } else {
         CatppuccinPalette::latte()
-    };
+    }
+}
+
+pub fn get_theme_colors(dark_mode: bool) -> (egui::Color32, egui::Color32) {
+    let p = palette(dark_mode);
     (p.base, p.text)
 }
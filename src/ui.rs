@@ -1,27 +1,76 @@
 use eframe::egui;
 
 use crate::app::NotepadApp;
-use crate::theme;
+use crate::atomic_file::BackupMode;
+use crate::commands::Command;
+use crate::document::Document;
+use crate::encoding::TextEncoding;
+use crate::export::ExportFormat;
+use crate::file_watch::ExternalChange;
+use crate::forecast;
+use crate::gutter;
+use crate::language::Language;
+use crate::line_ending::LineEnding;
+use crate::pdf::{PageSize, PdfFont};
+use crate::preview::{self, PreviewState};
+use crate::split::SplitOrientation;
+use crate::stats::{DetailedStats, DocStats};
+use crate::syntax;
+use crate::theme::{self, CatppuccinPalette};
+use crate::weather::{self, LocationSource, TemperatureUnit, WindUnit};
+use crate::wrap::WrapMode;
+use similar::ChangeTag;
+use std::ops::Range;
+use std::time::Duration;
 
 const ELEMENT_SPACING: f32 = 10.0;
 const TITLE_BAR_FONT_SIZE: f32 = 14.0;
+const STATUS_BAR_FONT_SIZE: f32 = 12.5;
+const HISTORY_MENU_LEN: usize = 15;
+const FIND_FIELD_WIDTH: f32 = 220.0;
+const PALETTE_WIDTH: f32 = 420.0;
+const PALETTE_LIST_HEIGHT: f32 = 320.0;
 
 impl NotepadApp {
-    pub fn render_title_bar(&self, ctx: &egui::Context) {
+    pub fn render_title_bar(&mut self, ctx: &egui::Context) {
         let (base_color, text_color) = theme::get_theme_colors(self.dark_mode);
 
-        let weather_text = if let Ok(weather) = self.weather.lock() {
-            if let Some(ref info) = *weather {
+        let located = weather::has_location(&self.settings.weather);
+        // Stale or offline note, with the error behind it for the hover text
+        let mut weather_status: Option<(String, Option<String>)> = None;
+        let weather_text = if !self.settings.weather.enabled {
+            String::new()
+        } else if !located {
+            "Set a weather location in Settings".to_string()
+        } else if let Ok(weather) = self.weather.lock() {
+            let error = weather.error.as_ref().map(|e| e.to_string());
+            if let Some(ref info) = weather.info {
+                let refresh = Duration::from_secs(self.settings.weather.refresh_secs);
+                let age = weather.updated.map(|updated| updated.elapsed());
+                if weather.error.is_some() || age.is_some_and(|age| age > refresh * 2) {
+                    let ago =
+                        age.map_or_else(String::new, |age| format!(" (updated {})", time_ago(age)));
+                    weather_status = Some((format!("stale{}", ago), error));
+                }
+                let units = &self.settings.weather;
                 format!(
-                    "{} {:.0}°F {}",
-                    info.icon, info.temperature_f, info.description
+                    "{}  {} {} {}  \u{1F4A8} {}",
+                    info.place,
+                    info.icon,
+                    info.temperature_text(units.temperature_unit),
+                    info.description,
+                    info.wind_text(units.wind_unit)
                 )
+            } else if let Some(ref e) = weather.error {
+                weather_status = Some((e.summary().to_string(), error));
+                String::new()
             } else {
                 "Loading...".to_string()
             }
         } else {
             String::new()
         };
+        let show_refresh = self.settings.weather.enabled && located;
 
         egui::TopBottomPanel::top("title_bar")
             .exact_height(32.0)
@@ -41,11 +90,38 @@ impl NotepadApp {
 
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         ui.add_space(15.0);
-                        ui.label(
+                        if show_refresh
+                            && ui
+                                .small_button("\u{27F3}")
+                                .on_hover_text("Refresh the weather now")
+                                .clicked()
+                        {
+                            self.refresh_weather_now();
+                        }
+                        if let Some((status, detail)) = &weather_status {
+                            let response = ui.label(
+                                egui::RichText::new(status)
+                                    .color(ui.visuals().warn_fg_color)
+                                    .size(TITLE_BAR_FONT_SIZE),
+                            );
+                            if let Some(detail) = detail {
+                                response.on_hover_text(detail);
+                            }
+                        }
+                        let label = egui::Label::new(
                             egui::RichText::new(&weather_text)
                                 .color(text_color)
                                 .size(TITLE_BAR_FONT_SIZE),
-                        );
+                        )
+                        .sense(egui::Sense::click());
+                        if ui
+                            .add(label)
+                            .on_hover_text("Show the forecast")
+                            .on_hover_cursor(egui::CursorIcon::PointingHand)
+                            .clicked()
+                        {
+                            self.show_forecast = !self.show_forecast;
+                        }
                     });
                 });
             });
@@ -54,88 +130,348 @@ impl NotepadApp {
     pub fn render_menu_bar(&mut self, ctx: &egui::Context) {
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
-                ui.menu_button("\u{1F4C4} File", |ui| {
-                    if ui
-                        .add(egui::Button::new("New").shortcut_text("Ctrl+N"))
-                        .clicked()
-                    {
-                        self.new_file();
-                        ui.close_menu();
-                    }
-
-                    if ui
-                        .add(egui::Button::new("Open...").shortcut_text("Ctrl+O"))
-                        .clicked()
-                    {
-                        self.open_file();
-                        ui.close_menu();
-                    }
+                let file_menu = ui.menu_button("\u{1F4C4} File", |ui| {
+                    self.command_button(ui, ctx, Command::New);
+                    self.command_button(ui, ctx, Command::Open);
+                    self.render_recent_menu(ui);
 
                     ui.separator();
 
-                    if ui
-                        .add(egui::Button::new("Save").shortcut_text("Ctrl+S"))
-                        .clicked()
-                    {
-                        self.save_file();
-                        ui.close_menu();
-                    }
+                    self.command_button(ui, ctx, Command::Save);
+                    self.command_button(ui, ctx, Command::SaveAs);
 
-                    if ui
-                        .add(egui::Button::new("Save As...").shortcut_text("Ctrl+Shift+S"))
-                        .clicked()
-                    {
-                        self.save_file_as();
-                        ui.close_menu();
-                    }
+                    let has_path = self.active_doc().file_path.is_some();
+                    ui.add_enabled_ui(has_path, |ui| {
+                        ui.menu_button("Reopen with Encoding", |ui| {
+                            for &encoding in TextEncoding::ALL {
+                                self.command_button(ui, ctx, Command::ReopenWithEncoding(encoding));
+                            }
+                        });
+                    });
+                    ui.menu_button("Save with Encoding", |ui| {
+                        for &encoding in TextEncoding::ALL {
+                            self.command_button(ui, ctx, Command::SaveWithEncoding(encoding));
+                        }
+                    });
+
+                    self.render_export_menu(ui, ctx);
 
                     ui.separator();
 
-                    if ui.button("Exit").clicked() {
-                        self.request_exit(ctx);
-                        ui.close_menu();
-                    }
+                    self.command_button(ui, ctx, Command::ToggleReadOnly);
+                    self.command_button(ui, ctx, Command::CloseTab);
+
+                    ui.separator();
+
+                    self.command_button(ui, ctx, Command::Exit);
                 });
+                if file_menu.inner.is_none() {
+                    // Prune the recent files again next time it opens
+                    self.recent_checked = false;
+                }
+
+                self.render_edit_menu(ui, ctx);
+                self.render_view_menu(ui, ctx);
 
                 ui.menu_button("\u{2699} Settings", |ui| {
-                    ui.label("Editor Font Size");
-                    ui.horizontal(|ui| {
-                        if ui.button("-").clicked() {
-                            self.font_size = (self.font_size - 1.0).max(8.0);
-                        }
-                        ui.label(format!("{:.0}", self.font_size));
-                        if ui.button("+").clicked() {
-                            self.font_size = (self.font_size + 1.0).min(48.0);
+                    ui.label(format!("Editor Font Size: {:.0}", self.font_size));
+                    self.command_button(ui, ctx, Command::FontSmaller);
+                    self.command_button(ui, ctx, Command::FontLarger);
+                    ui.menu_button("Presets", |ui| {
+                        for &command in Command::ALL {
+                            if matches!(command, Command::FontSize(_)) {
+                                self.command_button(ui, ctx, command);
+                            }
                         }
                     });
 
                     ui.separator();
 
-                    ui.menu_button("Presets", |ui| {
-                        if ui.button("Small (12)").clicked() {
-                            self.font_size = 12.0;
-                            ui.close_menu();
-                        }
-                        if ui.button("Medium (14)").clicked() {
-                            self.font_size = 14.0;
-                            ui.close_menu();
+                    ui.menu_button("Backups on Save", |ui| {
+                        for &mode in BackupMode::ALL {
+                            self.command_button(ui, ctx, Command::Backup(mode));
                         }
-                        if ui.button("Large (18)").clicked() {
-                            self.font_size = 18.0;
-                            ui.close_menu();
+                    });
+                    self.command_button(ui, ctx, Command::ToggleLineNumbers);
+
+                    ui.separator();
+
+                    self.command_button(ui, ctx, Command::ToggleAutosave);
+                    self.command_button(ui, ctx, Command::ToggleRestoreSession);
+                    ui.horizontal(|ui| {
+                        ui.label("Recent Files");
+                        ui.add(
+                            egui::DragValue::new(&mut self.settings.files.recent_limit)
+                                .range(1..=50),
+                        );
+                    });
+
+                    ui.separator();
+
+                    self.command_button(ui, ctx, Command::ToggleWeather);
+                    self.render_weather_location_menu(ui, ctx);
+                    ui.menu_button("Weather Units", |ui| {
+                        for &unit in TemperatureUnit::ALL {
+                            self.command_button(ui, ctx, Command::TemperatureUnit(unit));
                         }
-                        if ui.button("Extra Large (24)").clicked() {
-                            self.font_size = 24.0;
-                            ui.close_menu();
+                        ui.separator();
+                        ui.label("Wind Speed");
+                        for &unit in WindUnit::ALL {
+                            self.command_button(ui, ctx, Command::WindUnit(unit));
                         }
                     });
+                    self.command_button(ui, ctx, Command::ToggleMonitor);
+                });
+            });
+        });
+    }
+
+    fn render_export_menu(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        ui.menu_button("Export", |ui| {
+            self.command_button(ui, ctx, Command::Export(ExportFormat::Html));
+            self.command_button(ui, ctx, Command::Export(ExportFormat::Pdf));
+
+            ui.separator();
+
+            ui.menu_button("PDF Page Setup", |ui| {
+                ui.label("Page Size");
+                for &size in PageSize::ALL {
+                    self.command_button(ui, ctx, Command::PdfPageSize(size));
+                }
+                ui.separator();
+                ui.label("Font");
+                for &font in PdfFont::ALL {
+                    self.command_button(ui, ctx, Command::PdfFont(font));
+                }
+                let pdf = &mut self.settings.pdf;
+                ui.horizontal(|ui| {
+                    ui.label("Font Size");
+                    ui.add(
+                        egui::DragValue::new(&mut pdf.font_size)
+                            .range(6.0..=36.0)
+                            .suffix(" pt"),
+                    );
                 });
+                ui.horizontal(|ui| {
+                    ui.label("Margins");
+                    ui.add(
+                        egui::DragValue::new(&mut pdf.margin_mm)
+                            .range(0.0..=50.0)
+                            .suffix(" mm"),
+                    );
+                });
+                self.command_button(ui, ctx, Command::TogglePdfHeaderFooter);
+            });
+        });
+    }
+
+    fn render_weather_location_menu(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        ui.menu_button("Weather Location", |ui| {
+            for &source in LocationSource::ALL {
+                self.command_entry(ui, ctx, Command::WeatherLocation(source));
+            }
+
+            ui.separator();
+            let weather = &mut self.settings.weather;
+            let mut changed = false;
+            match weather.location {
+                LocationSource::Place => {
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut weather.place)
+                            .hint_text("City, e.g. Berlin")
+                            .desired_width(FIND_FIELD_WIDTH),
+                    );
+                    // Looked up once typing is done rather than per keystroke
+                    changed |= response.lost_focus();
+                }
+                LocationSource::Coordinates => {
+                    for (label, value, limit) in [
+                        ("Latitude", &mut weather.latitude, 90.0),
+                        ("Longitude", &mut weather.longitude, 180.0),
+                    ] {
+                        ui.horizontal(|ui| {
+                            ui.label(label);
+                            let response = ui.add(
+                                egui::DragValue::new(value)
+                                    .range(-limit..=limit)
+                                    .speed(0.01)
+                                    .max_decimals(4),
+                            );
+                            changed |= response.drag_stopped()
+                                || (response.changed() && !response.dragged());
+                        });
+                    }
+                }
+                LocationSource::Ip => {
+                    ui.weak("Sends your IP address to ip-api.com");
+                }
+            }
+
+            if changed {
+                self.refetch_weather();
+            }
+        });
+    }
+
+    fn render_recent_menu(&mut self, ui: &mut egui::Ui) {
+        // Files deleted or moved since they were opened drop off the list.
+        // The disk is checked once each time the File menu opens.
+        if !self.recent_checked {
+            self.prune_recent();
+            self.recent_checked = true;
+        }
+
+        ui.add_enabled_ui(!self.recent_files.is_empty(), |ui| {
+            ui.menu_button("Open Recent", |ui| {
+                let mut open = None;
+                for path in &self.recent_files {
+                    let name = path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| path.display().to_string());
+                    if ui
+                        .button(name)
+                        .on_hover_text(path.display().to_string())
+                        .clicked()
+                    {
+                        open = Some(path.clone());
+                    }
+                }
+
+                ui.separator();
+                if ui.button("Clear Recent").clicked() {
+                    self.clear_recent();
+                    ui.close_menu();
+                }
+
+                if let Some(path) = open {
+                    self.open_recent(path);
+                    ui.close_menu();
+                }
             });
         });
     }
 
+    fn render_view_menu(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        ui.menu_button("\u{1F441} View", |ui| {
+            self.command_button(ui, ctx, Command::OpenPalette);
+            self.command_button(ui, ctx, Command::ToggleTheme);
+            self.command_button(ui, ctx, Command::MarkdownPreview)
+                .on_hover_text("Shown next to Markdown documents");
+
+            ui.separator();
+            for &mode in WrapMode::ALL {
+                self.command_button(ui, ctx, Command::Wrap(mode));
+            }
+            ui.horizontal(|ui| {
+                ui.label("Wrap Column");
+                ui.add(egui::DragValue::new(&mut self.settings.editor.wrap_column).range(10..=500));
+            });
+            self.command_button(ui, ctx, Command::ToggleRuler);
+            self.command_button(ui, ctx, Command::HardWrap)
+                .on_hover_text("Reflow the selection or current paragraph to the wrap column");
+
+            ui.separator();
+            for &orientation in SplitOrientation::ALL {
+                self.command_button(ui, ctx, Command::Split(orientation));
+            }
+            self.command_button(ui, ctx, Command::FocusOtherPane);
+            self.command_button(ui, ctx, Command::SwapPanes);
+            self.command_button(ui, ctx, Command::ClosePane);
+        });
+    }
+
+    /// Menu entry for `command`, with the label, shortcut, enabled state and
+    /// checked state all taken from the command registry
+    fn command_button(
+        &mut self,
+        ui: &mut egui::Ui,
+        ctx: &egui::Context,
+        command: Command,
+    ) -> egui::Response {
+        let response = self.command_entry(ui, ctx, command);
+        if response.clicked() {
+            ui.close_menu();
+        }
+        response
+    }
+
+    /// Like [`Self::command_button`], but leaves the menu open, for choices
+    /// followed by more fields in the same menu
+    fn command_entry(
+        &mut self,
+        ui: &mut egui::Ui,
+        ctx: &egui::Context,
+        command: Command,
+    ) -> egui::Response {
+        let mut button =
+            egui::Button::new(command.label()).shortcut_text(self.shortcut_text(ctx, command));
+        if let Some(checked) = self.command_checked(command) {
+            button = button.selected(checked);
+        }
+        let response = ui.add_enabled(self.command_enabled(command), button);
+        if response.clicked() {
+            self.run_command(ctx, command);
+        }
+        response
+    }
+
+    fn render_edit_menu(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        ui.menu_button("\u{270F} Edit", |ui| {
+            let history = &self.active_doc().history;
+            let undo_labels: Vec<String> = history.undo_labels().take(HISTORY_MENU_LEN).collect();
+            let redo_labels: Vec<String> = history.redo_labels().take(HISTORY_MENU_LEN).collect();
+
+            self.command_button(ui, ctx, Command::Undo);
+            self.command_button(ui, ctx, Command::Redo);
+
+            ui.separator();
+
+            ui.menu_button("History", |ui| {
+                if undo_labels.is_empty() && redo_labels.is_empty() {
+                    ui.label("No edits yet");
+                }
+
+                // Redo steps are listed above the current state, newest at the top
+                for (i, label) in redo_labels.iter().enumerate().rev() {
+                    let text = egui::RichText::new(format!("\u{21B7} {}", label)).weak();
+                    if ui.button(text).clicked() {
+                        self.redo_steps(ctx, i + 1);
+                        ui.close_menu();
+                    }
+                }
+
+                if !redo_labels.is_empty() && !undo_labels.is_empty() {
+                    ui.separator();
+                }
+
+                for (i, label) in undo_labels.iter().enumerate() {
+                    if ui.button(format!("\u{21B6} {}", label)).clicked() {
+                        self.undo_steps(ctx, i + 1);
+                        ui.close_menu();
+                    }
+                }
+            });
+
+            ui.separator();
+
+            self.command_button(ui, ctx, Command::Find);
+            self.command_button(ui, ctx, Command::Replace);
+
+            ui.separator();
+
+            self.command_button(ui, ctx, Command::GoToLine);
+            self.command_button(ui, ctx, Command::NavigateBack);
+            self.command_button(ui, ctx, Command::NavigateForward);
+        });
+    }
+
     pub fn render_status_bar(&mut self, ctx: &egui::Context) {
         let (base_color, _) = theme::get_theme_colors(self.dark_mode);
+        let totals = self
+            .stats
+            .get(&self.documents[self.active_doc], DocStats::count)
+            .clone();
 
         egui::TopBottomPanel::bottom("status_bar")
             .frame(
@@ -156,8 +492,7 @@ impl NotepadApp {
                     )
                     .frame(false);
                     if ui.add(button).clicked() {
-                        self.dark_mode = !self.dark_mode;
-                        self.apply_theme(ctx);
+                        self.toggle_theme(ctx);
                     }
 
                     if let Some(ref msg) = self.status_message {
@@ -166,6 +501,18 @@ impl NotepadApp {
                     }
 
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.label(
+                            egui::RichText::new(self.active_doc().encoding.label())
+                                .size(STATUS_BAR_FONT_SIZE),
+                        );
+                        self.render_line_ending_toggle(ui);
+                        self.render_document_info(ui, &totals);
+
+                        if !self.settings.monitor.enabled {
+                            return;
+                        }
+                        ui.add_space(ELEMENT_SPACING);
+
                         let stats = &self.system_stats;
                         let gpu_text = stats
                             .gpu_usage
@@ -181,77 +528,1059 @@ impl NotepadApp {
                                 "CPU: {:.1}% | GPU: {} | RAM: {:.1}% | Temp: {}",
                                 stats.cpu_usage, gpu_text, stats.ram_usage, temp_text
                             ))
-                            .size(12.5),
+                            .size(STATUS_BAR_FONT_SIZE),
                         );
                     });
                 });
             });
     }
 
-    pub fn render_text_editor(&mut self, ctx: &egui::Context) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            egui::ScrollArea::vertical()
-                .auto_shrink([false, false])
-                .show(ui, |ui| {
-                    let editor_font =
-                        egui::FontId::new(self.font_size, egui::FontFamily::Monospace);
-                    let response = ui.add_sized(
-                        ui.available_size(),
-                        egui::TextEdit::multiline(&mut self.text)
-                            .font(editor_font)
-                            .desired_width(f32::INFINITY),
+    pub fn render_tab_bar(&mut self, ctx: &egui::Context) {
+        let mut select = None;
+        let mut close = None;
+        let mut new_tab = false;
+
+        egui::TopBottomPanel::top("tab_bar").show(ctx, |ui| {
+            ui.add_enabled_ui(!self.show_unsaved_dialog, |ui| {
+                egui::ScrollArea::horizontal()
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            for (index, doc) in self.documents.iter().enumerate() {
+                                let mut label = doc.title();
+                                if doc.read_only {
+                                    label = format!("\u{1F512} {}", label);
+                                }
+                                if doc.dirty {
+                                    label = format!("\u{25CF} {}", label);
+                                }
+
+                                let tab = ui.selectable_label(index == self.active_doc, label);
+                                if tab.clicked() {
+                                    select = Some(index);
+                                }
+                                if tab.middle_clicked() {
+                                    close = Some(index);
+                                }
+                                if let Some(ref path) = doc.file_path {
+                                    tab.on_hover_text(path.display().to_string());
+                                }
+
+                                if ui.small_button("\u{00D7}").clicked() {
+                                    close = Some(index);
+                                }
+                                ui.add_space(ELEMENT_SPACING);
+                            }
+
+                            if ui.small_button("+").on_hover_text("New tab").clicked() {
+                                new_tab = true;
+                            }
+                        });
+                    });
+            });
+        });
+
+        if let Some(index) = select {
+            self.select_tab(index);
+        }
+        if let Some(index) = close {
+            self.close_tab(index);
+        }
+        if new_tab {
+            self.new_file();
+        }
+    }
+
+    pub fn render_forecast_window(&mut self, ctx: &egui::Context) {
+        if !self.show_forecast || !self.settings.weather.enabled {
+            return;
+        }
+
+        let info = self
+            .weather
+            .lock()
+            .ok()
+            .and_then(|weather| weather.info.clone());
+        let unit = self.settings.weather.temperature_unit;
+        let palette = theme::palette(self.dark_mode);
+        let title = match &info {
+            Some(info) => format!("Forecast for {}", info.place),
+            None => "Forecast".to_string(),
+        };
+
+        egui::Window::new(title)
+            .id(egui::Id::new("forecast_window"))
+            .open(&mut self.show_forecast)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let Some(info) = info else {
+                    ui.weak("No weather data yet");
+                    return;
+                };
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new(&info.icon).size(28.0));
+                    ui.vertical(|ui| {
+                        ui.heading(info.temperature_text(unit));
+                        ui.label(format!(
+                            "{}, wind {}",
+                            info.description,
+                            info.wind_text(self.settings.weather.wind_unit)
+                        ));
+                    });
+                });
+
+                ui.separator();
+                ui.strong(format!("Next {} hours", weather::FORECAST_HOURS));
+                forecast::hourly_chart(ui, &info.hourly, unit, &palette);
+
+                ui.separator();
+                ui.strong("7 days");
+                forecast::daily_table(ui, &info.daily, unit);
+            });
+    }
+
+    pub fn render_command_palette(&mut self, ctx: &egui::Context) {
+        if !self.command_palette.open {
+            return;
+        }
+
+        let matches = self.command_palette_matches();
+        let shortcuts: Vec<String> = matches
+            .iter()
+            .map(|&command| self.shortcut_text(ctx, command))
+            .collect();
+        let mut run = None;
+        let mut close = false;
+
+        egui::Window::new("Command Palette")
+            .collapsible(false)
+            .resizable(false)
+            .title_bar(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 80.0])
+            .show(ctx, |ui| {
+                let palette = &mut self.command_palette;
+                // Taken before the text field sees them
+                let (up, down) = ui.input_mut(|i| {
+                    (
+                        i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                        i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                    )
+                });
+                if down && palette.selected + 1 < matches.len() {
+                    palette.selected += 1;
+                }
+                if up {
+                    palette.selected = palette.selected.saturating_sub(1);
+                }
+
+                let input = ui.add(
+                    egui::TextEdit::singleline(&mut palette.query)
+                        .hint_text("Type a command")
+                        .desired_width(PALETTE_WIDTH),
+                );
+                if palette.focus_input {
+                    input.request_focus();
+                    palette.focus_input = false;
+                }
+                if input.changed() {
+                    palette.selected = 0;
+                }
+                if input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    run = matches.get(palette.selected).copied();
+                    close = true;
+                }
+
+                ui.separator();
+                if matches.is_empty() {
+                    ui.weak("No matching commands");
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(PALETTE_LIST_HEIGHT)
+                    .show(ui, |ui| {
+                        for (i, (&command, shortcut)) in matches.iter().zip(&shortcuts).enumerate()
+                        {
+                            let selected = i == palette.selected;
+                            let text = command.title();
+                            let response = ui.add(
+                                egui::Button::new(text)
+                                    .shortcut_text(shortcut)
+                                    .selected(selected)
+                                    .frame(selected)
+                                    .min_size(egui::vec2(PALETTE_WIDTH, 0.0)),
+                            );
+                            if selected && (up || down) {
+                                response.scroll_to_me(None);
+                            }
+                            if response.clicked() {
+                                run = Some(command);
+                                close = true;
+                            }
+                        }
+                    });
+            });
+
+        if close {
+            self.close_command_palette(ctx);
+        }
+        if let Some(command) = run {
+            self.run_command(ctx, command);
+        }
+    }
+
+    pub fn render_go_to_dialog(&mut self, ctx: &egui::Context) {
+        if !self.go_to.open {
+            return;
+        }
+
+        let line_count = self
+            .stats
+            .get(&self.documents[self.active_doc], DocStats::count)
+            .lines;
+        let mut go = false;
+        let mut close = false;
+
+        egui::Window::new("Go to Line")
+            .collapsible(false)
+            .resizable(false)
+            .title_bar(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 80.0])
+            .show(ctx, |ui| {
+                let go_to = &mut self.go_to;
+                ui.horizontal(|ui| {
+                    let input = ui.add(
+                        egui::TextEdit::singleline(&mut go_to.input)
+                            .hint_text("line, line:col or 50%")
+                            .desired_width(FIND_FIELD_WIDTH),
                     );
+                    if go_to.focus_input {
+                        input.request_focus();
+                        go_to.focus_input = false;
+                    }
+                    if input.changed() {
+                        go_to.error = None;
+                    }
+                    if input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                        go = true;
+                    }
+                    go |= ui.button("Go").clicked();
+                    close = ui.small_button("\u{2715}").clicked();
+                });
 
-                    if response.changed() {
-                        self.dirty = true;
+                match &go_to.error {
+                    Some(error) => ui.colored_label(ui.visuals().error_fg_color, error),
+                    None => ui.weak(format!("{} lines", line_count)),
+                };
+            });
+
+        if go {
+            self.go_to_line(ctx);
+        } else if close {
+            self.close_go_to(ctx);
+        }
+    }
+
+    pub fn render_find_bar(&mut self, ctx: &egui::Context) {
+        if !self.find.open {
+            return;
+        }
+        self.update_find(ctx);
+
+        let mut step = None;
+        let mut replace_one = false;
+        let mut replace_all = false;
+        let mut close = false;
+        let mut changed = false;
+
+        egui::TopBottomPanel::top("find_bar").show(ctx, |ui| {
+            let find = &mut self.find;
+
+            ui.horizontal(|ui| {
+                let query = ui.add(
+                    egui::TextEdit::singleline(&mut find.query)
+                        .hint_text("Find")
+                        .desired_width(FIND_FIELD_WIDTH),
+                );
+                if find.focus_query {
+                    query.request_focus();
+                    find.focus_query = false;
+                }
+                changed |= query.changed();
+                if query.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    step = Some(!ui.input(|i| i.modifiers.shift));
+                    query.request_focus();
+                }
+
+                if ui
+                    .small_button("\u{2191}")
+                    .on_hover_text("Previous (Shift+Enter)")
+                    .clicked()
+                {
+                    step = Some(false);
+                }
+                if ui
+                    .small_button("\u{2193}")
+                    .on_hover_text("Next (Enter)")
+                    .clicked()
+                {
+                    step = Some(true);
+                }
+
+                changed |= ui
+                    .toggle_value(&mut find.case_sensitive, "Aa")
+                    .on_hover_text("Match case")
+                    .changed();
+                changed |= ui
+                    .toggle_value(&mut find.whole_word, "W")
+                    .on_hover_text("Whole word")
+                    .changed();
+                changed |= ui
+                    .toggle_value(&mut find.use_regex, ".*")
+                    .on_hover_text("Regular expression")
+                    .changed();
+
+                ui.add_space(ELEMENT_SPACING);
+                if let Some(ref error) = find.error {
+                    ui.colored_label(
+                        ui.visuals().error_fg_color,
+                        error.lines().last().unwrap_or(""),
+                    );
+                } else if !find.query.is_empty() {
+                    let count = match find.current {
+                        Some(i) => format!("{} of {}", i + 1, find.matches.len()),
+                        None => "No results".to_string(),
+                    };
+                    ui.label(count);
+                }
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui
+                        .small_button("\u{00D7}")
+                        .on_hover_text("Close (Esc)")
+                        .clicked()
+                    {
+                        close = true;
                     }
                 });
+            });
+
+            if find.show_replace {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut find.replacement)
+                            .hint_text(if find.use_regex {
+                                "Replace ($1 for groups)"
+                            } else {
+                                "Replace"
+                            })
+                            .desired_width(FIND_FIELD_WIDTH),
+                    );
+                    replace_one = ui.button("Replace").clicked();
+                    replace_all = ui.button("Replace All").clicked();
+                });
+            }
+        });
+
+        if changed {
+            self.find.invalidate();
+            self.update_find(ctx);
+            self.reveal_current_match(ctx);
+        }
+        if let Some(forward) = step {
+            self.find_step(ctx, forward);
+        }
+        if replace_one {
+            self.replace_current(ctx);
+        }
+        if replace_all {
+            self.replace_all(ctx);
+        }
+        if close {
+            self.close_find(ctx);
+        }
+    }
+
+    /// Caret position, selection size, totals and file type. Clicking it
+    /// opens the statistics window.
+    fn render_document_info(&mut self, ui: &mut egui::Ui, totals: &DocStats) {
+        self.render_language_picker(ui);
+
+        let doc = self.active_doc();
+
+        let mut info = format!("Ln {}, Col {}", doc.cursor_line, doc.cursor_column);
+        if doc.selection_len > 0 {
+            info.push_str(&format!(" ({} selected)", doc.selection_len));
+        }
+        info.push_str(&format!(
+            "  |  {} lines, {} words, {} chars",
+            totals.lines, totals.words, totals.chars
+        ));
+
+        let button =
+            egui::Button::new(egui::RichText::new(info).size(STATUS_BAR_FONT_SIZE)).frame(false);
+        if ui
+            .add(button)
+            .on_hover_text("Document statistics")
+            .clicked()
+        {
+            self.show_stats = !self.show_stats;
+        }
+    }
+
+    pub fn render_stats_window(&mut self, ctx: &egui::Context) {
+        if !self.show_stats {
+            return;
+        }
+
+        let doc = &self.documents[self.active_doc];
+        let title = doc.title();
+        let stats = self.detailed_stats.get(doc, DetailedStats::count);
+        let mut open = true;
+
+        egui::Window::new("Document Statistics")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.strong(title);
+                ui.add_space(ELEMENT_SPACING / 2.0);
+
+                egui::Grid::new("stats_grid")
+                    .num_columns(2)
+                    .spacing([ELEMENT_SPACING * 2.0, 4.0])
+                    .show(ui, |ui| {
+                        let rows = [
+                            ("Words", stats.totals.words.to_string()),
+                            ("Characters", stats.totals.chars.to_string()),
+                            (
+                                "Characters (no spaces)",
+                                stats.chars_without_spaces.to_string(),
+                            ),
+                            ("Lines", stats.totals.lines.to_string()),
+                            ("Paragraphs", stats.paragraphs.to_string()),
+                            ("Reading time", reading_time(stats.reading_minutes)),
+                        ];
+                        for (label, value) in rows {
+                            ui.label(label);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    });
+
+                if !stats.top_words.is_empty() {
+                    ui.separator();
+                    ui.strong("Most frequent words");
+                    egui::Grid::new("word_frequency_grid")
+                        .num_columns(2)
+                        .spacing([ELEMENT_SPACING * 2.0, 2.0])
+                        .show(ui, |ui| {
+                            for (word, count) in &stats.top_words {
+                                ui.label(word);
+                                ui.label(count.to_string());
+                                ui.end_row();
+                            }
+                        });
+                }
+            });
+
+        self.show_stats = open;
+    }
+
+    fn render_language_picker(&mut self, ui: &mut egui::Ui) {
+        let doc = &mut self.documents[self.active_doc];
+        let current = doc.language();
+        let text = egui::RichText::new(current.label()).size(STATUS_BAR_FONT_SIZE);
+
+        let response = ui.menu_button(text, |ui| {
+            let detected = Language::from_path(doc.file_path.as_deref());
+            if ui
+                .selectable_label(
+                    doc.language_override.is_none(),
+                    format!("Auto-detect ({})", detected.label()),
+                )
+                .clicked()
+            {
+                doc.language_override = None;
+                ui.close_menu();
+            }
+            ui.separator();
+            for &language in Language::ALL {
+                if ui
+                    .selectable_label(doc.language_override == Some(language), language.label())
+                    .clicked()
+                {
+                    doc.language_override = Some(language);
+                    ui.close_menu();
+                }
+            }
+        });
+        response
+            .response
+            .on_hover_text("Language used for syntax highlighting");
+    }
+
+    fn render_line_ending_toggle(&mut self, ui: &mut egui::Ui) {
+        let doc = self.active_doc();
+        let current = doc.line_ending;
+        let mut text = egui::RichText::new(current.label()).size(STATUS_BAR_FONT_SIZE);
+        if doc.mixed_line_endings {
+            text = egui::RichText::new(format!("\u{26A0} {} (mixed)", current.label()))
+                .size(STATUS_BAR_FONT_SIZE)
+                .color(ui.visuals().warn_fg_color);
+        }
+
+        let response = ui.menu_button(text, |ui| {
+            ui.label("Convert line endings to");
+            for &ending in LineEnding::ALL {
+                if ui
+                    .selectable_label(ending == current, ending.description())
+                    .clicked()
+                {
+                    self.convert_line_endings(ending);
+                    ui.close_menu();
+                }
+            }
+        });
+        response
+            .response
+            .on_hover_text("Line endings used when saving");
+    }
+
+    /// Rendered Markdown beside the editor, kept scrolled to the same place
+    pub fn render_markdown_preview(&mut self, ctx: &egui::Context) {
+        if !self.settings.editor.markdown_preview
+            || self.active_doc().language() != Language::Markdown
+        {
+            return;
+        }
+
+        let palette = theme::palette(self.dark_mode);
+        let doc = &self.documents[self.active_doc];
+        let toggled = egui::SidePanel::right("markdown_preview")
+            .resizable(true)
+            .default_width(ctx.screen_rect().width() / 2.0)
+            .min_width(160.0)
+            .show(ctx, |ui| {
+                self.preview.show(ui, doc, self.font_size, &palette)
+            })
+            .inner;
+        if let Some((revision, offset)) = toggled {
+            self.toggle_task(revision, offset);
+        }
+    }
+
+    pub fn render_text_editor(&mut self, ctx: &egui::Context) {
+        let switched = self.last_rendered_doc != Some(self.active_doc().id);
+        self.last_rendered_doc = Some(self.active_doc().id);
+        let wrap_mode = self.settings.editor.wrap;
+        // From the cached stats, so the gutter does not recount every frame
+        let line_count = self
+            .stats
+            .get(&self.documents[self.active_doc], DocStats::count)
+            .lines;
+        let (matches, current) = if self.find.open {
+            (self.find.matches.as_slice(), self.find.current_match())
+        } else {
+            (&[][..], None)
+        };
+        let doc = &mut self.documents[self.active_doc];
+        let orientation = doc.split.as_ref().map(|split| split.orientation);
+        let mut view = EditorView {
+            font: egui::FontId::new(self.font_size, egui::FontFamily::Monospace),
+            palette: theme::palette(self.dark_mode),
+            line_numbers: self.settings.editor.line_numbers,
+            line_count,
+            wrap_mode,
+            wrap_column: self.settings.editor.wrap_column.max(1),
+            show_ruler: wrap_mode == WrapMode::Column && self.settings.editor.show_ruler,
+            matches,
+            current,
+            restore_scroll: switched,
+            preview: &mut self.preview,
+            doc,
+        };
+
+        egui::CentralPanel::default().show(ctx, |ui| match orientation {
+            None => view.show_pane(ui, 0),
+            Some(SplitOrientation::Horizontal) => {
+                egui::TopBottomPanel::top("editor_split")
+                    .resizable(true)
+                    .default_height(ui.available_height() / 2.0)
+                    .min_height(40.0)
+                    .show_inside(ui, |ui| view.show_pane(ui, 0));
+                view.show_pane(ui, 1);
+            }
+            Some(SplitOrientation::Vertical) => {
+                egui::SidePanel::left("editor_split")
+                    .resizable(true)
+                    .default_width(ui.available_width() / 2.0)
+                    .min_width(80.0)
+                    .show_inside(ui, |ui| view.show_pane(ui, 0));
+                view.show_pane(ui, 1);
+            }
         });
     }
 
+    /// Tells the user about changes another program made to the active file
+    pub fn render_change_banner(&mut self, ctx: &egui::Context) {
+        let doc = self.active_doc();
+        let Some(change) = doc.external_change.clone() else {
+            return;
+        };
+        let palette = theme::palette(self.dark_mode);
+        let message = match &change {
+            ExternalChange::Modified => {
+                format!(
+                    "\u{26A0} \"{}\" was changed by another program.",
+                    doc.title()
+                )
+            }
+            ExternalChange::Deleted => format!("\u{26A0} \"{}\" was deleted.", doc.title()),
+            ExternalChange::Renamed(new_path) => format!(
+                "\u{26A0} \"{}\" was moved to {}.",
+                doc.title(),
+                new_path.display()
+            ),
+        };
+
+        let mut reload = false;
+        let mut keep = false;
+        let mut diff = false;
+        let mut follow = false;
+        let mut close = false;
+
+        egui::TopBottomPanel::top("change_banner").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(palette.red, message);
+                match change {
+                    ExternalChange::Modified => {
+                        reload = ui.button("Reload").clicked();
+                        keep = ui.button("Keep Mine").clicked();
+                        diff = ui.button("Show Diff").clicked();
+                    }
+                    ExternalChange::Deleted => {
+                        keep = ui.button("Keep Mine").clicked();
+                        close = ui.button("Close Tab").clicked();
+                    }
+                    ExternalChange::Renamed(_) => {
+                        follow = ui.button("Follow Rename").clicked();
+                        keep = ui.button("Keep Mine").clicked();
+                    }
+                }
+            });
+        });
+
+        if reload {
+            self.reload_file();
+        } else if keep {
+            self.keep_mine();
+        } else if diff {
+            self.show_disk_diff();
+        } else if follow {
+            self.follow_rename();
+        } else if close {
+            self.close_tab(self.active_doc);
+        }
+    }
+
+    /// Confirms saving over a file another program changed since it was
+    /// opened or last saved
+    pub fn handle_overwrite_dialog(&mut self, ctx: &egui::Context) {
+        let Some((id, _)) = self.confirm_overwrite else {
+            return;
+        };
+        let Some(index) = self.documents.iter().position(|d| d.id == id) else {
+            self.confirm_overwrite = None;
+            return;
+        };
+        self.active_doc = index;
+
+        let mut overwrite = false;
+        let mut diff = false;
+        let mut cancel = false;
+        let title = self.active_doc().title();
+
+        egui::Window::new("File Changed on Disk")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "\"{}\" was changed by another program. Saving will overwrite those changes.",
+                    title
+                ));
+                ui.add_space(ELEMENT_SPACING);
+
+                ui.horizontal(|ui| {
+                    overwrite = ui.button("Overwrite").clicked();
+                    diff = ui.button("Show Diff").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if overwrite {
+            let saved = self.overwrite_changed_file();
+            // Carry on with the close or exit that asked for the save
+            if saved && self.show_unsaved_dialog {
+                self.show_unsaved_dialog = false;
+                if let Some(action) = self.pending_action.take() {
+                    self.execute_pending_action(&action, ctx);
+                }
+            }
+        } else if diff {
+            self.show_disk_diff();
+        } else if cancel {
+            self.confirm_overwrite = None;
+        }
+    }
+
     pub fn handle_unsaved_dialog(&mut self, ctx: &egui::Context) {
-        if !self.show_unsaved_dialog {
+        if !self.show_unsaved_dialog || self.confirm_overwrite.is_some() {
             return;
         }
 
-        let mut close_dialog = false;
-        let pending = self.pending_action.clone();
+        let mut save = false;
+        let mut discard = false;
+        let mut cancel = false;
+        let title = self.active_doc().title();
 
         egui::Window::new("Unsaved Changes")
             .collapsible(false)
             .resizable(false)
             .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
             .show(ctx, |ui| {
-                ui.label("You have unsaved changes. What would you like to do?");
+                ui.label(format!(
+                    "\"{}\" has unsaved changes. What would you like to do?",
+                    title
+                ));
                 ui.add_space(ELEMENT_SPACING);
 
                 ui.horizontal(|ui| {
-                    if ui.button("Save").clicked() {
-                        self.save_file();
-                        if !self.dirty {
-                            close_dialog = true;
-                            if let Some(ref action) = pending {
-                                self.execute_pending_action(action, ctx);
+                    save = ui.button("Save").clicked();
+                    discard = ui.button("Don't Save").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if save {
+            self.save_file();
+            discard = !self.active_doc().dirty;
+        }
+
+        if discard {
+            self.show_unsaved_dialog = false;
+            if let Some(action) = self.pending_action.take() {
+                self.execute_pending_action(&action, ctx);
+            }
+        } else if cancel {
+            self.show_unsaved_dialog = false;
+            self.pending_action = None;
+        }
+    }
+
+    /// Offers the snapshots a crashed session left behind
+    pub fn render_recovery_dialog(&mut self, ctx: &egui::Context) {
+        if self.recovered.is_empty() {
+            return;
+        }
+
+        let mut restore = None;
+        let mut compare = None;
+        let mut discard = None;
+        let mut restore_all = false;
+        let mut discard_all = false;
+
+        egui::Window::new("Recover Unsaved Work")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(
+                    "Rusty Notepad did not shut down cleanly. These documents had unsaved changes:",
+                );
+                ui.add_space(ELEMENT_SPACING);
+
+                egui::Grid::new("recovery_grid")
+                    .num_columns(3)
+                    .spacing([ELEMENT_SPACING, 6.0])
+                    .show(ui, |ui| {
+                        for (index, recovered) in self.recovered.iter().enumerate() {
+                            let snapshot = &recovered.snapshot;
+                            let label = ui.label(snapshot.title());
+                            if let Some(path) = &snapshot.file_path {
+                                label.on_hover_text(path.display().to_string());
                             }
+                            ui.weak(snapshot.taken_at_text());
+                            ui.horizontal(|ui| {
+                                if ui.button("Restore").clicked() {
+                                    restore = Some(index);
+                                }
+                                if ui.button("Compare").clicked() {
+                                    compare = Some(index);
+                                }
+                                if ui.button("Discard").clicked() {
+                                    discard = Some(index);
+                                }
+                            });
+                            ui.end_row();
                         }
-                    }
+                    });
 
-                    if ui.button("Don't Save").clicked() {
-                        close_dialog = true;
-                        if let Some(ref action) = pending {
-                            self.execute_pending_action(action, ctx);
-                        }
-                    }
+                ui.add_space(ELEMENT_SPACING);
+                ui.horizontal(|ui| {
+                    restore_all = ui.button("Restore All").clicked();
+                    discard_all = ui.button("Discard All").clicked();
+                });
+            });
 
-                    if ui.button("Cancel").clicked() {
-                        close_dialog = true;
-                    }
+        if restore_all {
+            while !self.recovered.is_empty() {
+                self.restore_snapshot(0);
+            }
+        } else if discard_all {
+            while !self.recovered.is_empty() {
+                self.discard_snapshot(0);
+            }
+        } else if let Some(index) = restore {
+            self.restore_snapshot(index);
+        } else if let Some(index) = discard {
+            self.discard_snapshot(index);
+        } else if let Some(index) = compare {
+            self.compare_snapshot(index);
+        }
+    }
+
+    pub fn render_diff_window(&mut self, ctx: &egui::Context) {
+        let Some(diff) = &self.diff_view else {
+            return;
+        };
+
+        let palette = theme::palette(self.dark_mode);
+        let font = egui::FontId::monospace(self.font_size);
+        let row_height = ctx.fonts(|f| f.row_height(&font));
+        let mut open = true;
+
+        egui::Window::new(&diff.title)
+            .open(&mut open)
+            .default_size([640.0, 420.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} → {}", diff.old_label, diff.new_label));
+                    ui.colored_label(palette.green, format!("+{}", diff.added));
+                    ui.colored_label(palette.red, format!("-{}", diff.removed));
                 });
+                ui.separator();
+
+                egui::ScrollArea::both()
+                    .auto_shrink([false, false])
+                    .show_rows(ui, row_height, diff.lines.len(), |ui, rows| {
+                        for (tag, line) in &diff.lines[rows] {
+                            let (prefix, color) = match tag {
+                                ChangeTag::Insert => ("+ ", palette.green),
+                                ChangeTag::Delete => ("- ", palette.red),
+                                ChangeTag::Equal => ("  ", palette.text),
+                            };
+                            ui.label(
+                                egui::RichText::new(format!("{}{}", prefix, line))
+                                    .font(font.clone())
+                                    .color(color),
+                            );
+                        }
+                    });
             });
 
-        if close_dialog {
-            self.show_unsaved_dialog = false;
-            self.pending_action = None;
+        if !open {
+            self.diff_view = None;
+        }
+    }
+}
+
+/// How long ago something happened, in the largest whole unit
+fn time_ago(elapsed: Duration) -> String {
+    let minutes = elapsed.as_secs() / 60;
+    if minutes < 1 {
+        "just now".to_string()
+    } else if minutes < 60 {
+        format!("{} min ago", minutes)
+    } else {
+        format!("{} h ago", minutes / 60)
+    }
+}
+
+fn reading_time(minutes: f32) -> String {
+    if minutes < 1.0 {
+        "under a minute".to_string()
+    } else {
+        let minutes = minutes.round() as u32;
+        format!("{} min", minutes)
+    }
+}
+
+/// Everything needed to draw the active document in one or two editor
+/// panes
+struct EditorView<'a> {
+    doc: &'a mut Document,
+    preview: &'a mut PreviewState,
+    font: egui::FontId,
+    palette: CatppuccinPalette,
+    line_numbers: bool,
+    /// Lines in the document, for the gutter width
+    line_count: usize,
+    wrap_mode: WrapMode,
+    wrap_column: usize,
+    show_ruler: bool,
+    matches: &'a [Range<usize>],
+    current: Option<Range<usize>>,
+    /// Put each pane back at its saved scroll position
+    restore_scroll: bool,
+}
+
+impl EditorView<'_> {
+    /// Draws `pane` of the document. Only the focused pane updates the
+    /// caret shown in the status bar and follows scroll requests.
+    fn show_pane(&mut self, ui: &mut egui::Ui, pane: usize) {
+        let doc = &mut *self.doc;
+        let editor_id = doc.pane_editor_id(pane);
+        let language = doc.language();
+        let (matches, current) = (self.matches, self.current.as_ref());
+        let (font, palette) = (&self.font, &self.palette);
+        let wrap_mode = self.wrap_mode;
+
+        // Lines that do not wrap at the window edge may need to scroll
+        // sideways
+        let scroll_area = if wrap_mode == WrapMode::Window {
+            egui::ScrollArea::vertical()
+        } else {
+            egui::ScrollArea::both()
+        };
+        let mut scroll_area = scroll_area
+            .id_salt(("editor_scroll", pane))
+            .auto_shrink([false, false]);
+        if self.restore_scroll {
+            let offset = match &doc.split {
+                Some(split) if pane == 1 => split.scroll_offset,
+                _ => doc.scroll_offset,
+            };
+            scroll_area = scroll_area.scroll_offset(offset);
+        }
+
+        let output = scroll_area.show(ui, |ui| {
+            let char_width = ui.fonts(|f| f.glyph_width(font, ' '));
+            let column_width = self.wrap_column as f32 * char_width;
+            let highlighter = &mut doc.highlighter;
+            // Only the first layout of the frame sees the text as of this
+            // revision; TextEdit lays out again after applying an edit,
+            // before `record_edit` bumps the revision
+            let mut revision = Some((doc.id, doc.revision));
+            let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+                let spans = highlighter.highlight_revision(language, revision.take(), text);
+                let mut job = syntax::layout_job(text, font, palette, spans, matches, current);
+                job.wrap.max_width = match wrap_mode {
+                    WrapMode::None => f32::INFINITY,
+                    WrapMode::Window => wrap_width,
+                    // Slack so a full column does not wrap on rounding
+                    WrapMode::Column => column_width + 0.5,
+                };
+                ui.fonts(|f| f.layout_job(job))
+            };
+
+            let gutter_width = self
+                .line_numbers
+                .then(|| gutter::width(ui, font, self.line_count));
+
+            // A `&str` buffer lets the text be selected and copied but not edited
+            let mut read_only_text;
+            let buffer: &mut dyn egui::TextBuffer = if doc.read_only {
+                read_only_text = doc.text.as_str();
+                &mut read_only_text
+            } else {
+                &mut doc.text
+            };
+            // The editor frame is painted by `gutter::paint` so the
+            // current line band can go between it and the text
+            let background = ui.painter().add(egui::Shape::Noop);
+            let ruler = ui.painter().add(egui::Shape::Noop);
+            let output = egui::TextEdit::multiline(buffer)
+                .id(editor_id)
+                .font(font.clone())
+                .frame(false)
+                .margin(egui::Margin {
+                    left: 4.0 + gutter_width.unwrap_or(0.0),
+                    right: 4.0,
+                    top: 2.0,
+                    bottom: 2.0,
+                })
+                .desired_width(f32::INFINITY)
+                .min_size(ui.available_size())
+                .layouter(&mut layouter)
+                .show(ui);
+
+            if self.show_ruler {
+                let x = output.galley_pos.x + column_width;
+                let y = output.response.rect.y_range();
+                let stroke = egui::Stroke::new(1.0, palette.surface1);
+                ui.painter().set(ruler, egui::Shape::vline(x, y, stroke));
+            }
+
+            let caret = output
+                .cursor_range
+                .map_or(doc.cursor, |range| range.primary.ccursor.index);
+            let clicked_line =
+                gutter::paint(ui, background, &output, gutter_width, font, caret, palette);
+
+            // Clicking into the other pane moves the focus there
+            if output.response.has_focus() || clicked_line.is_some() {
+                if let Some(split) = &mut doc.split {
+                    split.focused = pane;
+                }
+            }
+            if let Some(line) = clicked_line {
+                let start = doc.char_index_at(line + 1, 1);
+                let end = doc.char_index_at(line + 2, 1);
+                doc.select_chars(ui.ctx(), start, end);
+                ui.ctx().memory_mut(|m| m.request_focus(editor_id));
+            }
+
+            if output.response.changed() {
+                doc.record_edit();
+            }
+            if doc.focused_pane() != pane {
+                return;
+            }
+            if let Some(range) = output.cursor_range {
+                doc.cursor = range.primary.ccursor.index;
+                doc.cursor_line = range.primary.pcursor.paragraph + 1;
+                doc.cursor_column = range.primary.pcursor.offset + 1;
+                doc.selection_len = range
+                    .primary
+                    .ccursor
+                    .index
+                    .abs_diff(range.secondary.ccursor.index);
+            }
+
+            if let Some(char_index) = doc.scroll_to_char.take() {
+                let cursor = egui::text::CCursor::new(char_index);
+                let rect = output
+                    .galley
+                    .pos_from_ccursor(cursor)
+                    .translate(output.galley_pos.to_vec2());
+                ui.scroll_to_rect(rect, Some(egui::Align::Center));
+            }
+
+            // Keep the Markdown preview on the same part of the document
+            let preview = &mut *self.preview;
+            let galley_top = output.galley_pos.y;
+            if let Some(line) = preview.scroll_editor_to.take() {
+                let y = galley_top + preview::line_offset(&output.galley, line);
+                let rect = egui::Rect::from_x_y_ranges(ui.max_rect().x_range(), y..=y);
+                ui.scroll_to_rect(rect, Some(egui::Align::TOP));
+            }
+            preview.editor_top_line =
+                preview::top_line(&output.galley, ui.clip_rect().top() - galley_top);
+        });
+
+        match &mut doc.split {
+            Some(split) if pane == 1 => split.scroll_offset = output.state.offset,
+            _ => doc.scroll_offset = output.state.offset,
         }
     }
 }
@@ -1,41 +1,448 @@
-use serde::Deserialize;
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::settings::WeatherSettings;
+
+/// Default service addresses; each can be pointed elsewhere in the
+/// `[weather]` settings, for a self-hosted Open-Meteo or a test server
+pub const FORECAST_URL: &str = "https://api.open-meteo.com";
+pub const GEOCODING_URL: &str = "https://geocoding-api.open-meteo.com";
+pub const IP_LOCATION_URL: &str = "http://ip-api.com";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// First retry after a failed fetch; each further failure doubles the wait
+const RETRY_BASE: Duration = Duration::from_secs(15);
+/// Longest wait between retries, however often fetches fail
+const RETRY_MAX: Duration = Duration::from_secs(30 * 60);
+/// Hours shown in the forecast chart
+pub const FORECAST_HOURS: usize = 24;
+const FORECAST_DAYS: usize = 7;
+
+/// Where the weather is reported for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LocationSource {
+    /// A place name looked up through Open-Meteo's geocoding service
+    Place,
+    /// Fixed latitude and longitude
+    Coordinates,
+    /// Guessed from the public IP address by ip-api.com. Opt-in only, as it
+    /// sends the address to a third party and is wrong behind a VPN.
+    Ip,
+}
+
+impl LocationSource {
+    pub const ALL: &'static [LocationSource] = &[
+        LocationSource::Place,
+        LocationSource::Coordinates,
+        LocationSource::Ip,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LocationSource::Place => "Place Name",
+            LocationSource::Coordinates => "Latitude and Longitude",
+            LocationSource::Ip => "From IP Address (ip-api.com)",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureUnit {
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    pub const ALL: &'static [TemperatureUnit] =
+        &[TemperatureUnit::Celsius, TemperatureUnit::Fahrenheit];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "Celsius (°C)",
+            TemperatureUnit::Fahrenheit => "Fahrenheit (°F)",
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "°C",
+            TemperatureUnit::Fahrenheit => "°F",
+        }
+    }
+
+    pub fn convert_celsius(self, celsius: f64) -> f64 {
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindUnit {
+    Kmh,
+    Mph,
+    Ms,
+}
+
+impl WindUnit {
+    pub const ALL: &'static [WindUnit] = &[WindUnit::Kmh, WindUnit::Mph, WindUnit::Ms];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            WindUnit::Kmh => "km/h",
+            WindUnit::Mph => "mph",
+            WindUnit::Ms => "m/s",
+        }
+    }
+
+    pub fn convert_kmh(self, kmh: f64) -> f64 {
+        match self {
+            WindUnit::Kmh => kmh,
+            WindUnit::Mph => kmh / 1.609_344,
+            WindUnit::Ms => kmh / 3.6,
+        }
+    }
+}
 
 #[derive(Debug, Deserialize)]
-struct GeoResponse {
-    lat: f64,
-    lon: f64,
+struct ForecastResponse {
+    current_weather: CurrentWeather,
+    #[serde(default)]
+    hourly: HourlyColumns,
+    #[serde(default)]
+    daily: DailyColumns,
 }
 
+#[derive(Debug, Deserialize)]
+struct CurrentWeather {
+    #[serde(default)]
+    time: String,
+    temperature: f64,
+    #[serde(default)]
+    windspeed: f64,
+    #[serde(default)]
+    weathercode: i64,
+}
+
+/// Open-Meteo sends hourly and daily data as one array per variable.
+/// Missing values come as `null`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct HourlyColumns {
+    time: Vec<String>,
+    temperature_2m: Vec<Option<f64>>,
+    precipitation: Vec<Option<f64>>,
+    weathercode: Vec<Option<i64>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct DailyColumns {
+    time: Vec<String>,
+    weathercode: Vec<Option<i64>>,
+    temperature_2m_max: Vec<Option<f64>>,
+    temperature_2m_min: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IpLocation {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    message: String,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    #[serde(default)]
+    city: String,
+    #[serde(default)]
+    country: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodingResponse {
+    #[serde(default)]
+    results: Vec<GeocodingResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodingResult {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    #[serde(default)]
+    country: Option<String>,
+}
+
+/// A point to fetch the weather for, with the name shown in the title bar
+#[derive(Debug, Clone, PartialEq)]
+struct Location {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+/// Current conditions, kept in metric units and converted to the user's
+/// units only for display
 #[derive(Debug, Clone)]
 pub struct WeatherInfo {
-    pub temperature_f: f64,
+    /// Name of the place the weather is for
+    pub place: String,
+    pub temperature_c: f64,
+    pub wind_kmh: f64,
     pub description: String,
     pub icon: String,
+    /// The next hours, starting with the current one
+    pub hourly: Vec<HourlyForecast>,
+    /// Today and the following days
+    pub daily: Vec<DailyForecast>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HourlyForecast {
+    /// Local time as `YYYY-MM-DDTHH:MM`
+    pub time: String,
+    pub temperature_c: f64,
+    pub precipitation_mm: f64,
+    pub weather_code: i64,
 }
 
-pub fn fetch_weather() -> Option<WeatherInfo> {
-    let geo_url = "http://ip-api.com/json/";
-    let client = reqwest::blocking::Client::builder()
-        .timeout(Duration::from_secs(10))
-        .build()
-        .ok()?;
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyForecast {
+    /// Local date as `YYYY-MM-DD`
+    pub date: String,
+    pub high_c: f64,
+    pub low_c: f64,
+    pub weather_code: i64,
+}
 
-    let geo_resp: GeoResponse = client.get(geo_url).send().ok()?.json().ok()?;
+impl WeatherInfo {
+    pub fn temperature_text(&self, unit: TemperatureUnit) -> String {
+        format!(
+            "{:.0}{}",
+            unit.convert_celsius(self.temperature_c),
+            unit.symbol()
+        )
+    }
 
-    let weather_url = format!(
-        "https://api.open-meteo.com/v1/forecast?latitude={}&longitude={}&current_weather=true&temperature_unit=fahrenheit",
-        geo_resp.lat, geo_resp.lon
-    );
+    pub fn wind_text(&self, unit: WindUnit) -> String {
+        format!("{:.0} {}", unit.convert_kmh(self.wind_kmh), unit.label())
+    }
+}
+
+/// Why a weather fetch failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum WeatherError {
+    /// The service could not be reached or answered with a server error
+    Network(String),
+    /// The configured place was not found, or the IP lookup failed
+    Geolocation(String),
+    /// An answer did not have the expected shape
+    Parse(String),
+    /// The service asked us to slow down (HTTP 429)
+    RateLimited,
+}
 
-    let resp = client.get(&weather_url).send().ok()?;
-    let json: serde_json::Value = resp.json().ok()?;
+impl WeatherError {
+    /// Short text for the title bar
+    pub fn summary(&self) -> &'static str {
+        match self {
+            WeatherError::Network(_) => "offline",
+            WeatherError::Geolocation(_) => "location not found",
+            WeatherError::Parse(_) => "unreadable answer",
+            WeatherError::RateLimited => "rate limited",
+        }
+    }
+}
+
+impl fmt::Display for WeatherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WeatherError::Network(message) => write!(f, "network error: {}", message),
+            WeatherError::Geolocation(message) => write!(f, "location error: {}", message),
+            WeatherError::Parse(message) => write!(f, "unexpected answer: {}", message),
+            WeatherError::RateLimited => write!(f, "too many requests; retrying later"),
+        }
+    }
+}
+
+/// The latest weather and how fetching it is going, shared with the fetch
+/// thread
+#[derive(Debug, Default)]
+pub struct WeatherState {
+    /// Last successful report, kept while later fetches fail
+    pub info: Option<WeatherInfo>,
+    /// When `info` was fetched
+    pub updated: Option<Instant>,
+    /// Why the latest fetch failed, cleared by the next success
+    pub error: Option<WeatherError>,
+    /// Failed fetches in a row, for the retry backoff
+    pub failures: u32,
+    pub in_flight: bool,
+    /// Bumped when the location changes, so a fetch for the old location
+    /// that finishes late is thrown away
+    pub generation: u64,
+}
+
+impl WeatherState {
+    /// Records the outcome of a fetch started at `generation`
+    pub fn finish(&mut self, generation: u64, result: Result<WeatherInfo, WeatherError>) {
+        if generation != self.generation {
+            return;
+        }
+        self.in_flight = false;
+        match result {
+            Ok(info) => {
+                self.info = Some(info);
+                self.updated = Some(Instant::now());
+                self.error = None;
+                self.failures = 0;
+            }
+            Err(error) => {
+                self.error = Some(error);
+                self.failures = self.failures.saturating_add(1);
+            }
+        }
+    }
+
+    /// How long to wait after the last fetch before the next one
+    pub fn next_fetch_after(&self, refresh: Duration) -> Duration {
+        if self.failures == 0 {
+            refresh
+        } else {
+            retry_delay(self.failures)
+        }
+    }
+}
+
+/// Wait before retrying after `failures` failed fetches in a row: 15 s,
+/// 30 s, 1 min, ... up to 30 min
+pub fn retry_delay(failures: u32) -> Duration {
+    let doublings = failures.saturating_sub(1).min(16);
+    (RETRY_BASE * 2u32.pow(doublings)).min(RETRY_MAX)
+}
+
+/// A source of weather reports. The app talks to Open-Meteo; anything else
+/// that can answer the same questions can stand in for it.
+pub trait WeatherProvider: Send + Sync {
+    /// Current conditions and the forecast at the location chosen in
+    /// `settings`
+    fn fetch(&self, settings: &WeatherSettings) -> Result<WeatherInfo, WeatherError>;
+}
+
+/// Open-Meteo forecasts and geocoding, plus ip-api.com for the opt-in IP
+/// lookup. Each field is a base URL without a trailing path.
+pub struct OpenMeteo {
+    client: reqwest::blocking::Client,
+    forecast_url: String,
+    geocoding_url: String,
+    ip_location_url: String,
+}
 
-    let current = json.get("current_weather")?;
-    let temp = current.get("temperature")?.as_f64()?;
-    let weather_code = current.get("weathercode")?.as_i64().unwrap_or(0);
+impl OpenMeteo {
+    pub fn new(settings: &WeatherSettings) -> Self {
+        let base = |url: &str| url.trim_end_matches('/').to_string();
+        Self {
+            client: reqwest::blocking::Client::builder()
+                .timeout(TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            forecast_url: base(&settings.forecast_url),
+            geocoding_url: base(&settings.geocoding_url),
+            ip_location_url: base(&settings.ip_location_url),
+        }
+    }
 
-    let (description, icon) = match weather_code {
+    /// Body of a successful GET
+    fn get(&self, url: &str, query: &[(&str, String)]) -> Result<String, WeatherError> {
+        let response = self
+            .client
+            .get(url)
+            .query(query)
+            .send()
+            .map_err(|e| WeatherError::Network(format!("could not reach {}: {}", url, e)))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(WeatherError::RateLimited);
+        }
+        if !status.is_success() {
+            return Err(WeatherError::Network(format!(
+                "{} answered {}",
+                url, status
+            )));
+        }
+        response.text().map_err(|e| {
+            WeatherError::Network(format!("could not read the answer from {}: {}", url, e))
+        })
+    }
+
+    fn resolve_location(&self, settings: &WeatherSettings) -> Result<Location, WeatherError> {
+        match settings.location {
+            LocationSource::Coordinates => Ok(Location {
+                name: format!("{:.2}, {:.2}", settings.latitude, settings.longitude),
+                latitude: settings.latitude,
+                longitude: settings.longitude,
+            }),
+            LocationSource::Place => {
+                let place = settings.place.trim();
+                let query = [("name", place.to_string()), ("count", "1".to_string())];
+                let body = self.get(&format!("{}/v1/search", self.geocoding_url), &query)?;
+                parse_geocoding(&body, place)
+            }
+            LocationSource::Ip => {
+                let body = self.get(&format!("{}/json/", self.ip_location_url), &[])?;
+                parse_ip_location(&body)
+            }
+        }
+    }
+}
+
+impl WeatherProvider for OpenMeteo {
+    fn fetch(&self, settings: &WeatherSettings) -> Result<WeatherInfo, WeatherError> {
+        let location = self.resolve_location(settings)?;
+        let query = [
+            ("latitude", location.latitude.to_string()),
+            ("longitude", location.longitude.to_string()),
+            ("current_weather", "true".to_string()),
+            (
+                "hourly",
+                "temperature_2m,precipitation,weathercode".to_string(),
+            ),
+            (
+                "daily",
+                "weathercode,temperature_2m_max,temperature_2m_min".to_string(),
+            ),
+            // Times in the place's own time zone, so hours read naturally
+            ("timezone", "auto".to_string()),
+            ("forecast_days", FORECAST_DAYS.to_string()),
+        ];
+        let body = self.get(&format!("{}/v1/forecast", self.forecast_url), &query)?;
+        parse_current(&body, location.name)
+    }
+}
+
+/// Whether the settings name a location to fetch the weather for
+pub fn has_location(settings: &WeatherSettings) -> bool {
+    settings.location != LocationSource::Place || !settings.place.trim().is_empty()
+}
+
+/// Joins the non-empty parts of a place name with commas
+fn place_name(parts: &[&str]) -> String {
+    parts
+        .iter()
+        .filter(|part| !part.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Description and icon for a WMO weather code as used by Open-Meteo
+pub fn describe(weather_code: i64) -> (&'static str, &'static str) {
+    match weather_code {
         0 => ("Clear", "\u{2600}"),
         1..=3 => ("Partly cloudy", "\u{26C5}"),
         45 | 48 => ("Foggy", "\u{1F32B}"),
@@ -48,11 +455,559 @@ pub fn fetch_weather() -> Option<WeatherInfo> {
         95 => ("Thunderstorm", "\u{26C8}"),
         96 | 99 => ("Thunderstorm", "\u{26C8}"),
         _ => ("Unknown", "\u{2601}"),
-    };
+    }
+}
 
-    Some(WeatherInfo {
-        temperature_f: temp,
+/// Reads the `current_weather` block of an Open-Meteo forecast. Open-Meteo
+/// reports °C and km/h unless asked otherwise.
+fn parse_current(body: &str, place: String) -> Result<WeatherInfo, WeatherError> {
+    let response: ForecastResponse =
+        serde_json::from_str(body).map_err(|e| WeatherError::Parse(format!("forecast: {}", e)))?;
+    let current = response.current_weather;
+    let (description, icon) = describe(current.weathercode);
+    Ok(WeatherInfo {
+        place,
+        temperature_c: current.temperature,
+        wind_kmh: current.windspeed,
         description: description.to_string(),
         icon: icon.to_string(),
+        hourly: hourly_from(&response.hourly, &current.time),
+        daily: daily_from(&response.daily),
     })
 }
+
+/// The [`FORECAST_HOURS`] hours from the one containing `now`. Hours
+/// without a temperature are dropped.
+fn hourly_from(columns: &HourlyColumns, now: &str) -> Vec<HourlyForecast> {
+    // Both are `YYYY-MM-DDTHH:MM`, so the hour prefix compares as text
+    let this_hour = now.get(..13).unwrap_or(now);
+    columns
+        .time
+        .iter()
+        .enumerate()
+        .filter(|(_, time)| time.get(..13).unwrap_or(time) >= this_hour)
+        .filter_map(|(i, time)| {
+            Some(HourlyForecast {
+                time: time.clone(),
+                temperature_c: columns.temperature_2m.get(i).copied().flatten()?,
+                precipitation_mm: columns
+                    .precipitation
+                    .get(i)
+                    .copied()
+                    .flatten()
+                    .unwrap_or(0.0),
+                weather_code: columns.weathercode.get(i).copied().flatten().unwrap_or(0),
+            })
+        })
+        .take(FORECAST_HOURS)
+        .collect()
+}
+
+fn daily_from(columns: &DailyColumns) -> Vec<DailyForecast> {
+    columns
+        .time
+        .iter()
+        .enumerate()
+        .filter_map(|(i, date)| {
+            Some(DailyForecast {
+                date: date.clone(),
+                high_c: columns.temperature_2m_max.get(i).copied().flatten()?,
+                low_c: columns.temperature_2m_min.get(i).copied().flatten()?,
+                weather_code: columns.weathercode.get(i).copied().flatten().unwrap_or(0),
+            })
+        })
+        .collect()
+}
+
+fn parse_geocoding(body: &str, place: &str) -> Result<Location, WeatherError> {
+    let response: GeocodingResponse =
+        serde_json::from_str(body).map_err(|e| WeatherError::Parse(format!("geocoding: {}", e)))?;
+    let result = response.results.into_iter().next().ok_or_else(|| {
+        WeatherError::Geolocation(format!("no place called \"{}\" was found", place))
+    })?;
+    Ok(Location {
+        name: place_name(&[&result.name, result.country.as_deref().unwrap_or("")]),
+        latitude: result.latitude,
+        longitude: result.longitude,
+    })
+}
+
+/// ip-api.com answers a failed lookup with a status and message instead
+/// of coordinates
+fn parse_ip_location(body: &str) -> Result<Location, WeatherError> {
+    let ip: IpLocation = serde_json::from_str(body)
+        .map_err(|e| WeatherError::Parse(format!("IP location: {}", e)))?;
+    if ip.status == "fail" {
+        return Err(WeatherError::Geolocation(format!(
+            "IP lookup failed: {}",
+            ip.message
+        )));
+    }
+    let (Some(latitude), Some(longitude)) = (ip.lat, ip.lon) else {
+        return Err(WeatherError::Parse(
+            "IP location: no coordinates".to_string(),
+        ));
+    };
+    Ok(Location {
+        name: place_name(&[&ip.city, &ip.country]),
+        latitude,
+        longitude,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const FORECAST: &str = r#"{
+        "latitude": 52.52,
+        "longitude": 13.419998,
+        "generationtime_ms": 0.05,
+        "utc_offset_seconds": 0,
+        "timezone": "GMT",
+        "elevation": 38.0,
+        "current_weather_units": {
+            "time": "iso8601",
+            "temperature": "°C",
+            "windspeed": "km/h",
+            "weathercode": "wmo code"
+        },
+        "current_weather": {
+            "time": "2024-05-01T12:00",
+            "interval": 900,
+            "temperature": 21.4,
+            "windspeed": 12.6,
+            "winddirection": 250,
+            "is_day": 1,
+            "weathercode": 3
+        }
+    }"#;
+
+    const GEOCODING: &str = r#"{
+        "results": [
+            {
+                "id": 2950159,
+                "name": "Berlin",
+                "latitude": 52.52437,
+                "longitude": 13.41053,
+                "country_code": "DE",
+                "country": "Germany",
+                "admin1": "Land Berlin"
+            },
+            {
+                "id": 5083330,
+                "name": "Berlin",
+                "latitude": 44.46867,
+                "longitude": -71.18508,
+                "country": "United States"
+            }
+        ],
+        "generationtime_ms": 0.7
+    }"#;
+
+    const IP_LOCATION: &str = r#"{
+        "status": "success",
+        "country": "Netherlands",
+        "city": "Amsterdam",
+        "lat": 52.3676,
+        "lon": 4.9041,
+        "query": "203.0.113.7"
+    }"#;
+
+    /// Answers each request with the canned status and body of the first
+    /// route whose path prefix matches, or 404. Returns the base URL.
+    fn stub_server(routes: Vec<(&'static str, u16, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    break;
+                };
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap_or(0) > 2 {
+                    header.clear();
+                }
+
+                let path = request_line.split_whitespace().nth(1).unwrap_or("");
+                let (status, body) = routes
+                    .iter()
+                    .find(|(prefix, _, _)| path.starts_with(prefix))
+                    .map_or((404, ""), |&(_, status, body)| (status, body));
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {} Stub\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+            }
+        });
+        url
+    }
+
+    fn settings_for(url: &str, location: LocationSource) -> WeatherSettings {
+        WeatherSettings {
+            location,
+            place: "Berlin".to_string(),
+            latitude: 48.85,
+            longitude: 2.35,
+            forecast_url: url.to_string(),
+            geocoding_url: url.to_string(),
+            ip_location_url: url.to_string(),
+            ..WeatherSettings::default()
+        }
+    }
+
+    #[test]
+    fn describes_every_wmo_code_group() {
+        let cases = [
+            (0, "Clear", "\u{2600}"),
+            (2, "Partly cloudy", "\u{26C5}"),
+            (48, "Foggy", "\u{1F32B}"),
+            (53, "Drizzle", "\u{1F327}"),
+            (65, "Rain", "\u{1F327}"),
+            (71, "Snow", "\u{2744}"),
+            (77, "Snow grains", "\u{2744}"),
+            (81, "Showers", "\u{1F327}"),
+            (86, "Snow showers", "\u{1F328}"),
+            (95, "Thunderstorm", "\u{26C8}"),
+            (99, "Thunderstorm", "\u{26C8}"),
+        ];
+        for (code, description, icon) in cases {
+            assert_eq!(describe(code), (description, icon), "code {}", code);
+        }
+    }
+
+    #[test]
+    fn unknown_codes_fall_back_to_a_cloud() {
+        for code in [-1, 4, 50, 100] {
+            assert_eq!(describe(code), ("Unknown", "\u{2601}"), "code {}", code);
+        }
+    }
+
+    #[test]
+    fn parses_current_weather_in_metric_units() {
+        let info = parse_current(FORECAST, "Berlin, Germany".to_string()).unwrap();
+        assert_eq!(info.place, "Berlin, Germany");
+        assert_eq!(info.temperature_c, 21.4);
+        assert_eq!(info.wind_kmh, 12.6);
+        assert_eq!(info.description, "Partly cloudy");
+        assert_eq!(info.icon, "\u{26C5}");
+        assert!(info.hourly.is_empty() && info.daily.is_empty());
+    }
+
+    #[test]
+    fn missing_wind_and_code_default_to_calm_and_clear() {
+        let body = r#"{"current_weather": {"temperature": -3.0}}"#;
+        let info = parse_current(body, String::new()).unwrap();
+        assert_eq!(info.temperature_c, -3.0);
+        assert_eq!(info.wind_kmh, 0.0);
+        assert_eq!(info.description, "Clear");
+    }
+
+    #[test]
+    fn rejects_forecasts_without_current_weather() {
+        assert!(parse_current(r#"{"hourly": {}}"#, String::new()).is_err());
+        assert!(parse_current(r#"{"current_weather": {}}"#, String::new()).is_err());
+        assert!(parse_current("<html>busy</html>", String::new()).is_err());
+        assert!(parse_current("", String::new()).is_err());
+    }
+
+    #[test]
+    fn hourly_forecast_starts_at_the_current_hour() {
+        let body = r#"{
+            "current_weather": {"time": "2024-05-01T13:45", "temperature": 20.0},
+            "hourly": {
+                "time": ["2024-05-01T12:00", "2024-05-01T13:00", "2024-05-01T14:00",
+                         "2024-05-01T15:00"],
+                "temperature_2m": [19.0, 20.5, null, 18.0],
+                "precipitation": [0.0, 0.4, 1.0, null],
+                "weathercode": [1, 61, 61, null]
+            }
+        }"#;
+        let info = parse_current(body, String::new()).unwrap();
+        let expected = [
+            ("2024-05-01T13:00", 20.5, 0.4, 61),
+            ("2024-05-01T15:00", 18.0, 0.0, 0),
+        ];
+        assert_eq!(info.hourly.len(), expected.len());
+        for (hour, (time, temperature, precipitation, code)) in info.hourly.iter().zip(expected) {
+            assert_eq!(hour.time, time);
+            assert_eq!(hour.temperature_c, temperature);
+            assert_eq!(hour.precipitation_mm, precipitation);
+            assert_eq!(hour.weather_code, code);
+        }
+    }
+
+    #[test]
+    fn hourly_forecast_is_capped() {
+        let times: Vec<String> = (0..48)
+            .map(|h| format!("\"2024-05-{:02}T{:02}:00\"", 1 + h / 24, h % 24))
+            .collect();
+        let body = format!(
+            r#"{{"current_weather": {{"time": "2024-05-01T00:00", "temperature": 1.0}},
+                "hourly": {{"time": [{}], "temperature_2m": [{}]}}}}"#,
+            times.join(","),
+            vec!["1.5"; 48].join(",")
+        );
+        let info = parse_current(&body, String::new()).unwrap();
+        assert_eq!(info.hourly.len(), FORECAST_HOURS);
+        assert_eq!(info.hourly[0].time, "2024-05-01T00:00");
+    }
+
+    #[test]
+    fn parses_daily_highs_and_lows() {
+        let body = r#"{
+            "current_weather": {"temperature": 20.0},
+            "daily": {
+                "time": ["2024-05-01", "2024-05-02", "2024-05-03"],
+                "weathercode": [3, 95, 0],
+                "temperature_2m_max": [22.1, 25.0, null],
+                "temperature_2m_min": [11.3, 14.2, 9.0]
+            }
+        }"#;
+        let info = parse_current(body, String::new()).unwrap();
+        assert_eq!(
+            info.daily,
+            vec![
+                DailyForecast {
+                    date: "2024-05-01".to_string(),
+                    high_c: 22.1,
+                    low_c: 11.3,
+                    weather_code: 3,
+                },
+                DailyForecast {
+                    date: "2024-05-02".to_string(),
+                    high_c: 25.0,
+                    low_c: 14.2,
+                    weather_code: 95,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn geocoding_takes_the_first_result() {
+        let location = parse_geocoding(GEOCODING, "Berlin").unwrap();
+        assert_eq!(location.name, "Berlin, Germany");
+        assert_eq!(location.latitude, 52.52437);
+        assert_eq!(location.longitude, 13.41053);
+    }
+
+    #[test]
+    fn geocoding_without_results_names_the_place() {
+        let error = parse_geocoding(r#"{"generationtime_ms": 0.3}"#, "Atlantis").unwrap_err();
+        assert!(
+            matches!(&error, WeatherError::Geolocation(m) if m.contains("Atlantis")),
+            "{:?}",
+            error
+        );
+        assert!(parse_geocoding(r#"{"results": []}"#, "Atlantis").is_err());
+    }
+
+    #[test]
+    fn parses_ip_location() {
+        let location = parse_ip_location(IP_LOCATION).unwrap();
+        assert_eq!(location.name, "Amsterdam, Netherlands");
+        assert_eq!((location.latitude, location.longitude), (52.3676, 4.9041));
+    }
+
+    #[test]
+    fn failed_ip_lookup_is_an_error() {
+        let body = r#"{"status": "fail", "message": "private range"}"#;
+        assert_eq!(
+            parse_ip_location(body).unwrap_err(),
+            WeatherError::Geolocation("IP lookup failed: private range".to_string())
+        );
+    }
+
+    #[test]
+    fn place_name_skips_missing_parts() {
+        assert_eq!(place_name(&["Oslo", "Norway"]), "Oslo, Norway");
+        assert_eq!(place_name(&["Oslo", ""]), "Oslo");
+        assert_eq!(place_name(&["", ""]), "");
+    }
+
+    #[test]
+    fn formats_in_the_chosen_units() {
+        let info = parse_current(FORECAST, String::new()).unwrap();
+        assert_eq!(info.temperature_text(TemperatureUnit::Celsius), "21°C");
+        assert_eq!(info.temperature_text(TemperatureUnit::Fahrenheit), "71°F");
+        assert_eq!(info.wind_text(WindUnit::Kmh), "13 km/h");
+        assert_eq!(info.wind_text(WindUnit::Mph), "8 mph");
+        assert_eq!(info.wind_text(WindUnit::Ms), "4 m/s");
+    }
+
+    #[test]
+    fn place_lookup_needs_a_name() {
+        let mut settings = WeatherSettings::default();
+        assert!(!has_location(&settings));
+        settings.place = "  ".to_string();
+        assert!(!has_location(&settings));
+        settings.place = "Lima".to_string();
+        assert!(has_location(&settings));
+        settings.place.clear();
+        settings.location = LocationSource::Coordinates;
+        assert!(has_location(&settings));
+    }
+
+    #[test]
+    fn provider_resolves_a_place_then_fetches_its_weather() {
+        let url = stub_server(vec![
+            ("/v1/search?name=Berlin", 200, GEOCODING),
+            (
+                "/v1/forecast?latitude=52.52437&longitude=13.41053",
+                200,
+                FORECAST,
+            ),
+        ]);
+        let settings = settings_for(&url, LocationSource::Place);
+        let info = OpenMeteo::new(&settings).fetch(&settings).unwrap();
+        assert_eq!(info.place, "Berlin, Germany");
+        assert_eq!(info.temperature_c, 21.4);
+    }
+
+    #[test]
+    fn provider_uses_fixed_coordinates_without_geocoding() {
+        let url = stub_server(vec![(
+            "/v1/forecast?latitude=48.85&longitude=2.35",
+            200,
+            FORECAST,
+        )]);
+        let settings = settings_for(&url, LocationSource::Coordinates);
+        let info = OpenMeteo::new(&settings).fetch(&settings).unwrap();
+        assert_eq!(info.place, "48.85, 2.35");
+    }
+
+    #[test]
+    fn provider_locates_by_ip_only_when_asked() {
+        let url = stub_server(vec![
+            ("/json/", 200, IP_LOCATION),
+            (
+                "/v1/forecast?latitude=52.3676&longitude=4.9041",
+                200,
+                FORECAST,
+            ),
+        ]);
+        let settings = settings_for(&url, LocationSource::Ip);
+        let info = OpenMeteo::new(&settings).fetch(&settings).unwrap();
+        assert_eq!(info.place, "Amsterdam, Netherlands");
+    }
+
+    #[test]
+    fn provider_reports_failed_ip_lookups() {
+        let url = stub_server(vec![(
+            "/json/",
+            200,
+            r#"{"status": "fail", "message": "private range", "query": "10.0.0.1"}"#,
+        )]);
+        let settings = settings_for(&url, LocationSource::Ip);
+        let error = OpenMeteo::new(&settings).fetch(&settings).unwrap_err();
+        assert!(
+            matches!(&error, WeatherError::Geolocation(m) if m.contains("private range")),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn provider_reports_http_errors() {
+        let url = stub_server(vec![("/v1/forecast", 503, "Service Unavailable")]);
+        let settings = settings_for(&url, LocationSource::Coordinates);
+        let error = OpenMeteo::new(&settings).fetch(&settings).unwrap_err();
+        assert!(
+            matches!(&error, WeatherError::Network(m) if m.contains("503")),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn provider_reports_rate_limits() {
+        let url = stub_server(vec![
+            ("/v1/search", 200, GEOCODING),
+            (
+                "/v1/forecast",
+                429,
+                r#"{"error": true, "reason": "Too many requests"}"#,
+            ),
+        ]);
+        let settings = settings_for(&url, LocationSource::Place);
+        let error = OpenMeteo::new(&settings).fetch(&settings).unwrap_err();
+        assert_eq!(error, WeatherError::RateLimited);
+    }
+
+    #[test]
+    fn provider_reports_unknown_places() {
+        let url = stub_server(vec![("/v1/search", 200, r#"{"results": []}"#)]);
+        let settings = settings_for(&url, LocationSource::Place);
+        let error = OpenMeteo::new(&settings).fetch(&settings).unwrap_err();
+        assert!(
+            matches!(&error, WeatherError::Geolocation(m) if m.contains("Berlin")),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn provider_reports_malformed_answers() {
+        let url = stub_server(vec![("/v1/forecast", 200, "{\"current_weather\": ")]);
+        let settings = settings_for(&url, LocationSource::Coordinates);
+        let error = OpenMeteo::new(&settings).fetch(&settings).unwrap_err();
+        assert!(matches!(error, WeatherError::Parse(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn provider_reports_unreachable_servers() {
+        // Bind and drop to find a port nothing is listening on
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let url = format!("http://127.0.0.1:{}/", port);
+        let settings = settings_for(&url, LocationSource::Coordinates);
+        let error = OpenMeteo::new(&settings).fetch(&settings).unwrap_err();
+        assert!(matches!(error, WeatherError::Network(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn retries_back_off_up_to_a_limit() {
+        assert_eq!(retry_delay(1), Duration::from_secs(15));
+        assert_eq!(retry_delay(2), Duration::from_secs(30));
+        assert_eq!(retry_delay(4), Duration::from_secs(120));
+        assert_eq!(retry_delay(100), RETRY_MAX);
+    }
+
+    #[test]
+    fn failures_keep_the_last_report() {
+        let mut state = WeatherState::default();
+        let info = parse_current(FORECAST, "Berlin".to_string()).unwrap();
+        state.finish(0, Ok(info));
+        state.finish(0, Err(WeatherError::RateLimited));
+        state.finish(0, Err(WeatherError::RateLimited));
+        assert!(state.info.is_some());
+        assert_eq!(state.failures, 2);
+        assert_eq!(state.error, Some(WeatherError::RateLimited));
+        let refresh = Duration::from_secs(600);
+        assert_eq!(state.next_fetch_after(refresh), retry_delay(2));
+
+        // A fetch for a location that has since changed is ignored
+        state.generation += 1;
+        state.finish(0, Err(WeatherError::Parse(String::new())));
+        assert_eq!(state.failures, 2);
+
+        state.finish(1, parse_current(FORECAST, "Berlin".to_string()));
+        assert_eq!(state.failures, 0);
+        assert_eq!(state.error, None);
+        assert_eq!(state.next_fetch_after(refresh), refresh);
+    }
+}
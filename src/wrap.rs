@@ -0,0 +1,338 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+use crate::language::Language;
+
+/// How the editor breaks long lines on screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WrapMode {
+    /// Lines run on and the editor scrolls horizontally
+    None,
+    /// Lines wrap at the edge of the window
+    Window,
+    /// Lines wrap at the configured column
+    Column,
+}
+
+impl WrapMode {
+    pub const ALL: &'static [WrapMode] = &[WrapMode::None, WrapMode::Window, WrapMode::Column];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            WrapMode::None => "No Wrap",
+            WrapMode::Window => "Wrap at Window Edge",
+            WrapMode::Column => "Wrap at Column",
+        }
+    }
+}
+
+/// Byte range of the whole lines touched by `range`, without the final
+/// newline
+pub fn line_range(text: &str, range: Range<usize>) -> Range<usize> {
+    let start = text[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    // A selection ending right after a newline does not include the next line
+    let end_from = if range.end > range.start && text[..range.end].ends_with('\n') {
+        range.end - 1
+    } else {
+        range.end
+    };
+    let end = text[end_from..]
+        .find('\n')
+        .map_or(text.len(), |i| end_from + i);
+    start..end.max(start)
+}
+
+/// Byte range of the paragraph around `offset`, or `None` when its line
+/// is never reflowed (blank, a Markdown heading or code)
+pub fn paragraph_range(text: &str, offset: usize, language: Language) -> Option<Range<usize>> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let caret_line = text[..offset].matches('\n').count();
+    let (group, wrap) = paragraphs(&lines, language)
+        .into_iter()
+        .find(|(group, _)| group.contains(&caret_line))?;
+    if !wrap {
+        return None;
+    }
+    let start: usize = lines[..group.start].iter().map(|l| l.len() + 1).sum();
+    let len: usize = lines[group].iter().map(|l| l.len() + 1).sum();
+    Some(start..start + len - 1)
+}
+
+/// Comment and quote markers that may start a reflowed line
+fn comment_markers(language: Language) -> &'static [&'static str] {
+    match language {
+        // Prose often starts a line with `#`, `--` or `;`; only quotes repeat
+        Language::PlainText => &[">"],
+        Language::Rust => &["///", "//!", "//"],
+        Language::Toml | Language::Yaml | Language::Shell => &["#"],
+        Language::Json => &[],
+        Language::Markdown => &[">"],
+    }
+}
+
+/// Length of the comment or quote marker at the start of `text`
+fn marker_len(text: &str, language: Language) -> usize {
+    comment_markers(language)
+        .iter()
+        .find(|m| text.starts_with(*m))
+        .map_or(0, |m| m.len())
+}
+
+/// Leading whitespace plus a comment or quote marker, repeated on every
+/// line of a reflowed paragraph
+fn line_prefix(line: &str, language: Language) -> &str {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+    let marker = marker_len(rest, language);
+    let spaces = rest[marker..].len() - rest[marker..].trim_start().len();
+    if marker == 0 {
+        &line[..indent]
+    } else {
+        &line[..indent + marker + spaces]
+    }
+}
+
+/// Prefix the lines after `first` need to belong to its paragraph: the
+/// same prefix, plus room for a list marker
+fn continuation_prefix(first: &str, language: Language) -> String {
+    let prefix = line_prefix(first, language);
+    let marker = list_marker_len(&first[prefix.len()..]);
+    format!("{}{}", prefix, " ".repeat(marker))
+}
+
+/// Up to three spaces of indentation, as Markdown allows before a block
+fn markdown_block(line: &str) -> &str {
+    let indent = line.bytes().take_while(|&b| b == b' ').count();
+    if indent > 3 {
+        ""
+    } else {
+        &line[indent..]
+    }
+}
+
+/// A `# Heading` line, up to `######`
+fn is_atx_heading(line: &str) -> bool {
+    let rest = markdown_block(line);
+    let hashes = rest.bytes().take_while(|&b| b == b'#').count();
+    (1..=6).contains(&hashes) && matches!(rest[hashes..].chars().next(), None | Some(' ' | '\t'))
+}
+
+/// The ```` ``` ```` or `~~~` line opening or closing a code block
+fn is_fence(line: &str) -> bool {
+    let rest = markdown_block(line);
+    rest.starts_with("```") || rest.starts_with("~~~")
+}
+
+/// Lines that are kept as they are: Markdown headings, and anything but
+/// comments in source code
+fn is_fixed(line: &str, language: Language) -> bool {
+    match language {
+        Language::PlainText => false,
+        Language::Markdown => is_atx_heading(line),
+        _ => marker_len(line.trim_start(), language) == 0,
+    }
+}
+
+/// The `-- ` line that starts an email or Usenet signature. Markdown reads
+/// it as a heading underline instead.
+fn is_signature_separator(line: &str, language: Language) -> bool {
+    language == Language::PlainText && line.trim_end() == "--"
+}
+
+/// Length of a list marker like `- `, `* ` or `12. ` at the start of `text`
+fn list_marker_len(text: &str) -> usize {
+    let digits = text.bytes().take_while(u8::is_ascii_digit).count();
+    let marker = match text.as_bytes().get(digits) {
+        Some(b'.' | b')') if digits > 0 => digits + 1,
+        Some(b'-' | b'*' | b'+') if digits == 0 => 1,
+        _ => return 0,
+    };
+    if text[marker..].starts_with(' ') {
+        marker + text[marker..].len() - text[marker..].trim_start().len()
+    } else {
+        0
+    }
+}
+
+/// Splits `lines` into paragraphs, as ranges of line indices, each with
+/// whether it is reflowed. A paragraph ends at a blank line, a new list
+/// item or a change of prefix; lines that are never reflowed stand alone.
+/// A signature, from its `-- ` line on, is kept as it is.
+fn paragraphs(lines: &[&str], language: Language) -> Vec<(Range<usize>, bool)> {
+    let mut out = Vec::new();
+    let mut open: Option<usize> = None;
+    let mut in_fence = false;
+    let mut in_signature = false;
+
+    for (i, line) in lines.iter().enumerate() {
+        let prefix = line_prefix(line, language);
+        let rest = &line[prefix.len()..];
+        let fence = language == Language::Markdown && is_fence(line);
+        in_signature = in_signature || !in_fence && is_signature_separator(line, language);
+        let fixed = fence || in_fence || in_signature || is_fixed(line, language);
+        if fence {
+            in_fence = !in_fence;
+        }
+        let blank = rest.trim().is_empty();
+
+        let joins = open.is_some_and(|first| {
+            !fixed
+                && !blank
+                && list_marker_len(rest) == 0
+                && prefix == continuation_prefix(lines[first], language)
+        });
+        if joins {
+            continue;
+        }
+        if let Some(first) = open.take() {
+            out.push((first..i, true));
+        }
+        if fixed || blank {
+            out.push((i..i + 1, false));
+        } else {
+            open = Some(i);
+        }
+    }
+    if let Some(first) = open {
+        out.push((first..lines.len(), true));
+    }
+    out
+}
+
+/// Reflows each paragraph of `text` so lines are at most `column` chars
+/// wide where the words allow it. Indentation, comment and quote markers
+/// are kept, and list items wrap under their first word. Headings and
+/// code are left alone.
+pub fn hard_wrap(text: &str, column: usize, language: Language) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut out: Vec<String> = Vec::new();
+    for (group, wrap) in paragraphs(&lines, language) {
+        if wrap {
+            out.extend(reflow(&lines[group], column, language));
+        } else {
+            out.extend(lines[group].iter().map(|line| line.to_string()));
+        }
+    }
+    out.join("\n")
+}
+
+fn reflow(lines: &[&str], column: usize, language: Language) -> Vec<String> {
+    let first = lines[0];
+    let prefix = line_prefix(first, language);
+    let marker = list_marker_len(&first[prefix.len()..]);
+    let first_prefix = &first[..prefix.len() + marker];
+    let next_prefix = format!("{}{}", prefix, " ".repeat(marker));
+
+    let words = lines.iter().enumerate().flat_map(|(i, line)| {
+        let skip = if i == 0 {
+            first_prefix.len()
+        } else {
+            line_prefix(line, language).len()
+        };
+        line[skip..].split_whitespace()
+    });
+
+    let mut out = Vec::new();
+    let mut current = first_prefix.to_string();
+    let mut width = current.chars().count();
+    let mut empty = true;
+    for word in words {
+        let word_width = word.chars().count();
+        if !empty && width + 1 + word_width > column {
+            out.push(std::mem::replace(&mut current, next_prefix.clone()));
+            width = next_prefix.chars().count();
+            empty = true;
+        }
+        if !empty {
+            current.push(' ');
+            width += 1;
+        }
+        current.push_str(word);
+        width += word_width;
+        empty = false;
+    }
+    out.push(current);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doc_comment_stops_at_code() {
+        let text = "/// Returns the thing\nfn foo() -> u32 {\n    1\n}";
+        let range = paragraph_range(text, 5, Language::Rust).unwrap();
+        assert_eq!(&text[range], "/// Returns the thing");
+        assert_eq!(hard_wrap(text, 80, Language::Rust), text);
+        assert_eq!(paragraph_range(text, 25, Language::Rust), None);
+    }
+
+    #[test]
+    fn markdown_headings_are_not_reflowed() {
+        let text = "# Title\nSome body\ntext here.";
+        assert_eq!(paragraph_range(text, 2, Language::Markdown), None);
+        let range = paragraph_range(text, 10, Language::Markdown).unwrap();
+        assert_eq!(&text[range], "Some body\ntext here.");
+        assert_eq!(
+            hard_wrap(text, 80, Language::Markdown),
+            "# Title\nSome body text here."
+        );
+    }
+
+    #[test]
+    fn paragraph_ends_where_the_prefix_changes() {
+        let text = "// a comment\n> a quote\nplain";
+        assert_eq!(
+            hard_wrap(text, 80, Language::PlainText),
+            "// a comment\n> a quote\nplain"
+        );
+    }
+
+    #[test]
+    fn plain_text_keeps_only_quote_markers() {
+        let text = "Dashes -- like these\nstay in the text.";
+        assert_eq!(
+            hard_wrap(text, 80, Language::PlainText),
+            "Dashes -- like these stay in the text."
+        );
+        assert_eq!(
+            hard_wrap("> quoted text that\n> wraps", 14, Language::PlainText),
+            "> quoted text\n> that wraps"
+        );
+    }
+
+    #[test]
+    fn signatures_are_kept() {
+        let text = "Thanks for the\nreview.\n-- \nSam\nExample Corp";
+        assert_eq!(
+            hard_wrap(text, 80, Language::PlainText),
+            "Thanks for the review.\n-- \nSam\nExample Corp"
+        );
+        assert_eq!(paragraph_range(text, 30, Language::PlainText), None);
+        // Editors that strip trailing spaces leave a bare `--`
+        assert_eq!(
+            hard_wrap("Bye\nnow\n--\nSam\nB.", 80, Language::PlainText),
+            "Bye now\n--\nSam\nB."
+        );
+    }
+
+    #[test]
+    fn list_items_rewrap_under_their_first_word() {
+        let text = "- one two\n  three four";
+        let wrapped = hard_wrap(text, 12, Language::Markdown);
+        assert_eq!(wrapped, "- one two\n  three four");
+        assert_eq!(
+            hard_wrap(&wrapped, 80, Language::Markdown),
+            "- one two three four"
+        );
+    }
+
+    #[test]
+    fn markdown_code_blocks_are_kept() {
+        let text = "```\nlet a = 1;\nlet b = 2;\n```";
+        assert_eq!(hard_wrap(text, 80, Language::Markdown), text);
+        assert_eq!(paragraph_range(text, 6, Language::Markdown), None);
+    }
+}